@@ -0,0 +1,33 @@
+use std::hint::black_box;
+use std::io;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+const WAR_AND_PEACE: &[u8] = include_bytes!("../data/ok/06-war-and-peace.txt.gz");
+const APP: &[u8] = include_bytes!("../data/ok/05-app.gz");
+
+fn bench_decompress(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decompress");
+    for (name, data) in [("war_and_peace", WAR_AND_PEACE), ("app", APP)] {
+        let mut output = Vec::new();
+        ripgzip::decompress(data, &mut output).unwrap();
+        group.throughput(Throughput::Bytes(output.len() as u64));
+
+        group.bench_function(name, |b| {
+            b.iter(|| ripgzip::decompress(black_box(data), io::sink()).unwrap())
+        });
+        group.bench_function(format!("{name}/streaming"), |b| {
+            b.iter(|| {
+                io::copy(
+                    &mut ripgzip::GzDecoder::new(black_box(data)),
+                    &mut io::sink(),
+                )
+                .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_decompress);
+criterion_main!(benches);
@@ -1,7 +1,6 @@
 #![forbid(unsafe_code)]
 
-use byteorder::ReadBytesExt;
-use std::io::{self, BufRead};
+use std::io::{self, BufRead, Read};
 use std::ops::{Add, AddAssign};
 ////////////////////////////////////////////////////////////////////////////////
 
@@ -31,10 +30,12 @@ impl BitSequence {
         self.len
     }
 
-    pub fn concat(self, other: Self) -> Self {
-        let len = self.len + other.len;
-        let sbits = other.bits | (self.bits << other.len);
-        Self { len, bits: sbits }
+    /// The same bits in the reverse order.
+    pub fn reversed(self) -> Self {
+        if self.len == 0 {
+            return self;
+        }
+        Self::new(self.bits.reverse_bits() >> (16 - self.len), self.len)
     }
 }
 
@@ -42,91 +43,128 @@ impl BitSequence {
 
 pub struct BitReader<T> {
     stream: T,
-    current_byte: u8,
-    current_index: i8,
-    // TODO: your code goes here.
+    /// Bits read from the stream but not consumed yet, the next one is the least significant.
+    buffer: u64,
+    len: u8,
+    /// Whole bytes of `buffer` lent by `fill_buf` at a byte boundary.
+    bytes: [u8; 8],
 }
 
 impl<T: BufRead> BitReader<T> {
     pub fn new(stream: T) -> Self {
         Self {
             stream,
-            current_byte: 0,
-            current_index: -1,
+            buffer: 0,
+            len: 0,
+            bytes: [0; 8],
         }
     }
 
     pub fn read_u32(&mut self) -> io::Result<u32> {
-        let mut out = 0u32;
-        let mut cur_pos = 0;
-        let len = 32;
-        loop {
-            if self.current_index == -1 || self.current_index == 8 {
-                self.current_byte = self.stream.read_u8()?;
-                self.current_index = 0;
-            }
-            (out, cur_pos) = self.read_from_buf_u32(len, out, cur_pos);
-            if cur_pos == len {
-                return Ok(out);
-            }
-        }
+        let low = self.read_bits(16)?.bits() as u32;
+        let high = self.read_bits(16)?.bits() as u32;
+        Ok(high << 16 | low)
     }
     pub fn read_str_to_null(&mut self) -> io::Result<String> {
         let mut buf = Vec::new();
-        self.stream.read_until(u8::try_from('\0').unwrap(), &mut buf)?;
+        self.borrow_reader_from_boundary().read_until(u8::try_from('\0').unwrap(), &mut buf)?;
         match String::from_utf8(buf) {
             Ok(o) => { Ok(o) }
             Err(_) => { Err(io::Error::from(io::ErrorKind::UnexpectedEof)) }
         }
     }
 
-    pub fn read_bits(&mut self, mut len: u8) -> io::Result<BitSequence> {
-        if len == 0 {
-            return Ok(BitSequence::new(0, 0));
-        }
-        let mut out = 0;
-        let mut cur_pos = 0;
-        loop {
-            if self.current_index == -1 || self.current_index == 8 {
-                self.current_byte = self.stream.read_u8()?;
-                self.current_index = 0;
-            }
-            (out, cur_pos) = self.read_from_buf_u16(len, out, cur_pos);
-            if cur_pos == len {
-                return Ok(BitSequence::new(out, len));
-            }
+    pub fn read_bits(&mut self, len: u8) -> io::Result<BitSequence> {
+        let seq = self.peek(len)?;
+        if seq.len() < len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
         }
+        self.consume(len);
+        Ok(seq)
     }
 
-    fn read_from_buf_u32(&mut self, len: u8, mut out: u32, mut cur_pos: u8) -> (u32, u8) {
-        while self.current_index < 8 && cur_pos < len {
-            let va = (self.current_byte as u32 >> self.current_index) & 1;
-            out += (va << cur_pos) as u32;
-            cur_pos += 1;
-            self.current_index += 1;
+    /// The next `len` bits, at most 16, without consuming them. Fewer bits are returned
+    /// only at the end of the stream.
+    pub fn peek(&mut self, len: u8) -> io::Result<BitSequence> {
+        if self.len < len {
+            self.refill()?;
         }
-        (out, cur_pos)
-    }
-    fn read_from_buf_u16(&mut self, len: u8, mut out: u16, mut cur_pos: u8) -> (u16, u8) {
-        while self.current_index < 8 && cur_pos < len {
-            let va = (self.current_byte as u16 >> self.current_index) & 1;
-            out += (va << cur_pos) as u16;
-            cur_pos += 1;
-            self.current_index += 1;
+        let len = len.min(self.len);
+        Ok(BitSequence::new((self.buffer & ((1 << len) - 1)) as u16, len))
+    }
+
+    /// Skip `len` bits that are already peeked.
+    pub fn consume(&mut self, len: u8) {
+        assert!(len <= self.len, "consumed bits must be peeked first");
+        self.buffer = self.buffer.checked_shr(len as u32).unwrap_or(0);
+        self.len -= len;
+    }
+
+    /// Read whole bytes from the stream until the buffer has more than 56 bits or the
+    /// stream ends.
+    fn refill(&mut self) -> io::Result<()> {
+        while self.len <= 56 {
+            let buf = self.stream.fill_buf()?;
+            if let Some(word) = buf.get(..8) {
+                let count = (64 - self.len as usize) / 8;
+                let mut word = u64::from_le_bytes(word.try_into().unwrap());
+                if count < 8 {
+                    word &= (1 << (8 * count)) - 1;
+                }
+                self.buffer |= word << self.len;
+                self.len += 8 * count as u8;
+                self.stream.consume(count);
+                return Ok(());
+            }
+            let Some(byte) = buf.first() else {
+                break;
+            };
+            self.buffer |= (*byte as u64) << self.len;
+            self.len += 8;
+            self.stream.consume(1);
         }
-        (out, cur_pos)
+        Ok(())
     }
 
+    /// Discard all the unread bits in the current byte and return a reader of the remaining
+    /// bytes, the buffered ones go first.
+    pub fn borrow_reader_from_boundary(&mut self) -> ByteReader<'_, T> {
+        self.consume(self.len % 8);
+        ByteReader(self)
+    }
+}
 
+/// Reads the bytes of `BitReader` from a byte boundary.
+pub struct ByteReader<'a, T>(&'a mut BitReader<T>);
 
-    /// Discard all the unread bits in the current byte and return a mutable reference
-    /// to the underlying reader.
-    pub fn borrow_reader_from_boundary(&mut self) -> &mut T {
-        self.current_index = -1;
-        &mut self.stream
+impl<T: BufRead> Read for ByteReader<'_, T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
     }
 }
 
+impl<T: BufRead> BufRead for ByteReader<'_, T> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let reader = &mut *self.0;
+        if reader.len == 0 {
+            return reader.stream.fill_buf();
+        }
+        reader.bytes = reader.buffer.to_le_bytes();
+        Ok(&reader.bytes[..reader.len as usize / 8])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if self.0.len == 0 {
+            self.0.stream.consume(amt);
+        } else {
+            self.0.consume(8 * amt as u8);
+        }
+    }
+}
 
 ////////////////////////////////////////////////////////////////////////////////
 
@@ -161,4 +199,23 @@ mod tests {
         assert_eq!(reader.read_bits(8)?, BitSequence::new(0b10101111, 8));
         Ok(())
     }
+
+    #[test]
+    fn peek_and_consume() -> io::Result<()> {
+        let data: Vec<u8> = (0..20).collect();
+        let mut reader = BitReader::new(data.as_slice());
+        assert_eq!(reader.peek(12)?, BitSequence::new(0x100, 12));
+        reader.consume(4);
+        assert_eq!(reader.read_bits(16)?, BitSequence::new(0x2010, 16));
+        assert_eq!(reader.read_u32()?, 0x60504030);
+
+        let mut bytes = [0; 5];
+        reader.borrow_reader_from_boundary().read_exact(&mut bytes)?;
+        assert_eq!(bytes, [7, 8, 9, 10, 11]);
+        for byte in 12..20 {
+            assert_eq!(reader.read_bits(8)?, BitSequence::new(byte, 8));
+        }
+        assert_eq!(reader.peek(8)?, BitSequence::new(0, 0));
+        Ok(())
+    }
 }
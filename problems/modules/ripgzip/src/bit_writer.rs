@@ -0,0 +1,104 @@
+#![forbid(unsafe_code)]
+
+use std::io::{self, Write};
+
+use crate::bit_reader::BitSequence;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Writes bits starting from the least significant one, the counterpart of `BitReader`.
+pub struct BitWriter<T> {
+    stream: T,
+    buffer: u64,
+    len: u8,
+}
+
+impl<T: Write> BitWriter<T> {
+    pub fn new(stream: T) -> Self {
+        Self {
+            stream,
+            buffer: 0,
+            len: 0,
+        }
+    }
+
+    pub fn write_bits(&mut self, seq: BitSequence) -> io::Result<()> {
+        if seq.len() == 0 {
+            return Ok(());
+        }
+        let mask = (1u64 << seq.len()) - 1;
+        self.buffer |= (seq.bits() as u64 & mask) << self.len;
+        self.len += seq.len();
+        if self.len >= 32 {
+            self.stream.write_all(&(self.buffer as u32).to_le_bytes())?;
+            self.buffer >>= 32;
+            self.len -= 32;
+        }
+        Ok(())
+    }
+
+    /// Huffman codes are packed starting from the most significant bit.
+    pub fn write_code(&mut self, code: BitSequence) -> io::Result<()> {
+        self.write_bits(code.reversed())
+    }
+
+    /// The underlying writer, bits that are not written yet go after anything written into it.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.stream
+    }
+
+    /// Pad the current byte with zeros and return a mutable reference to the underlying
+    /// writer.
+    pub fn borrow_writer_from_boundary(&mut self) -> io::Result<&mut T> {
+        let bytes = (self.len as usize).div_ceil(8);
+        self.stream.write_all(&self.buffer.to_le_bytes()[..bytes])?;
+        self.buffer = 0;
+        self.len = 0;
+        Ok(&mut self.stream)
+    }
+
+    /// Write the remaining bits padded with zeros and return the underlying writer.
+    pub fn finish(mut self) -> io::Result<T> {
+        self.borrow_writer_from_boundary()?;
+        Ok(self.stream)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bit_reader::BitReader;
+
+    #[test]
+    fn write_bits() -> io::Result<()> {
+        let mut writer = BitWriter::new(Vec::new());
+        writer.write_bits(BitSequence::new(0b1, 1))?;
+        writer.write_bits(BitSequence::new(0b01, 2))?;
+        writer.write_bits(BitSequence::new(0b100, 3))?;
+        writer.write_bits(BitSequence::new(0b1101, 4))?;
+        writer.write_bits(BitSequence::new(0b10110, 5))?;
+        writer.write_bits(BitSequence::new(0b01011111, 8))?;
+        assert_eq!(writer.finish()?, [0b01100011, 0b11011011, 0b00101111]);
+        Ok(())
+    }
+
+    #[test]
+    fn write_code_and_borrow_writer() -> io::Result<()> {
+        let mut writer = BitWriter::new(Vec::new());
+        writer.write_code(BitSequence::new(0b110, 3))?;
+        writer.borrow_writer_from_boundary()?.write_all(&[0xab])?;
+        for i in 0..10 {
+            writer.write_bits(BitSequence::new(i, 13))?;
+        }
+        let data = writer.finish()?;
+        assert_eq!(data[..2], [0b011, 0xab]);
+
+        let mut reader = BitReader::new(&data[2..]);
+        for i in 0..10 {
+            assert_eq!(reader.read_bits(13)?, BitSequence::new(i, 13));
+        }
+        Ok(())
+    }
+}
@@ -1,10 +1,16 @@
 #![forbid(unsafe_code)]
 
-use std::{collections::HashMap, convert::TryFrom, io::BufRead};
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    convert::TryFrom,
+    io::{self, BufRead, Write},
+};
 
 use anyhow::{bail, Context, Result};
 
 use crate::bit_reader::{BitReader, BitSequence};
+use crate::bit_writer::BitWriter;
 use crate::deflate::huffman_coding::LitLenToken::{EndOfBlock, Length, Literal};
 
 pub(super) fn decode_litlen_distance_trees<T: BufRead>(
@@ -33,11 +39,7 @@ pub(super) fn decode_litlen_distance_trees<T: BufRead>(
     let code_len_coding = HuffmanCoding::<TreeCodeToken>::from_lengths(codeLen.as_slice())?;
     let mut lit_len_lens = Vec::with_capacity((hlit + hdist + 258) as usize);
     while lit_len_lens.len() < (hlit + hdist + 258) as usize {
-        let sym = code_len_coding.read_symbol(bit_reader);
-        if sym.is_err() {
-            println!("{}", lit_len_lens.len())
-        }
-        let sym = sym?;
+        let sym = code_len_coding.read_symbol(bit_reader)?;
         match sym {
             TreeCodeToken::Length(v) => {
                 lit_len_lens.push(v);
@@ -58,15 +60,9 @@ pub(super) fn decode_litlen_distance_trees<T: BufRead>(
         }
     }
     let hlit = hlit as usize;
-    let mut dist_vec = Vec::from(&lit_len_lens[hlit + 257..]);
-    if dist_vec.iter().filter(|x| **x >= 1).count() == 1 {
-        while dist_vec.len() < 32 {
-            dist_vec.push(0);
-        }
-        dist_vec[31] = 1;
-    }
+    // A single distance code of one bit is allowed, the tables accept incomplete codes.
     Ok((HuffmanCoding::<LitLenToken>::from_lengths(&lit_len_lens[0..hlit + 257])?,
-        HuffmanCoding::<DistanceToken>::from_lengths(&dist_vec)?))
+        HuffmanCoding::<DistanceToken>::from_lengths(&lit_len_lens[hlit + 257..])?))
 
 
     // See RFC 1951, section 3.2.7.
@@ -198,68 +194,219 @@ impl TryFrom<HuffmanCodeWord> for DistanceToken {
 ////////////////////////////////////////////////////////////////////////////////
 
 const MAX_BITS: usize = 15;
+/// Bits indexing the primary table, longer codes continue in the secondary tables.
+const PRIMARY_BITS: u8 = 9;
 
 pub struct HuffmanCodeWord(pub u16);
 
+#[derive(Clone, Copy, Debug)]
+enum Entry<T> {
+    Invalid,
+    /// The symbol and the length of its whole code.
+    Symbol(T, u8),
+    /// The secondary table at `offset` is indexed by the next `bits` bits after the primary ones.
+    Subtable { offset: usize, bits: u8 },
+}
+
+/// Decodes a symbol with one or two table lookups. The tables are indexed by the bits as they
+/// are read, i.e. by the reversed codes, and every code is repeated for all the possible bits
+/// after it.
 pub struct HuffmanCoding<T> {
-    map: HashMap<BitSequence, T>,
+    primary_bits: u8,
+    /// The primary table followed by the secondary ones.
+    table: Vec<Entry<T>>,
 }
 
 impl<T> HuffmanCoding<T>
 where
     T: Copy + TryFrom<HuffmanCodeWord, Error=anyhow::Error>,
 {
-    pub fn new(map: HashMap<BitSequence, T>) -> Self {
-        Self { map }
-    }
-
     #[allow(unused)]
     pub fn decode_symbol(&self, seq: BitSequence) -> Option<T> {
-        self.map.get(&seq).copied()
+        match self.lookup(seq.reversed().bits()) {
+            Entry::Symbol(symbol, len) if len == seq.len() => Some(symbol),
+            _ => None,
+        }
     }
 
     pub fn read_symbol<U: BufRead>(&self, bit_reader: &mut BitReader<U>) -> Result<T> {
-        let mut r = BitSequence::new(0, 0);
-        loop {
-            let b = bit_reader.read_bits(1)?;
-            r = r.concat(b);
-            if r.len() > MAX_BITS as u8 {
-                bail!("incorrect huffman code")
+        let bits = bit_reader.peek(MAX_BITS as u8)?;
+        match self.lookup(bits.bits()) {
+            Entry::Symbol(symbol, len) if len <= bits.len() => {
+                bit_reader.consume(len);
+                Ok(symbol)
             }
-            if let Some(o) = self.map.get(&r) {
-                return Ok(o.clone());
+            _ if bits.len() < MAX_BITS as u8 => Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+            _ => bail!("incorrect huffman code"),
+        }
+    }
+
+    /// The entry of the code at the start of `bits`, in the order they are read.
+    fn lookup(&self, bits: u16) -> Entry<T> {
+        let bits = bits as usize;
+        match self.table[bits & ((1 << self.primary_bits) - 1)] {
+            Entry::Subtable { offset, bits: sub_bits } => {
+                self.table[offset + (bits >> self.primary_bits & ((1 << sub_bits) - 1))]
             }
+            entry => entry,
         }
     }
 
     pub fn from_lengths(code_lengths: &[u8]) -> Result<Self> {
-        let mut map = HashMap::<BitSequence, T>::new();
-        let mut bl_count = [0; MAX_BITS + 1];
-        for x in code_lengths {
-            bl_count[*x as usize] += 1;
+        let codes = canonical_codes(code_lengths)?;
+        let max_len = code_lengths.iter().copied().max().unwrap_or(0);
+        let primary_bits = max_len.clamp(1, PRIMARY_BITS);
+
+        // Secondary tables are as long as the longest code with their prefix needs.
+        let mut sub_bits = vec![0u8; 1 << primary_bits];
+        for code in &codes {
+            if code.len() > primary_bits {
+                let prefix = code.reversed().bits() as usize & ((1 << primary_bits) - 1);
+                sub_bits[prefix] = sub_bits[prefix].max(code.len() - primary_bits);
+            }
         }
-        let mut code = 0;
-        let mut next_code: [u16; MAX_BITS + 1] = [0; MAX_BITS + 1];
-        bl_count[0] = 0;
-        for bits in 1..=MAX_BITS {
-            code = (code + bl_count[bits - 1]) << 1;
-            next_code[bits] = code;
+        let mut table = vec![Entry::Invalid; 1 << primary_bits];
+        for (prefix, bits) in sub_bits.iter().enumerate() {
+            if *bits > 0 {
+                table[prefix] = Entry::Subtable { offset: table.len(), bits: *bits };
+                table.resize(table.len() + (1 << bits), Entry::Invalid);
+            }
         }
-        for x in code_lengths.iter().enumerate() {
-            if *x.1 == 0 {
+
+        for (symbol, code) in codes.into_iter().enumerate() {
+            if code.len() == 0 {
                 continue;
             }
-            if x.0 > u16::MAX as usize {
-                bail!("max value is 2^16-1")
+            let entry = Entry::Symbol(T::try_from(HuffmanCodeWord(symbol as u16))?, code.len());
+            let reversed = code.reversed().bits() as usize;
+            let (start, step, end) = if code.len() <= primary_bits {
+                (reversed, 1 << code.len(), 1 << primary_bits)
+            } else {
+                let prefix = reversed & ((1 << primary_bits) - 1);
+                let Entry::Subtable { offset, bits } = table[prefix] else {
+                    unreachable!()
+                };
+                let rest = reversed >> primary_bits;
+                (offset + rest, 1 << (code.len() - primary_bits), offset + (1 << bits))
+            };
+            for index in (start..end).step_by(step) {
+                table[index] = entry;
             }
-            map.insert(BitSequence::new(next_code[*x.1 as usize], *x.1), T::try_from(HuffmanCodeWord(x.0 as u16))?);
-            next_code[*x.1 as usize] += 1;
         }
-        // See RFC 1951, section 3.2.2.
-        // TODO: your code goes here.
-        Ok(Self { map })
+        Ok(Self { primary_bits, table })
+    }
+}
+
+/// Codes of the symbols, the encoding side of `HuffmanCoding::from_lengths`.
+pub struct HuffmanEncoding {
+    codes: Vec<BitSequence>,
+}
+
+impl HuffmanEncoding {
+    pub fn from_lengths(code_lengths: &[u8]) -> Result<Self> {
+        Ok(Self { codes: canonical_codes(code_lengths)? })
+    }
+
+    pub fn write_symbol<U: Write>(&self, bit_writer: &mut BitWriter<U>, symbol: u16) -> io::Result<()> {
+        bit_writer.write_code(self.codes[symbol as usize])
+    }
+}
+
+/// Codes assigned to the symbols by their lengths, see RFC 1951, section 3.2.2.
+/// Symbols of zero length get an empty code.
+fn canonical_codes(code_lengths: &[u8]) -> Result<Vec<BitSequence>> {
+    if code_lengths.len() > u16::MAX as usize + 1 {
+        bail!("max value is 2^16-1")
+    }
+    let mut bl_count = [0; MAX_BITS + 1];
+    for x in code_lengths {
+        if *x as usize > MAX_BITS {
+            bail!("max code length is {}", MAX_BITS)
+        }
+        bl_count[*x as usize] += 1;
+    }
+    let kraft: u32 = (1..=MAX_BITS).map(|len| (bl_count[len] as u32) << (MAX_BITS - len)).sum();
+    if kraft > 1 << MAX_BITS {
+        bail!("over-subscribed huffman code lengths")
+    }
+    let mut code = 0;
+    let mut next_code: [u16; MAX_BITS + 1] = [0; MAX_BITS + 1];
+    bl_count[0] = 0;
+    for bits in 1..=MAX_BITS {
+        code = (code + bl_count[bits - 1]) << 1;
+        next_code[bits] = code;
+    }
+    Ok(code_lengths
+        .iter()
+        .map(|len| {
+            if *len == 0 {
+                return BitSequence::new(0, 0);
+            }
+            let code = BitSequence::new(next_code[*len as usize], *len);
+            next_code[*len as usize] += 1;
+            code
+        })
+        .collect())
+}
+
+/// Lengths of a complete prefix code for the symbols with the given frequencies, none
+/// longer than `max_bits`. Unused symbols get zero length, but at least two symbols
+/// get a code, so that the decoders accepting only complete codes are happy.
+pub fn lengths_from_frequencies(frequencies: &[u32], max_bits: u8) -> Vec<u8> {
+    let mut lengths = vec![0u8; frequencies.len()];
+    let symbols: Vec<usize> = (0..frequencies.len()).filter(|i| frequencies[*i] > 0).collect();
+    if symbols.len() < 2 {
+        let first = symbols.first().copied().unwrap_or(0);
+        lengths[first] = 1;
+        lengths[if first == 0 { 1 } else { 0 }] = 1;
+        return lengths;
+    }
+
+    // Leaves are the nodes 0..symbols.len(), the parents are created after the children.
+    let mut parents = vec![0usize; 2 * symbols.len() - 1];
+    let mut heap: BinaryHeap<_> = symbols
+        .iter()
+        .enumerate()
+        .map(|(node, symbol)| Reverse((frequencies[*symbol] as u64, node)))
+        .collect();
+    let mut next_node = symbols.len();
+    while let (Some(Reverse((a, left))), Some(Reverse((b, right)))) = (heap.pop(), heap.pop()) {
+        parents[left] = next_node;
+        parents[right] = next_node;
+        heap.push(Reverse((a + b, next_node)));
+        next_node += 1;
+    }
+    let mut depths = vec![0u8; parents.len()];
+    for node in (0..parents.len() - 1).rev() {
+        depths[node] = depths[parents[node]] + 1;
+    }
+    for (node, symbol) in symbols.iter().enumerate() {
+        lengths[*symbol] = depths[node].min(max_bits);
+    }
+
+    // Clamped lengths overflow the Kraft sum, lengthen the rarest short codes to fix it,
+    // then shorten the longest codes while there's room left, so the code stays complete.
+    let capacity = 1u64 << max_bits;
+    let weight = |len: u8| 1u64 << (max_bits - len);
+    let mut kraft: u64 = symbols.iter().map(|symbol| weight(lengths[*symbol])).sum();
+    while kraft > capacity {
+        let symbol = *symbols
+            .iter()
+            .filter(|symbol| lengths[**symbol] < max_bits)
+            .max_by_key(|symbol| (lengths[**symbol], Reverse(frequencies[**symbol])))
+            .unwrap();
+        lengths[symbol] += 1;
+        kraft -= weight(lengths[symbol]);
+    }
+    while kraft < capacity {
+        let symbol = *symbols
+            .iter()
+            .max_by_key(|symbol| (lengths[**symbol], frequencies[**symbol]))
+            .unwrap();
+        kraft += weight(lengths[symbol]);
+        lengths[symbol] -= 1;
     }
-    // 00
+    lengths
 }
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -404,4 +551,65 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn lengths_from_frequencies_are_limited() -> Result<()> {
+        let frequencies: Vec<u32> = (0..30).map(|i| 1 << i.min(25)).collect();
+        let lengths = lengths_from_frequencies(&frequencies, 7);
+        assert!(lengths.iter().all(|len| (1..=7).contains(len)));
+        let kraft: u32 = lengths.iter().map(|len| 1 << (7 - len)).sum();
+        assert_eq!(kraft, 1 << 7);
+        assert!(lengths[29] <= lengths[0]);
+
+        assert_eq!(lengths_from_frequencies(&[0, 0, 5], 15), [1, 0, 1]);
+        assert_eq!(lengths_from_frequencies(&[3, 1, 1, 1], 15), [1, 3, 3, 2]);
+        Ok(())
+    }
+
+    #[test]
+    fn encoding_is_decoded() -> Result<()> {
+        let lengths = [3, 4, 5, 5, 0, 0, 6, 6, 4, 0, 6, 0, 7];
+        let encoding = HuffmanEncoding::from_lengths(&lengths)?;
+        let symbols = [0, 1, 2, 3, 6, 7, 8, 10, 12];
+        let mut writer = BitWriter::new(Vec::new());
+        for symbol in symbols {
+            encoding.write_symbol(&mut writer, symbol)?;
+        }
+        let data = writer.finish()?;
+
+        let code = HuffmanCoding::<Value>::from_lengths(&lengths)?;
+        let mut reader = BitReader::new(data.as_slice());
+        for symbol in symbols {
+            assert_eq!(code.read_symbol(&mut reader)?, Value(symbol));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn long_codes_use_secondary_tables() -> Result<()> {
+        let mut lengths: Vec<u8> = (1..=15).collect();
+        lengths.extend([0, 15]);
+        let encoding = HuffmanEncoding::from_lengths(&lengths)?;
+        let symbols: Vec<u16> = (0..lengths.len() as u16).filter(|s| lengths[*s as usize] > 0).collect();
+        let mut writer = BitWriter::new(Vec::new());
+        for symbol in symbols.iter().rev().chain(&symbols) {
+            encoding.write_symbol(&mut writer, *symbol)?;
+        }
+        let data = writer.finish()?;
+
+        let code = HuffmanCoding::<Value>::from_lengths(&lengths)?;
+        let mut reader = BitReader::new(data.as_slice());
+        for symbol in symbols.iter().rev().chain(&symbols) {
+            assert_eq!(code.read_symbol(&mut reader)?, Value(*symbol));
+        }
+
+        assert!(HuffmanCoding::<Value>::from_lengths(&[1, 2, 2, 2]).is_err());
+
+        // A single code of one bit is incomplete, the other bit is invalid.
+        let code = HuffmanCoding::<Value>::from_lengths(&[0, 0, 1])?;
+        let mut reader = BitReader::new([0b10, 0xff].as_slice());
+        assert_eq!(code.read_symbol(&mut reader)?, Value(2));
+        assert!(code.read_symbol(&mut reader).is_err());
+        Ok(())
+    }
 }
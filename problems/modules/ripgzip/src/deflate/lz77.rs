@@ -0,0 +1,315 @@
+#![forbid(unsafe_code)]
+
+use crate::tracking_writer::HISTORY_SIZE;
+
+////////////////////////////////////////////////////////////////////////////////
+
+pub const MIN_MATCH: usize = 3;
+pub const MAX_MATCH: usize = 258;
+/// Bytes ahead of the current position needed to look for the longest match and
+/// to hash the strings after it.
+pub const MIN_LOOKAHEAD: usize = MAX_MATCH + MIN_MATCH + 1;
+
+const WINDOW_MASK: usize = HISTORY_SIZE - 1;
+const HASH_BITS: usize = 15;
+const HASH_MASK: usize = (1 << HASH_BITS) - 1;
+const NIL: u32 = u32::MAX;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Token {
+    Literal(u8),
+    Match { length: u16, distance: u16 },
+}
+
+/// How hard the matcher looks for matches, the same knobs as in zlib.
+#[derive(Clone, Copy, Debug)]
+pub struct Params {
+    /// The chain is searched four times shorter if the previous match is at least this long.
+    good_length: usize,
+    /// Lazy matching is not tried for matches at least this long. Without lazy matching,
+    /// strings of longer matches are not inserted into the hash chains.
+    max_lazy: usize,
+    /// The search stops once a match of this length is found.
+    nice_length: usize,
+    max_chain: usize,
+    lazy: bool,
+}
+
+const LEVELS: [Params; 9] = [
+    Params::new(4, 4, 8, 4, false),
+    Params::new(4, 5, 16, 8, false),
+    Params::new(4, 6, 32, 32, false),
+    Params::new(4, 4, 16, 16, true),
+    Params::new(8, 16, 32, 32, true),
+    Params::new(8, 16, 128, 128, true),
+    Params::new(8, 32, 128, 256, true),
+    Params::new(32, 128, 258, 1024, true),
+    Params::new(32, 258, 258, 4096, true),
+];
+
+impl Params {
+    const fn new(
+        good_length: usize,
+        max_lazy: usize,
+        nice_length: usize,
+        max_chain: usize,
+        lazy: bool,
+    ) -> Self {
+        Self {
+            good_length,
+            max_lazy,
+            nice_length,
+            max_chain,
+            lazy,
+        }
+    }
+
+    /// Parameters of a compression level from 1 to 9.
+    pub fn level(level: u32) -> Option<Self> {
+        LEVELS.get((level as usize).checked_sub(1)?).copied()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Finds matches with hash chains over a window of two histories: once it's full,
+/// the older half is dropped.
+pub struct Matcher {
+    window: Vec<u8>,
+    pos: usize,
+    inserted: usize,
+    head: Vec<u32>,
+    prev: Vec<u32>,
+    /// A match found at the next position while trying lazy matching.
+    next_match: Option<(usize, usize, usize)>,
+    params: Params,
+}
+
+impl Matcher {
+    pub fn new(params: Params) -> Self {
+        Self {
+            window: Vec::with_capacity(2 * HISTORY_SIZE),
+            pos: 0,
+            inserted: 0,
+            head: vec![NIL; 1 << HASH_BITS],
+            prev: vec![NIL; HISTORY_SIZE],
+            next_match: None,
+            params,
+        }
+    }
+
+    /// Bytes of the window, the ones before `pos` are already turned into tokens.
+    pub fn window(&self) -> &[u8] {
+        &self.window
+    }
+
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.window.len() == 2 * HISTORY_SIZE
+    }
+
+    /// Append as much of `data` as fits into the window, returning the number of bytes taken.
+    pub fn push(&mut self, data: &[u8]) -> usize {
+        let len = data.len().min(2 * HISTORY_SIZE - self.window.len());
+        self.window.extend_from_slice(&data[..len]);
+        len
+    }
+
+    /// Drop the older half of the window, all positions move back by `HISTORY_SIZE`.
+    pub fn slide(&mut self) {
+        self.window.drain(..HISTORY_SIZE);
+        self.pos -= HISTORY_SIZE;
+        self.inserted -= HISTORY_SIZE;
+        for entry in self.head.iter_mut().chain(self.prev.iter_mut()) {
+            *entry = match *entry {
+                NIL => NIL,
+                pos if (pos as usize) < HISTORY_SIZE => NIL,
+                pos => pos - HISTORY_SIZE as u32,
+            };
+        }
+        if let Some((pos, _, _)) = &mut self.next_match {
+            *pos -= HISTORY_SIZE;
+        }
+    }
+
+    /// The next token, `None` if more input is needed. If `finishing`, there's no more input
+    /// and the window is tokenized up to the end.
+    pub fn next_token(&mut self, finishing: bool) -> Option<Token> {
+        let available = self.window.len() - self.pos;
+        if available == 0 || (!finishing && available < MIN_LOOKAHEAD) {
+            return None;
+        }
+        let pos = self.pos;
+        let found = match self.next_match.take() {
+            Some((at, length, distance)) if at == pos => Some((length, distance)),
+            _ => self.find(pos, MIN_MATCH - 1, self.params.max_chain),
+        };
+        self.insert_up_to(pos + 1);
+
+        let Some((length, distance)) = found else {
+            self.pos += 1;
+            return Some(Token::Literal(self.window[pos]));
+        };
+        if self.params.lazy && length < self.params.max_lazy {
+            let chain = if length >= self.params.good_length {
+                self.params.max_chain / 4
+            } else {
+                self.params.max_chain
+            };
+            if let Some((next_length, next_distance)) = self.find(pos + 1, length, chain) {
+                self.next_match = Some((pos + 1, next_length, next_distance));
+                self.pos += 1;
+                return Some(Token::Literal(self.window[pos]));
+            }
+        }
+        if self.params.lazy || length <= self.params.max_lazy {
+            self.insert_up_to(pos + length);
+        } else {
+            self.inserted = pos + length;
+        }
+        self.pos += length;
+        Some(Token::Match {
+            length: length as u16,
+            distance: distance as u16,
+        })
+    }
+
+    fn hash(&self, pos: usize) -> usize {
+        let bytes = &self.window[pos..pos + MIN_MATCH];
+        ((bytes[0] as usize) << 10 ^ (bytes[1] as usize) << 5 ^ bytes[2] as usize) & HASH_MASK
+    }
+
+    fn insert_up_to(&mut self, end: usize) {
+        while self.inserted < end {
+            let pos = self.inserted;
+            if pos + MIN_MATCH <= self.window.len() {
+                let hash = self.hash(pos);
+                self.prev[pos & WINDOW_MASK] = self.head[hash];
+                self.head[hash] = pos as u32;
+            }
+            self.inserted += 1;
+        }
+    }
+
+    /// The longest match at `pos` that is longer than `min_length`, as a length and a distance.
+    fn find(&self, pos: usize, min_length: usize, max_chain: usize) -> Option<(usize, usize)> {
+        let max_length = MAX_MATCH.min(self.window.len() - pos);
+        if max_length < MIN_MATCH || min_length >= max_length {
+            return None;
+        }
+        let mut best = None;
+        let mut best_length = min_length;
+        let mut candidate = self.head[self.hash(pos)];
+        for _ in 0..max_chain {
+            if candidate == NIL {
+                break;
+            }
+            let start = candidate as usize;
+            if start >= pos || pos - start > HISTORY_SIZE {
+                break;
+            }
+            if self.window[start + best_length] == self.window[pos + best_length] {
+                let length = self.window[start..start + max_length]
+                    .iter()
+                    .zip(&self.window[pos..pos + max_length])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if length > best_length {
+                    best_length = length;
+                    best = Some((length, pos - start));
+                    if length >= self.params.nice_length || length == max_length {
+                        break;
+                    }
+                }
+            }
+            let next = self.prev[start & WINDOW_MASK];
+            if next != NIL && next as usize >= start {
+                break;
+            }
+            candidate = next;
+        }
+        best
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokenize(data: &[u8], level: u32) -> Vec<Token> {
+        let mut matcher = Matcher::new(Params::level(level).unwrap());
+        let mut tokens = vec![];
+        let mut data = data;
+        loop {
+            if matcher.is_full() {
+                matcher.slide();
+            }
+            let taken = matcher.push(data);
+            data = &data[taken..];
+            while let Some(token) = matcher.next_token(data.is_empty()) {
+                tokens.push(token);
+            }
+            if data.is_empty() {
+                return tokens;
+            }
+        }
+    }
+
+    fn detokenize(tokens: &[Token]) -> Vec<u8> {
+        let mut out = vec![];
+        for token in tokens {
+            match *token {
+                Token::Literal(byte) => out.push(byte),
+                Token::Match { length, distance } => {
+                    for _ in 0..length {
+                        out.push(out[out.len() - distance as usize]);
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn matches() {
+        let tokens = tokenize(b"abcabcabcabcx", 6);
+        assert_eq!(
+            tokens,
+            [
+                Token::Literal(b'a'),
+                Token::Literal(b'b'),
+                Token::Literal(b'c'),
+                Token::Match {
+                    length: 9,
+                    distance: 3
+                },
+                Token::Literal(b'x'),
+            ]
+        );
+    }
+
+    #[test]
+    fn window_slides() {
+        let mut state = 12345u32;
+        let data: Vec<u8> = (0..200_000)
+            .map(|i| {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                if i % 1000 < 500 {
+                    b'a' + (state >> 16) as u8 % 4
+                } else {
+                    (state >> 16) as u8
+                }
+            })
+            .collect();
+        for level in [1, 6, 9] {
+            let tokens = tokenize(&data, level);
+            assert!(tokens.len() < data.len());
+            assert_eq!(detokenize(&tokens), data);
+        }
+    }
+}
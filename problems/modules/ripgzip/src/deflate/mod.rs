@@ -1,10 +1,13 @@
 #![forbid(unsafe_code)]
 
 mod huffman_coding;
+mod lz77;
 
 pub mod reader;
+pub mod writer;
 
 pub use reader::DeflateReader;
+pub use writer::DeflateWriter;
 use crate::gzip;
 use anyhow::Result;
 use crate::bit_reader::BitReader;
@@ -34,11 +37,9 @@ enum CompressionType {
 
 
 impl<T: BufRead, I: Write> gzip::Decoder<T, I> for DeflateReader {
-    fn decode(&mut self, reader: &mut BitReader<T>,
-              tracking_writer: &mut TrackingWriter<I>) -> Result<()> {
-
-        while !self.read_block(reader, tracking_writer)? {}
-        Ok(())
+    fn decode_some(&mut self, reader: &mut BitReader<T>,
+                   tracking_writer: &mut TrackingWriter<I>, limit: usize) -> Result<bool> {
+        self.read_some(reader, tracking_writer, limit)
     }
 }
 
@@ -4,7 +4,7 @@ use crate::tracking_writer::TrackingWriter;
 use crate::deflate::{BlockHeader, CompressionType};
 use anyhow::bail;
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
-use std::io::{BufRead, Write};
+use std::io::{self, BufRead, Write};
 
 
 
@@ -12,7 +12,14 @@ use std::io::{BufRead, Write};
 pub struct DeflateReader {
     fixed_tree: Vec<u8>,
     distance_tree: Vec<u8>,
-    // TODO: your code goes here.
+    state: State,
+}
+
+/// Where the decoding stopped, so that it can be resumed.
+enum State {
+    BlockHeader,
+    Stored { remaining: usize, is_final: bool },
+    Compressed { trees: (HuffmanCoding<LitLenToken>, HuffmanCoding<DistanceToken>), is_final: bool },
 }
 
 impl DeflateReader {
@@ -27,11 +34,12 @@ impl DeflateReader {
         for i in 256..=279 {
             len_vec.push(7);
         }
-        for i in 280..=287 {
+        // Symbols 286 and 287 never occur and come last, so the other codes are the same.
+        for i in 280..=285 {
             len_vec.push(8);
         }
-        let dist_vec = vec![5; 32];
-        Self { fixed_tree: len_vec, distance_tree: dist_vec }
+        let dist_vec = vec![5; 30];
+        Self { fixed_tree: len_vec, distance_tree: dist_vec, state: State::BlockHeader }
     }
     fn next_block<T: BufRead>(&mut self, mut bit_reader: &mut BitReader<T>) -> anyhow::Result<BlockHeader> {
         let is_final = match bit_reader.read_bits(1)?.bits() {
@@ -49,30 +57,67 @@ impl DeflateReader {
         Ok(BlockHeader { is_final, compression_type: c_type })
     }
 
+    /// Decode until at least `limit` bytes are written or the final block ends. Returns
+    /// whether the stream has ended, then the reader is ready for the next one.
+    pub fn read_some<T: BufRead, I: Write>(&mut self, bit_reader: &mut BitReader<T>,
+                                           writer: &mut TrackingWriter<I>, limit: usize) -> anyhow::Result<bool> {
+        let start = writer.byte_count();
+        loop {
+            let left = limit.saturating_sub(writer.byte_count() - start);
+            let (block_ended, is_final) = match &mut self.state {
+                State::BlockHeader => {
+                    self.state = self.read_block_header(bit_reader)?;
+                    continue;
+                }
+                State::Stored { remaining, is_final } => {
+                    let mut inner_input = bit_reader.borrow_reader_from_boundary();
+                    while *remaining > 0 && writer.byte_count() - start < limit {
+                        let buf = inner_input.fill_buf()?;
+                        if buf.is_empty() {
+                            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+                        }
+                        let len = buf.len().min(*remaining);
+                        writer.write_all(&buf[..len])?;
+                        inner_input.consume(len);
+                        *remaining -= len;
+                    }
+                    (*remaining == 0, *is_final)
+                }
+                State::Compressed { trees, is_final } => {
+                    (Self::read_compressed(bit_reader, writer, trees, left)?, *is_final)
+                }
+            };
+            if block_ended {
+                self.state = State::BlockHeader;
+                if is_final {
+                    return Ok(true);
+                }
+            }
+            if writer.byte_count() - start >= limit {
+                return Ok(false);
+            }
+        }
+    }
 
-    pub fn read_block<T: BufRead, I: Write>(&mut self, mut bit_reader: &mut BitReader<T>,
-                                            mut writer: &mut TrackingWriter<I>, ) -> anyhow::Result<bool> {
+    fn read_block_header<T: BufRead>(&mut self, mut bit_reader: &mut BitReader<T>) -> anyhow::Result<State> {
         let header = self.next_block(bit_reader)?;
+        let is_final = header.is_final;
         match header.compression_type {
             CompressionType::Uncompressed => {
-                let inner_input = bit_reader.borrow_reader_from_boundary();
+                let mut inner_input = bit_reader.borrow_reader_from_boundary();
                 let (len, nlen) = (inner_input.read_u16::<LittleEndian>()?, inner_input.read_u16::<LittleEndian>()?);
                 if len ^ 0xFFFF != nlen {
                     bail!("nlen check failed")
                 }
-                for _ in 0..len {
-                    writer.write_u8(bit_reader.read_bits(8)?.bits() as u8)?;
-                }
-                Ok(header.is_final)
+                Ok(State::Stored { remaining: len as usize, is_final })
             }
             CompressionType::FixedTree => {
-                self.read_compressed(bit_reader, writer, (HuffmanCoding::from_lengths(&self.fixed_tree)?, HuffmanCoding::from_lengths(&self.distance_tree)?))?;
-                Ok(header.is_final)
+                let trees = (HuffmanCoding::from_lengths(&self.fixed_tree)?, HuffmanCoding::from_lengths(&self.distance_tree)?);
+                Ok(State::Compressed { trees, is_final })
             }
             CompressionType::DynamicTree => {
-                let decoded = decode_litlen_distance_trees(&mut bit_reader)?;
-                self.read_compressed(bit_reader, writer, decoded)?;
-                Ok(header.is_final)
+                let trees = decode_litlen_distance_trees(&mut bit_reader)?;
+                Ok(State::Compressed { trees, is_final })
             }
             CompressionType::Reserved => {
                 bail!("unsupported block type")
@@ -80,23 +125,19 @@ impl DeflateReader {
         }
     }
 
-    fn read_compressed<T: BufRead, I: Write>(&mut self, mut bit_reader: &mut BitReader<T>,
-                                             mut writer: &mut TrackingWriter<I>, (lit_len_tree, distance_tree): (HuffmanCoding<LitLenToken>, HuffmanCoding<DistanceToken>)) -> anyhow::Result<()> {
-        let mut count = 0;
-        loop {
-            count += 1;
+    /// Returns whether the end of the block is reached, otherwise at least `limit` bytes are written.
+    fn read_compressed<T: BufRead, I: Write>(mut bit_reader: &mut BitReader<T>, writer: &mut TrackingWriter<I>,
+                                             (lit_len_tree, distance_tree): &(HuffmanCoding<LitLenToken>, HuffmanCoding<DistanceToken>),
+                                             limit: usize) -> anyhow::Result<bool> {
+        let start = writer.byte_count();
+        while writer.byte_count() - start < limit {
             let sym = lit_len_tree.read_symbol(&mut bit_reader)?;
             match sym {
                 LitLenToken::Literal(v) => {
-                    let q = v as char;
-                    // println!("{}", q);
-                    let res = writer.write_u8(v);
-                    if res.is_err() {
-                        return Err(anyhow::Error::from(res.unwrap_err()));
-                    }
+                    writer.write_u8(v)?;
                 }
                 LitLenToken::EndOfBlock => {
-                    return Ok(());
+                    return Ok(true);
                 }
                 LitLenToken::Length { mut base, extra_bits } => {
                     let extra = bit_reader.read_bits(extra_bits)?.bits();
@@ -110,6 +151,7 @@ impl DeflateReader {
                 }
             }
         }
+        Ok(false)
     }
 
     fn get_fixed_tree(&mut self) {}
@@ -0,0 +1,456 @@
+#![forbid(unsafe_code)]
+
+use std::{
+    convert::TryFrom,
+    io::{self, Write},
+};
+
+use anyhow::{bail, Result};
+
+use crate::bit_reader::BitSequence;
+use crate::bit_writer::BitWriter;
+use crate::deflate::huffman_coding::{
+    lengths_from_frequencies, DistanceToken, HuffmanCodeWord, HuffmanEncoding, LitLenToken,
+};
+use crate::deflate::lz77::{Matcher, Params, Token, MAX_MATCH};
+use crate::deflate::CompressionType;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A block is emitted once it has this many tokens.
+const MAX_BLOCK_TOKENS: usize = 1 << 14;
+const MAX_STORED_LEN: usize = u16::MAX as usize;
+const END_OF_BLOCK: usize = 256;
+const LIT_LEN_SYMBOLS: usize = 286;
+const DISTANCE_SYMBOLS: usize = 30;
+const MAX_CODE_LENGTH: u8 = 15;
+const MAX_CODE_LENGTH_CODE_LENGTH: u8 = 7;
+/// The order of code length code lengths, see RFC 1951, section 3.2.7.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Compresses the data written into it as DEFLATE stream, see RFC 1951.
+pub struct DeflateWriter<T: Write> {
+    writer: BitWriter<T>,
+    matcher: Matcher,
+    tokens: Vec<Token>,
+    /// Position in the window of the first byte of the current block.
+    block_start: usize,
+    /// Symbol, extra bits and base for each match length.
+    length_codes: Vec<(u16, u8, u16)>,
+}
+
+/// A code length symbol with the value of its extra bits.
+type CodeLengthToken = (u8, u8);
+
+impl<T: Write> DeflateWriter<T> {
+    /// `level` is from 1 (fastest) to 9 (best compression).
+    pub fn new(writer: T, level: u32) -> Result<Self> {
+        let Some(params) = Params::level(level) else {
+            bail!("compression level must be from 1 to 9, got {}", level)
+        };
+        let mut length_codes = vec![(0, 0, 0); MAX_MATCH + 1];
+        for symbol in 257..=285 {
+            if let LitLenToken::Length { base, extra_bits } =
+                LitLenToken::try_from(HuffmanCodeWord(symbol))?
+            {
+                let last = (base as usize + (1 << extra_bits) - 1).min(MAX_MATCH);
+                for entry in &mut length_codes[base as usize..=last] {
+                    *entry = (symbol, extra_bits, base);
+                }
+            }
+        }
+        Ok(Self {
+            writer: BitWriter::new(writer),
+            matcher: Matcher::new(params),
+            tokens: Vec::with_capacity(MAX_BLOCK_TOKENS),
+            block_start: 0,
+            length_codes,
+        })
+    }
+
+    /// The underlying writer, e.g. to write a container header before the stream.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.writer.get_mut()
+    }
+
+    /// Compress the rest of the data, write the final block and return the underlying writer.
+    pub fn finish(mut self) -> Result<T> {
+        self.tokenize(true)?;
+        self.write_block(true)?;
+        Ok(self.writer.finish()?)
+    }
+
+    fn write_data(&mut self, mut data: &[u8]) -> Result<()> {
+        while !data.is_empty() {
+            if self.matcher.is_full() {
+                // Stored blocks need the bytes of the block, which are going to be dropped.
+                self.write_block(false)?;
+                self.matcher.slide();
+                self.block_start = self.matcher.pos();
+            }
+            let taken = self.matcher.push(data);
+            data = &data[taken..];
+            self.tokenize(false)?;
+        }
+        Ok(())
+    }
+
+    fn tokenize(&mut self, finishing: bool) -> Result<()> {
+        while let Some(token) = self.matcher.next_token(finishing) {
+            self.tokens.push(token);
+            if self.tokens.len() == MAX_BLOCK_TOKENS {
+                self.write_block(false)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the tokens as a block of the type that takes the least bits.
+    fn write_block(&mut self, is_final: bool) -> Result<()> {
+        if self.tokens.is_empty() && !is_final {
+            return Ok(());
+        }
+        let mut lit_len_frequencies = [0u32; LIT_LEN_SYMBOLS];
+        let mut distance_frequencies = [0u32; DISTANCE_SYMBOLS];
+        let mut extra_bits = 0u64;
+        lit_len_frequencies[END_OF_BLOCK] = 1;
+        for token in &self.tokens {
+            match *token {
+                Token::Literal(byte) => lit_len_frequencies[byte as usize] += 1,
+                Token::Match { length, distance } => {
+                    let (symbol, length_extra_bits, _) = self.length_codes[length as usize];
+                    lit_len_frequencies[symbol as usize] += 1;
+                    let symbol = distance_symbol(distance);
+                    distance_frequencies[symbol as usize] += 1;
+                    extra_bits += length_extra_bits as u64
+                        + DistanceToken::try_from(HuffmanCodeWord(symbol))?.extra_bits as u64;
+                }
+            }
+        }
+        let data_cost = |lit_len_lengths: &[u8], distance_lengths: &[u8]| {
+            let cost = |frequencies: &[u32], lengths: &[u8]| -> u64 {
+                frequencies
+                    .iter()
+                    .zip(lengths)
+                    .map(|(frequency, len)| *frequency as u64 * *len as u64)
+                    .sum()
+            };
+            cost(&lit_len_frequencies, lit_len_lengths)
+                + cost(&distance_frequencies, distance_lengths)
+                + extra_bits
+        };
+
+        let lit_len_lengths = lengths_from_frequencies(&lit_len_frequencies, MAX_CODE_LENGTH);
+        let distance_lengths = lengths_from_frequencies(&distance_frequencies, MAX_CODE_LENGTH);
+        let header = DynamicHeader::new(&lit_len_lengths, &distance_lengths);
+        let dynamic_cost = 3 + header.cost() + data_cost(&lit_len_lengths, &distance_lengths);
+        let (fixed_lit_len_lengths, fixed_distance_lengths) = fixed_lengths();
+        let fixed_cost = 3 + data_cost(&fixed_lit_len_lengths, &fixed_distance_lengths);
+        let len = self.matcher.pos() - self.block_start;
+        let stored_cost = (len.div_ceil(MAX_STORED_LEN).max(1) * (3 + 7 + 32) + 8 * len) as u64;
+
+        if stored_cost <= fixed_cost.min(dynamic_cost) {
+            self.write_stored(is_final)?;
+        } else if fixed_cost <= dynamic_cost {
+            self.write_header(is_final, CompressionType::FixedTree)?;
+            self.write_tokens(&fixed_lit_len_lengths, &fixed_distance_lengths)?;
+        } else {
+            self.write_header(is_final, CompressionType::DynamicTree)?;
+            header.write(&mut self.writer)?;
+            self.write_tokens(&lit_len_lengths, &distance_lengths)?;
+        }
+        self.tokens.clear();
+        self.block_start = self.matcher.pos();
+        Ok(())
+    }
+
+    fn write_header(&mut self, is_final: bool, compression_type: CompressionType) -> Result<()> {
+        self.writer
+            .write_bits(BitSequence::new(is_final as u16, 1))?;
+        self.writer
+            .write_bits(BitSequence::new(compression_type as u16, 2))?;
+        Ok(())
+    }
+
+    fn write_stored(&mut self, is_final: bool) -> Result<()> {
+        let data = &self.matcher.window()[self.block_start..self.matcher.pos()];
+        let mut chunks: Vec<_> = data.chunks(MAX_STORED_LEN).collect();
+        if chunks.is_empty() {
+            chunks.push(&[]);
+        }
+        let count = chunks.len();
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            self.writer
+                .write_bits(BitSequence::new((is_final && i + 1 == count) as u16, 1))?;
+            self.writer
+                .write_bits(BitSequence::new(CompressionType::Uncompressed as u16, 2))?;
+            let writer = self.writer.borrow_writer_from_boundary()?;
+            let len = chunk.len() as u16;
+            writer.write_all(&len.to_le_bytes())?;
+            writer.write_all(&(!len).to_le_bytes())?;
+            writer.write_all(chunk)?;
+        }
+        Ok(())
+    }
+
+    fn write_tokens(&mut self, lit_len_lengths: &[u8], distance_lengths: &[u8]) -> Result<()> {
+        let lit_len = HuffmanEncoding::from_lengths(lit_len_lengths)?;
+        let distance = HuffmanEncoding::from_lengths(distance_lengths)?;
+        for token in &self.tokens {
+            match *token {
+                Token::Literal(byte) => lit_len.write_symbol(&mut self.writer, byte as u16)?,
+                Token::Match {
+                    length,
+                    distance: dist,
+                } => {
+                    let (symbol, extra_bits, base) = self.length_codes[length as usize];
+                    lit_len.write_symbol(&mut self.writer, symbol)?;
+                    self.writer
+                        .write_bits(BitSequence::new(length - base, extra_bits))?;
+                    let symbol = distance_symbol(dist);
+                    let token = DistanceToken::try_from(HuffmanCodeWord(symbol))?;
+                    distance.write_symbol(&mut self.writer, symbol)?;
+                    self.writer
+                        .write_bits(BitSequence::new(dist - token.base, token.extra_bits))?;
+                }
+            }
+        }
+        lit_len.write_symbol(&mut self.writer, END_OF_BLOCK as u16)?;
+        Ok(())
+    }
+}
+
+impl<T: Write> Write for DeflateWriter<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_data(buf).map_err(io::Error::other)?;
+        Ok(buf.len())
+    }
+
+    /// Blocks are only written once they are full or on `finish`.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Code lengths of a dynamic block encoded with the code length code.
+struct DynamicHeader {
+    lit_len_count: usize,
+    distance_count: usize,
+    code_length_lengths: Vec<u8>,
+    code_length_count: usize,
+    tokens: Vec<CodeLengthToken>,
+}
+
+impl DynamicHeader {
+    fn new(lit_len_lengths: &[u8], distance_lengths: &[u8]) -> Self {
+        let used = |lengths: &[u8], min: usize| {
+            (lengths.iter().rposition(|len| *len != 0).unwrap_or(0) + 1).max(min)
+        };
+        let lit_len_count = used(lit_len_lengths, 257);
+        let distance_count = used(distance_lengths, 1);
+        let lengths: Vec<u8> = lit_len_lengths[..lit_len_count]
+            .iter()
+            .chain(&distance_lengths[..distance_count])
+            .copied()
+            .collect();
+        let tokens = run_length_encode(&lengths);
+        let mut frequencies = [0u32; 19];
+        for (symbol, _) in &tokens {
+            frequencies[*symbol as usize] += 1;
+        }
+        let code_length_lengths =
+            lengths_from_frequencies(&frequencies, MAX_CODE_LENGTH_CODE_LENGTH);
+        let code_length_count = CODE_LENGTH_ORDER
+            .iter()
+            .rposition(|symbol| code_length_lengths[*symbol] != 0)
+            .map_or(0, |i| i + 1)
+            .max(4);
+        Self {
+            lit_len_count,
+            distance_count,
+            code_length_lengths,
+            code_length_count,
+            tokens,
+        }
+    }
+
+    fn cost(&self) -> u64 {
+        let tokens: u64 = self
+            .tokens
+            .iter()
+            .map(|(symbol, _)| {
+                self.code_length_lengths[*symbol as usize] as u64 + extra_bits(*symbol) as u64
+            })
+            .sum();
+        5 + 5 + 4 + 3 * self.code_length_count as u64 + tokens
+    }
+
+    fn write<T: Write>(&self, writer: &mut BitWriter<T>) -> Result<()> {
+        writer.write_bits(BitSequence::new((self.lit_len_count - 257) as u16, 5))?;
+        writer.write_bits(BitSequence::new((self.distance_count - 1) as u16, 5))?;
+        writer.write_bits(BitSequence::new((self.code_length_count - 4) as u16, 4))?;
+        for symbol in &CODE_LENGTH_ORDER[..self.code_length_count] {
+            writer.write_bits(BitSequence::new(
+                self.code_length_lengths[*symbol] as u16,
+                3,
+            ))?;
+        }
+        let encoding = HuffmanEncoding::from_lengths(&self.code_length_lengths)?;
+        for (symbol, extra) in &self.tokens {
+            encoding.write_symbol(writer, *symbol as u16)?;
+            writer.write_bits(BitSequence::new(*extra as u16, extra_bits(*symbol)))?;
+        }
+        Ok(())
+    }
+}
+
+/// Encode code lengths with repeat codes 16, 17 and 18, see RFC 1951, section 3.2.7.
+fn run_length_encode(lengths: &[u8]) -> Vec<CodeLengthToken> {
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < lengths.len() {
+        let len = lengths[i];
+        let mut run = lengths[i..].iter().take_while(|x| **x == len).count();
+        i += run;
+        if len == 0 {
+            while run >= 11 {
+                let count = run.min(138);
+                tokens.push((18, (count - 11) as u8));
+                run -= count;
+            }
+            if run >= 3 {
+                tokens.push((17, (run - 3) as u8));
+                run = 0;
+            }
+        } else {
+            tokens.push((len, 0));
+            run -= 1;
+            while run >= 3 {
+                let count = run.min(6);
+                tokens.push((16, (count - 3) as u8));
+                run -= count;
+            }
+        }
+        tokens.extend(std::iter::repeat_n((len, 0), run));
+    }
+    tokens
+}
+
+fn extra_bits(code_length_symbol: u8) -> u8 {
+    match code_length_symbol {
+        16 => 2,
+        17 => 3,
+        18 => 7,
+        _ => 0,
+    }
+}
+
+fn distance_symbol(distance: u16) -> u16 {
+    if distance <= 4 {
+        return distance - 1;
+    }
+    let distance = distance - 1;
+    let bits = 15 - distance.leading_zeros() as u16;
+    2 * bits + ((distance >> (bits - 1)) & 1)
+}
+
+/// Code lengths of the fixed Huffman codes, see RFC 1951, section 3.2.6.
+fn fixed_lengths() -> (Vec<u8>, Vec<u8>) {
+    let mut lit_len = vec![8; 288];
+    lit_len[144..256].fill(9);
+    lit_len[256..280].fill(7);
+    (lit_len, vec![5; DISTANCE_SYMBOLS])
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bit_reader::BitReader;
+    use crate::deflate::DeflateReader;
+    use crate::gzip::Decoder;
+    use crate::tracking_writer::TrackingWriter;
+
+    fn roundtrip(data: &[u8], level: u32) -> Result<Vec<u8>> {
+        let mut writer = DeflateWriter::new(Vec::new(), level)?;
+        writer.write_all(data)?;
+        let compressed = writer.finish()?;
+
+        let mut output = Vec::new();
+        let mut tracking_writer = TrackingWriter::new(&mut output);
+        DeflateReader::new().decode(
+            &mut BitReader::new(compressed.as_slice()),
+            &mut tracking_writer,
+        )?;
+        tracking_writer.flush()?;
+        drop(tracking_writer);
+        assert_eq!(output, data);
+        Ok(compressed)
+    }
+
+    #[test]
+    fn distance_symbols() -> Result<()> {
+        for symbol in 0..30 {
+            let token = DistanceToken::try_from(HuffmanCodeWord(symbol))?;
+            let last = token.base as u32 + (1 << token.extra_bits) - 1;
+            assert_eq!(distance_symbol(token.base), symbol);
+            assert_eq!(distance_symbol(last as u16), symbol);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn run_length_encoding() {
+        let lengths = [0, 0, 0, 0, 5, 5, 5, 5, 5, 5, 5, 5, 2, 0, 0];
+        assert_eq!(
+            run_length_encode(&lengths),
+            [(17, 1), (5, 0), (16, 3), (5, 0), (2, 0), (0, 0), (0, 0)]
+        );
+        assert_eq!(run_length_encode(&[0; 141]), [(18, 127), (17, 0)]);
+    }
+
+    #[test]
+    fn roundtrips() -> Result<()> {
+        assert_eq!(roundtrip(b"", 6)?, [0x03, 0x00]);
+        roundtrip(b"a", 1)?;
+
+        let text: Vec<u8> = b"It's a truth universally acknowledged. "
+            .iter()
+            .copied()
+            .cycle()
+            .take(100_000)
+            .collect();
+        for level in 1..=9 {
+            assert!(roundtrip(&text, level)?.len() < 1000);
+        }
+
+        let mut state = 1u32;
+        let noise: Vec<u8> = (0..70_000)
+            .map(|_| {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect();
+        let compressed = roundtrip(&noise, 9)?;
+        assert!(compressed.len() < noise.len() + 50);
+
+        let mixed: Vec<u8> = noise[..30_000]
+            .iter()
+            .chain(&text[..50_000])
+            .copied()
+            .collect();
+        roundtrip(&mixed, 6)?;
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_level() {
+        assert!(DeflateWriter::new(Vec::new(), 0).is_err());
+        assert!(DeflateWriter::new(Vec::new(), 10).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+#![forbid(unsafe_code)]
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Read, Write};
+
+use anyhow::{bail, Result};
+
+use crate::gzip::header::CompressionMethod;
+use crate::gzip::GzipReader;
+use crate::tracking_writer::{TrackingWriter, HISTORY_SIZE};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Bytes decoded at once when the pending output runs out.
+const CHUNK_SIZE: usize = HISTORY_SIZE;
+
+enum State {
+    Header,
+    Body,
+    Done,
+}
+
+/// Decompresses the gzip members of the underlying reader as the output is read. Memory is
+/// bounded: only the history and about a chunk of decoded but not yet read bytes are kept.
+pub struct GzDecoder<R: BufRead> {
+    gzip: GzipReader<R, VecDeque<u8>>,
+    writer: TrackingWriter<VecDeque<u8>>,
+    state: State,
+}
+
+impl<R: BufRead> GzDecoder<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            gzip: GzipReader::new(reader),
+            writer: TrackingWriter::new(VecDeque::with_capacity(CHUNK_SIZE)),
+            state: State::Header,
+        }
+    }
+
+    /// Make some progress decoding the input, returns false once it has ended.
+    fn decode_chunk(&mut self) -> Result<bool> {
+        match self.state {
+            State::Header => {
+                let (header, _) = self.gzip.parse_header()?;
+                if let CompressionMethod::Unknown(_) = header.compression_method {
+                    bail!("unsupported compression method")
+                }
+                self.state = State::Body;
+            }
+            State::Body => {
+                let gzip = &mut self.gzip;
+                if gzip
+                    .decoder
+                    .decode_some(&mut gzip.reader, &mut self.writer, CHUNK_SIZE)?
+                {
+                    gzip.parse_footer(self.writer.byte_count(), self.writer.crc32())?;
+                    self.writer.reset();
+                    self.state = if gzip.has_next_member()? {
+                        State::Header
+                    } else {
+                        State::Done
+                    };
+                }
+            }
+            State::Done => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Decode until some output is pending or the input has ended.
+    fn fill(&mut self) -> io::Result<()> {
+        while self.writer.get_mut().is_empty() {
+            if !self.decode_chunk().map_err(into_io_error)? {
+                break;
+            }
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for GzDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.fill()?;
+        self.writer.get_mut().read(buf)
+    }
+}
+
+impl<R: BufRead> BufRead for GzDecoder<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.fill()?;
+        self.writer.get_mut().fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.writer.get_mut().consume(amt)
+    }
+}
+
+/// Keep I/O errors of the underlying reader as they are, the others mean invalid data.
+fn into_io_error(err: anyhow::Error) -> io::Error {
+    match err.downcast::<io::Error>() {
+        Ok(err) => err,
+        Err(err) => io::Error::new(io::ErrorKind::InvalidData, err),
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gzip::GzipWriter;
+
+    fn compress(data: &[u8], level: u32) -> Result<Vec<u8>> {
+        let mut writer = GzipWriter::new(Vec::new(), level)?;
+        writer.write_all(data)?;
+        writer.finish()
+    }
+
+    #[test]
+    fn read_in_small_pieces() -> Result<()> {
+        let lines: Vec<String> = (0..20000)
+            .map(|i| format!("line {}", i * i % 7919))
+            .collect();
+        let text = lines.join("\n");
+        let mut data = compress(text.as_bytes(), 6)?;
+        data.extend(compress(b"\nlast", 1)?);
+
+        let mut decoder = GzDecoder::new(data.as_slice());
+        let mut out = Vec::new();
+        let mut buf = [0; 7];
+        loop {
+            let len = decoder.read(&mut buf)?;
+            if len == 0 {
+                break;
+            }
+            out.extend_from_slice(&buf[..len]);
+        }
+        assert_eq!(out, format!("{text}\nlast").as_bytes());
+
+        let decoded: Vec<String> = GzDecoder::new(data.as_slice())
+            .lines()
+            .collect::<io::Result<_>>()?;
+        assert_eq!(decoded.len(), lines.len() + 1);
+        assert_eq!(decoded[..lines.len()], lines);
+        Ok(())
+    }
+
+    #[test]
+    fn memory_is_bounded() -> Result<()> {
+        let data = compress(&vec![b'z'; 10_000_000], 9)?;
+        let mut decoder = GzDecoder::new(data.as_slice());
+        let mut total = 0;
+        while !decoder.fill_buf()?.is_empty() {
+            assert!(decoder.writer.get_mut().len() < 2 * CHUNK_SIZE);
+            let len = decoder.fill_buf()?.len();
+            total += len;
+            decoder.consume(len);
+        }
+        assert_eq!(total, 10_000_000);
+        Ok(())
+    }
+
+    #[test]
+    fn errors() {
+        let mut data = compress(b"some data", 6).unwrap();
+        let len = data.len();
+        data[len - 8] ^= 1;
+        let err = GzDecoder::new(data.as_slice())
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("crc32 check failed"));
+
+        let err = GzDecoder::new(&data[..len - 10])
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -50,6 +50,24 @@ impl MemberHeader {
         ((digest.finalize()) & 0xffff) as u16
     }
 
+    pub fn write<W: std::io::Write>(&self, writer: &mut W) -> anyhow::Result<()> {
+        writer.write_all(&[ID1, ID2, self.compression_method.into(), self.flags().0])?;
+        writer.write_all(&self.modification_time.to_le_bytes())?;
+        writer.write_all(&[self.extra_flags, self.os])?;
+        if let Some(extra) = &self.extra {
+            writer.write_all(&(extra.len() as u16).to_le_bytes())?;
+            writer.write_all(extra)?;
+        }
+        for text in [&self.name, &self.comment].into_iter().flatten() {
+            writer.write_all(text.as_bytes())?;
+            writer.write_all(&[0])?;
+        }
+        if self.has_crc {
+            writer.write_all(&self.crc16().to_le_bytes())?;
+        }
+        Ok(())
+    }
+
     pub fn flags(&self) -> MemberFlags {
         let mut flags = MemberFlags(0);
         flags.set_is_text(self.is_text);
@@ -92,6 +110,14 @@ pub struct MemberFooter {
     pub data_size: u32,
 }
 
+impl MemberFooter {
+    pub fn write<W: std::io::Write>(&self, writer: &mut W) -> anyhow::Result<()> {
+        writer.write_all(&self.data_crc32.to_le_bytes())?;
+        writer.write_all(&self.data_size.to_le_bytes())?;
+        Ok(())
+    }
+}
+
 impl<T: BufRead, I: std::io::Write> crate::gzip::GzipReader<T, I> {
    pub(super) fn parse_header(&mut self) -> anyhow::Result<(MemberHeader, MemberFlags)> {
         let mut reader = &mut self.reader;
@@ -6,13 +6,20 @@ mod header;
 
 mod flags;
 
+mod writer;
+
+mod decoder;
+
+pub use decoder::GzDecoder;
+pub use writer::GzipWriter;
+
 use crate::bit_reader::BitReader;
 use crate::deflate::{DeflateReader};
 use anyhow::{bail, Result};
 use byteorder::ReadBytesExt;
 
 use std::fmt::Write;
-use std::io::BufRead;
+use std::io::{self, BufRead};
 use crate::gzip::header::CompressionMethod;
 use crate::tracking_writer::TrackingWriter;
 
@@ -28,7 +35,13 @@ const FNAME_OFFSET: u8 = 3;
 const FCOMMENT_OFFSET: u8 = 4;
 
 pub trait Decoder<T: BufRead, I: std::io::Write> {
-    fn decode(&mut self, _: &mut BitReader<T>, _: &mut TrackingWriter<I>) -> Result<()>;
+    /// Decode until at least `limit` bytes are written or the stream ends, returns whether it ended.
+    fn decode_some(&mut self, _: &mut BitReader<T>, _: &mut TrackingWriter<I>, limit: usize) -> Result<bool>;
+
+    fn decode(&mut self, reader: &mut BitReader<T>, writer: &mut TrackingWriter<I>) -> Result<()> {
+        while !self.decode_some(reader, writer, usize::MAX)? {}
+        Ok(())
+    }
 }
 pub struct GzipReader<T, I> {
     reader: BitReader<T>,
@@ -41,14 +54,45 @@ impl<T: BufRead, I: std::io::Write> GzipReader<T, I> {
         Self { reader: BitReader::new(reader), decoder: Box::new(decoder) }
     }
 
+    /// Decode the members one after another until the end of the input.
     pub fn decode(mut self, writer: I) -> Result<()> {
-        let header = self.parse_header()?;
         let mut tracking_writer = TrackingWriter::new(writer);
-        match header.0.compression_method {
-            CompressionMethod::Deflate => { self.decoder.decode(&mut self.reader, &mut tracking_writer)?; }
-            CompressionMethod::Unknown(v) => { bail!("unsupported compression method") }
+        loop {
+            let header = self.parse_header()?;
+            match header.0.compression_method {
+                CompressionMethod::Deflate => { self.decoder.decode(&mut self.reader, &mut tracking_writer)?; }
+                CompressionMethod::Unknown(v) => { bail!("unsupported compression method") }
+            }
+            self.parse_footer(tracking_writer.byte_count(), tracking_writer.crc32())?;
+            tracking_writer.reset();
+            if !self.has_next_member()? {
+                break;
+            }
+        }
+        io::Write::flush(&mut tracking_writer)?;
+        Ok(())
+    }
+
+    /// Whether another member follows the one just decoded. Zero padding at the end
+    /// of the input is skipped, like GNU gzip does.
+    fn has_next_member(&mut self) -> Result<bool> {
+        let mut stream = self.reader.borrow_reader_from_boundary();
+        let mut padding = false;
+        loop {
+            let buf = stream.fill_buf()?;
+            if buf.is_empty() {
+                return Ok(false);
+            }
+            match buf.iter().position(|byte| *byte != 0) {
+                Some(0) if !padding => return Ok(true),
+                Some(_) => bail!("unexpected data after zero padding"),
+                None => {
+                    padding = true;
+                    let len = buf.len();
+                    stream.consume(len);
+                }
+            }
         }
-        self.parse_footer(tracking_writer.byte_count(), tracking_writer.crc32() )
     }
 
 }
@@ -0,0 +1,77 @@
+#![forbid(unsafe_code)]
+
+use std::io::{self, Write};
+
+use anyhow::Result;
+use crc::{Crc, Digest, CRC_32_ISO_HDLC};
+
+use crate::deflate::DeflateWriter;
+use crate::gzip::header::{CompressionMethod, MemberFooter, MemberHeader};
+
+////////////////////////////////////////////////////////////////////////////////
+
+const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+/// Extra flags of the header for the maximum and the fastest compression.
+const XFL_BEST: u8 = 2;
+const XFL_FASTEST: u8 = 4;
+const OS_UNKNOWN: u8 = 255;
+
+/// Writes a single gzip member: the header, the deflate stream of the data written into it
+/// and the footer with CRC32 and ISIZE of the data.
+pub struct GzipWriter<T: Write> {
+    deflate: DeflateWriter<T>,
+    crc32: Digest<'static, u32>,
+    size: u32,
+}
+
+impl<T: Write> GzipWriter<T> {
+    pub fn new(writer: T, level: u32) -> Result<Self> {
+        let mut deflate = DeflateWriter::new(writer, level)?;
+        let header = MemberHeader {
+            compression_method: CompressionMethod::Deflate,
+            modification_time: 0,
+            extra: None,
+            name: None,
+            comment: None,
+            extra_flags: match level {
+                9 => XFL_BEST,
+                1 => XFL_FASTEST,
+                _ => 0,
+            },
+            os: OS_UNKNOWN,
+            has_crc: false,
+            is_text: false,
+        };
+        header.write(deflate.get_mut())?;
+        Ok(Self {
+            deflate,
+            crc32: CRC32.digest(),
+            size: 0,
+        })
+    }
+
+    /// Write the final deflate block and the footer, returning the underlying writer.
+    pub fn finish(self) -> Result<T> {
+        let mut writer = self.deflate.finish()?;
+        let footer = MemberFooter {
+            data_crc32: self.crc32.finalize(),
+            data_size: self.size,
+        };
+        footer.write(&mut writer)?;
+        Ok(writer)
+    }
+}
+
+impl<T: Write> Write for GzipWriter<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.deflate.write(buf)?;
+        self.crc32.update(&buf[..len]);
+        // ISIZE is the size modulo 2^32.
+        self.size = self.size.wrapping_add(len as u32);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.deflate.flush()
+    }
+}
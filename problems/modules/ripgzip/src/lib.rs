@@ -1,17 +1,89 @@
 #![forbid(unsafe_code)]
 
-use std::io::{BufRead, Write};
+use std::io::{self, BufRead, Read, Write};
+use std::str::FromStr;
 
-use crate::gzip::GzipReader;
-use anyhow::{Context, Result};
+use crate::bit_reader::BitReader;
+use crate::deflate::DeflateReader;
+use crate::gzip::{Decoder, GzipReader, GzipWriter};
+use crate::tracking_writer::TrackingWriter;
+use crate::zlib::ZlibReader;
+use anyhow::{bail, Context, Result};
 use log::*;
 
 mod deflate;
 mod gzip;
 mod bit_reader;
+mod bit_writer;
 mod tracking_writer;
+mod zlib;
+
+pub use gzip::GzDecoder;
 
 pub fn decompress<R: BufRead, W: Write>(input: R, mut output: W) -> Result<()> {
     let mut gz = GzipReader::new(input);
     gz.decode(output)
 }
+
+/// Decompress a raw deflate stream of RFC 1951, without any container around it.
+pub fn decompress_deflate<R: BufRead, W: Write>(input: R, output: W) -> Result<()> {
+    let mut tracking_writer = TrackingWriter::new(output);
+    DeflateReader::new().decode(&mut BitReader::new(input), &mut tracking_writer)?;
+    tracking_writer.flush()?;
+    Ok(())
+}
+
+/// Decompress a zlib stream of RFC 1950. The `dictionary` is needed only for the streams
+/// compressed with a preset one.
+pub fn decompress_zlib<R: BufRead, W: Write>(input: R, output: W, dictionary: Option<&[u8]>) -> Result<()> {
+    ZlibReader::new(input, dictionary).decode(output)
+}
+
+/// The input with the bytes read by `Format::detect` put back in front of it.
+pub type Peeked<R> = io::Chain<io::Cursor<Vec<u8>>, R>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Gzip,
+    Zlib,
+    /// A deflate stream without a container.
+    Raw,
+}
+
+impl Format {
+    /// Guess the format by the first two bytes of the input, returning it along with the
+    /// whole input, those bytes included. Anything that is neither gzip nor zlib is supposed
+    /// to be a raw deflate stream.
+    pub fn detect<R: BufRead>(mut input: R) -> io::Result<(Self, Peeked<R>)> {
+        // A single fill_buf may return less than two bytes, e.g. for a pipe.
+        let mut head = Vec::with_capacity(2);
+        input.by_ref().take(2).read_to_end(&mut head)?;
+        let format = match head[..] {
+            [0x1f, 0x8b] => Self::Gzip,
+            [cmf, flg] if zlib::is_header(cmf, flg) => Self::Zlib,
+            _ => Self::Raw,
+        };
+        Ok((format, io::Cursor::new(head).chain(input)))
+    }
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "gzip" => Ok(Self::Gzip),
+            "zlib" => Ok(Self::Zlib),
+            "raw" => Ok(Self::Raw),
+            _ => bail!("unknown format {}", s),
+        }
+    }
+}
+
+/// Compress the input into a single gzip member, `level` is from 1 (fastest) to 9 (best).
+pub fn compress<R: BufRead, W: Write>(mut input: R, output: W, level: u32) -> Result<()> {
+    let mut gz = GzipWriter::new(output, level)?;
+    io::copy(&mut input, &mut gz).context("failed to compress")?;
+    gz.finish()?.flush()?;
+    Ok(())
+}
@@ -1,12 +1,14 @@
 #![forbid(unsafe_code)]
 
-use std::fs::File;
-use std::io::{stdin, stdout, BufReader};
+use std::fs;
+use std::io::{stdin, stdout, BufRead, Write};
+use std::path::PathBuf;
 
 use log::*;
 use structopt::StructOpt;
 
-use ripgzip::decompress;
+use anyhow::{bail, Context};
+use ripgzip::{compress, decompress, decompress_deflate, decompress_zlib, Format};
 
 #[derive(StructOpt, Debug)]
 #[structopt()]
@@ -14,20 +16,21 @@ struct Opts {
     /// Decompress data
     #[structopt(short = "d", long = "decompress")]
     decompress: bool,
+    /// Compression level from 1 (fastest) to 9 (best)
+    #[structopt(short = "l", long = "level", default_value = "6")]
+    level: u32,
+    /// Format of the compressed data: gzip, zlib, raw (deflate) or auto to detect it
+    #[structopt(long = "format", default_value = "auto")]
+    format: String,
+    /// Preset dictionary of zlib streams
+    #[structopt(long = "dictionary")]
+    dictionary: Option<PathBuf>,
     /// Verbose mode (-v, -vv, -vvv, etc)
     #[structopt(short = "v", long = "verbose", parse(from_occurrences))]
     verbose: usize,
 }
-//06-war-and-peace.txt.gz
+
 fn main() {
-   /* let c = std::env::current_dir().unwrap();
-    let ck = c.join("problems/modules/ripgzip");
-    let file = File::open(ck.join("data/corrupted/08-bad-nlen.gz")).unwrap();
-    let output_file = File::create(ck.join("header")).unwrap();
-    let res = decompress(BufReader::new(file), output_file);
-    if res.is_err() {
-        panic!("{}", res.unwrap_err())
-    }*/
     let opts = Opts::from_args();
     stderrlog::new()
         .verbosity(1 + opts.verbose)
@@ -35,10 +38,46 @@ fn main() {
         .init()
         .expect("failed to initialize logging");
 
-    if opts.decompress {
-        if let Err(err) = decompress(stdin().lock(), stdout().lock()) {
-            error!("{:#}", err);
-            std::process::exit(1);
+    if let Err(err) = run(opts) {
+        error!("{:#}", err);
+        std::process::exit(1);
+    }
+}
+
+fn run(opts: Opts) -> anyhow::Result<()> {
+    let input = stdin().lock();
+    let output = stdout().lock();
+    let format = match opts.format.as_str() {
+        "auto" => None,
+        format => Some(format.parse::<Format>()?),
+    };
+    if !opts.decompress {
+        if format.is_some_and(|format| format != Format::Gzip) {
+            bail!("only gzip compression is supported");
+        }
+        return compress(input, output, opts.level);
+    }
+
+    match format {
+        Some(format) => decode(format, input, output, &opts),
+        None => {
+            let (format, input) = Format::detect(input)?;
+            decode(format, input, output, &opts)
+        }
+    }
+}
+
+fn decode<R: BufRead, W: Write>(format: Format, input: R, output: W, opts: &Opts) -> anyhow::Result<()> {
+    info!("decompressing {:?} data", format);
+    match format {
+        Format::Gzip => decompress(input, output),
+        Format::Zlib => {
+            let dictionary = match &opts.dictionary {
+                Some(path) => Some(fs::read(path).with_context(|| format!("failed to read {}", path.display()))?),
+                None => None,
+            };
+            decompress_zlib(input, output, dictionary.as_deref())
         }
+        Format::Raw => decompress_deflate(input, output),
     }
 }
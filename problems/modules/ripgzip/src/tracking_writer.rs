@@ -6,7 +6,7 @@ use anyhow::{bail, Result};
 use crc::{Digest, CRC_32_ISO_HDLC};
 ////////////////////////////////////////////////////////////////////////////////
 
-const HISTORY_SIZE: usize = 32768;
+pub const HISTORY_SIZE: usize = 32768;
 const X25: crc::Crc<u32> = crc::Crc::<u32>::new(&CRC_32_ISO_HDLC);
 pub struct TrackingWriter<T: Write> {
     inner: BufWriter<T>,
@@ -22,8 +22,8 @@ impl<T: Write> Write for TrackingWriter<T> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         match self.inner.write(buf) {
             Ok(v) => {
-                self.crc_32.update(buf);
-                self.length += buf.len();
+                self.crc_32.update(&buf[..v]);
+                self.length += v;
                 for i in 0..v {
                     self.append_to_buf(buf[i]);
                 }
@@ -83,12 +83,34 @@ impl<T: Write> TrackingWriter<T> {
         }
     }
 
+    /// The underlying writer, without the bytes that are not flushed yet.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut()
+    }
+
     pub fn byte_count(&self) -> usize {
         self.length
     }
 
-    pub fn crc32(self) -> u32 {
-        self.crc_32.finalize()
+    pub fn crc32(&self) -> u32 {
+        self.crc_32.clone().finalize()
+    }
+
+    /// Make the end of `dictionary` the history, as if it was written before. It's neither
+    /// counted nor included into CRC32.
+    pub fn set_dictionary(&mut self, dictionary: &[u8]) {
+        for b in &dictionary[dictionary.len().saturating_sub(HISTORY_SIZE)..] {
+            self.append_to_buf(*b);
+        }
+    }
+
+    /// Forget the history, the count and CRC32 of the written bytes, e.g. before
+    /// the next gzip member.
+    pub fn reset(&mut self) {
+        self.current_index = 0;
+        self.buf_len = 0;
+        self.crc_32 = X25.digest();
+        self.length = 0;
     }
 
     fn append_to_buf(&mut self, b: u8) {
@@ -0,0 +1,98 @@
+#![forbid(unsafe_code)]
+
+use std::io::{self, Write};
+
+////////////////////////////////////////////////////////////////////////////////
+
+const MOD_ADLER: u32 = 65521;
+/// The most bytes that can be summed before `b` may overflow, see zlib's `NMAX`.
+const NMAX: usize = 5552;
+
+/// Adler-32 checksum of RFC 1950, section 8.2.
+#[derive(Clone, Copy, Debug)]
+pub struct Adler32 {
+    a: u32,
+    b: u32,
+}
+
+impl Adler32 {
+    pub fn new() -> Self {
+        Self { a: 1, b: 0 }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for chunk in data.chunks(NMAX) {
+            for byte in chunk {
+                self.a += *byte as u32;
+                self.b += self.a;
+            }
+            self.a %= MOD_ADLER;
+            self.b %= MOD_ADLER;
+        }
+    }
+
+    pub fn finalize(&self) -> u32 {
+        self.b << 16 | self.a
+    }
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    let mut adler = Adler32::new();
+    adler.update(data);
+    adler.finalize()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Passes the written bytes through, computing their Adler-32.
+pub struct Adler32Writer<T> {
+    inner: T,
+    adler: Adler32,
+}
+
+impl<T: Write> Adler32Writer<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            adler: Adler32::new(),
+        }
+    }
+
+    pub fn adler32(&self) -> u32 {
+        self.adler.finalize()
+    }
+}
+
+impl<T: Write> Write for Adler32Writer<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.adler.update(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksum() -> io::Result<()> {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+
+        let data = vec![0xff; 100_000];
+        let mut writer = Adler32Writer::new(Vec::new());
+        for chunk in data.chunks(777) {
+            writer.write_all(chunk)?;
+        }
+        assert_eq!(writer.adler32(), adler32(&data));
+        assert_eq!(writer.adler32(), 0x149a302c);
+        Ok(())
+    }
+}
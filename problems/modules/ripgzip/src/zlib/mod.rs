@@ -0,0 +1,149 @@
+#![forbid(unsafe_code)]
+
+mod adler32;
+
+use std::io::{BufRead, Write};
+
+use anyhow::{bail, Result};
+use byteorder::{BigEndian, ReadBytesExt};
+use log::info;
+
+use crate::bit_reader::BitReader;
+use crate::deflate::DeflateReader;
+use crate::gzip::Decoder;
+use crate::tracking_writer::TrackingWriter;
+use adler32::{adler32, Adler32Writer};
+
+////////////////////////////////////////////////////////////////////////////////
+
+const CM_DEFLATE: u8 = 8;
+/// The window of 32K, the largest one allowed.
+const MAX_CINFO: u8 = 7;
+const FDICT: u8 = 1 << 5;
+
+/// Whether the two bytes look like a zlib header: deflate with a valid window and FCHECK.
+pub fn is_header(cmf: u8, flg: u8) -> bool {
+    cmf & 0x0f == CM_DEFLATE
+        && cmf >> 4 <= MAX_CINFO
+        && (u16::from(cmf) << 8 | u16::from(flg)) % 31 == 0
+}
+
+/// Reads a zlib stream of RFC 1950: the header, the deflate stream and Adler-32 of the data.
+pub struct ZlibReader<T, I> {
+    reader: BitReader<T>,
+    decoder: Box<dyn Decoder<T, Adler32Writer<I>>>,
+    dictionary: Option<Vec<u8>>,
+}
+
+impl<T: BufRead, I: Write> ZlibReader<T, I> {
+    /// The `dictionary` is needed only for the streams compressed with a preset one.
+    pub fn new(reader: T, dictionary: Option<&[u8]>) -> Self {
+        Self {
+            reader: BitReader::new(reader),
+            decoder: Box::new(DeflateReader::new()),
+            dictionary: dictionary.map(<[u8]>::to_vec),
+        }
+    }
+
+    pub fn decode(mut self, writer: I) -> Result<()> {
+        let dictionary_id = self.parse_header()?;
+        let mut tracking_writer = TrackingWriter::new(Adler32Writer::new(writer));
+        if let Some(id) = dictionary_id {
+            let Some(dictionary) = &self.dictionary else {
+                bail!("preset dictionary is required")
+            };
+            if adler32(dictionary) != id {
+                bail!("preset dictionary id mismatch")
+            }
+            tracking_writer.set_dictionary(dictionary);
+        }
+        self.decoder
+            .decode(&mut self.reader, &mut tracking_writer)?;
+        tracking_writer.flush()?;
+        let checksum = self
+            .reader
+            .borrow_reader_from_boundary()
+            .read_u32::<BigEndian>()?;
+        if checksum != tracking_writer.get_mut().adler32() {
+            bail!("adler32 check failed")
+        }
+        Ok(())
+    }
+
+    /// Returns the Adler-32 of the preset dictionary if it's used.
+    fn parse_header(&mut self) -> Result<Option<u32>> {
+        let mut stream = self.reader.borrow_reader_from_boundary();
+        let (cmf, flg) = (stream.read_u8()?, stream.read_u8()?);
+        if cmf & 0x0f != CM_DEFLATE {
+            bail!("unsupported compression method")
+        }
+        if cmf >> 4 > MAX_CINFO {
+            bail!("invalid window size")
+        }
+        if !is_header(cmf, flg) {
+            bail!("header check failed")
+        }
+        info!("compression level is {}", flg >> 6);
+        if flg & FDICT == 0 {
+            return Ok(None);
+        }
+        Ok(Some(stream.read_u32::<BigEndian>()?))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELLO: [u8; 34] = [
+        120, 218, 203, 72, 205, 201, 201, 87, 200, 64, 144, 58, 10, 85, 57, 153, 73, 10, 229, 249,
+        69, 57, 41, 138, 92, 25, 20, 73, 3, 0, 29, 169, 32, 197,
+    ];
+    const DICTIONARY: &[u8] = b"the quick brown fox jumps over the lazy dog";
+    const WITH_DICTIONARY: [u8; 18] = [
+        120, 249, 97, 60, 15, 250, 67, 102, 163, 171, 65, 51, 2, 0, 93, 102, 15, 250,
+    ];
+
+    fn decode(data: &[u8], dictionary: Option<&[u8]>) -> Result<Vec<u8>> {
+        let mut output = Vec::new();
+        ZlibReader::new(data, dictionary).decode(&mut output)?;
+        Ok(output)
+    }
+
+    #[test]
+    fn decode_stream() -> Result<()> {
+        assert!(is_header(HELLO[0], HELLO[1]));
+        assert_eq!(
+            decode(&HELLO, None)?,
+            b"hello hello hello, zlib world!\n".repeat(3)
+        );
+
+        let mut corrupted = HELLO;
+        corrupted[33] ^= 1;
+        assert!(decode(&corrupted, None)
+            .unwrap_err()
+            .to_string()
+            .contains("adler32 check failed"));
+        corrupted[1] ^= 1;
+        assert!(decode(&corrupted, None)
+            .unwrap_err()
+            .to_string()
+            .contains("header check failed"));
+        Ok(())
+    }
+
+    #[test]
+    fn preset_dictionary() -> Result<()> {
+        assert_eq!(
+            decode(&WITH_DICTIONARY, Some(DICTIONARY))?,
+            b"the lazy dog jumps over the quick brown fox"
+        );
+        let err = decode(&WITH_DICTIONARY, None).unwrap_err();
+        assert!(err.to_string().contains("preset dictionary is required"));
+        let err = decode(&WITH_DICTIONARY, Some(b"another dictionary")).unwrap_err();
+        assert!(err.to_string().contains("preset dictionary id mismatch"));
+        Ok(())
+    }
+}
@@ -5,6 +5,8 @@ import pathlib
 import subprocess
 import sys
 import random
+import tempfile
+import zlib
 
 DIR = pathlib.Path(__file__).parent.absolute()
 DEBUG_BINARY_PATH = DIR / ".." / ".." / ".." / "target" / "debug" / "ripgzip"
@@ -76,11 +78,87 @@ def test_big_random_cases():
             raise
 
 
+def compress_file_ripgzip(data, level):
+    proc = subprocess.run(
+        [RELEASE_BINARY_PATH, "-l", str(level)], input=data, capture_output=True, check=True
+    )
+    return proc.stdout
+
+
+def test_compression_cases():
+    random.seed(7345234)
+
+    cases = [b"", b"a"]
+    for file_path in sorted(OK_TESTS_PATH.iterdir())[:3]:
+        with open(file_path, "rb") as f:
+            cases.append(gzip.decompress(f.read()))
+    cases.append(bytes(random.randrange(256) for _ in range(100000)))
+    cases.append(bytes(random.randrange(4) for _ in range(1000)) * 300)
+
+    for i, data in enumerate(cases):
+        for level in [1, 6, 9]:
+            print(f"testing compression, case #{i + 1}, level {level}")
+
+            compressed = compress_file_ripgzip(data, level)
+            assert gzip.decompress(compressed) == data, "incorrect compressed data"
+            assert decompress_file_ripgzip(compressed) == data, "incorrect roundtrip"
+            assert len(compressed) <= len(data) + len(data) // 1000 + 64, "compressed data is too big"
+
+
+def test_multi_member_cases():
+    print("checking file 'data/09-concat.gz'")
+    with open(DIR / "data" / "09-concat.gz", "rb") as f:
+        data = f.read()
+    assert decompress_file_ripgzip(data) == gzip.decompress(data), "incorrect output"
+
+    random.seed(93452)
+    for i in range(10):
+        print(f"testing multi-member data, case #{i + 1}")
+
+        parts = [bytes(random.randrange(16) for _ in range(random.randrange(50000))) for _ in range(i + 1)]
+        compressed = b"".join(gzip.compress(part) for part in parts) + b"\0" * random.randrange(3)
+        assert decompress_file_ripgzip(compressed) == b"".join(parts), "incorrect output"
+
+
+def decompress_ripgzip_args(data, args):
+    proc = subprocess.run([RELEASE_BINARY_PATH, "-d", *args], input=data, capture_output=True, check=True)
+    return proc.stdout
+
+
+def test_zlib_and_raw_cases():
+    random.seed(2364523)
+
+    for i in range(10):
+        print(f"testing zlib and raw deflate data, case #{i + 1}")
+
+        data = bytes(random.randrange(8) for _ in range(random.randrange(100000)))
+        level = random.randrange(10)
+        raw = zlib.compressobj(level, zlib.DEFLATED, -15)
+        cases = [
+            (zlib.compress(data, level), "zlib"),
+            (raw.compress(data) + raw.flush(), "raw"),
+        ]
+        for compressed, format in cases:
+            assert decompress_ripgzip_args(compressed, ["--format", format]) == data, "incorrect output"
+            assert decompress_ripgzip_args(compressed, []) == data, f"{format} is not detected"
+
+        dictionary = bytes(random.randrange(8) for _ in range(1000))
+        compressor = zlib.compressobj(level, zdict=dictionary)
+        compressed = compressor.compress(data) + compressor.flush()
+        with tempfile.NamedTemporaryFile() as f:
+            f.write(dictionary)
+            f.flush()
+            assert decompress_ripgzip_args(compressed, ["--dictionary", f.name]) == data, "incorrect output"
+
+
 def main():
     bundles = [
         test_static_cases,
         test_small_random_cases,
         test_big_random_cases,
+        test_compression_cases,
+        test_multi_member_cases,
+        test_zlib_and_raw_cases,
     ]
 
     if len(sys.argv) > 1:
@@ -0,0 +1,46 @@
+use std::io::{BufReader, Read};
+
+use ripgzip::Format;
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    ripgzip::compress(data, &mut output, 6).unwrap();
+    output
+}
+
+fn zlib(data: &[u8]) -> Vec<u8> {
+    // Our gzip members have no optional header fields.
+    let gzip = gzip(data);
+    let raw = &gzip[10..gzip.len() - 8];
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), &byte| {
+        let a = (a + byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    [&[0x78, 0x9c], raw, &(b << 16 | a).to_be_bytes()].concat()
+}
+
+/// Detect the format of the input coming one byte per `fill_buf`.
+fn detect(data: &[u8]) -> (Format, Vec<u8>) {
+    let (format, mut input) = Format::detect(BufReader::with_capacity(1, data)).unwrap();
+    let mut all = Vec::new();
+    input.read_to_end(&mut all).unwrap();
+    (format, all)
+}
+
+#[test]
+fn short_reads() {
+    let data = b"some data, some data";
+    let gzip = gzip(data);
+    assert_eq!(detect(&gzip), (Format::Gzip, gzip.clone()));
+    let zlib = zlib(data);
+    assert_eq!(detect(&zlib), (Format::Zlib, zlib.clone()));
+    let raw = &gzip[10..gzip.len() - 8];
+    assert_eq!(detect(raw), (Format::Raw, raw.to_vec()));
+    assert_eq!(detect(&[0x1f]), (Format::Raw, vec![0x1f]));
+    assert_eq!(detect(&[]), (Format::Raw, vec![]));
+
+    let mut output = Vec::new();
+    let (_, input) = Format::detect(BufReader::with_capacity(1, zlib.as_slice())).unwrap();
+    ripgzip::decompress_zlib(input, &mut output, None).unwrap();
+    assert_eq!(output, data);
+}
@@ -0,0 +1,27 @@
+fn decompress(mut data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut output = Vec::new();
+    ripgzip::decompress(&mut data, &mut output)?;
+    Ok(output)
+}
+
+fn compress(mut data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    ripgzip::compress(&mut data, &mut output, 6).unwrap();
+    output
+}
+
+#[test]
+fn members() {
+    let parts: [&[u8]; 3] = [b"first member\n", b"", &[b'x'; 100_000]];
+    let mut data = Vec::new();
+    for part in parts {
+        data.extend(compress(part));
+    }
+    assert_eq!(decompress(&data).unwrap(), parts.concat());
+
+    data.extend([0; 100]);
+    assert_eq!(decompress(&data).unwrap(), parts.concat());
+
+    data.push(1);
+    assert!(decompress(&data).is_err());
+}
@@ -1,48 +1,161 @@
-use super::config::Config;
+use super::{
+    config::Config,
+    manifest::{Content, Manifest},
+};
 use anyhow::{Context, Result};
 use std::{
+    collections::HashSet,
     fs,
     path::{Path, PathBuf},
 };
+use toml_edit::{Array, DocumentMut, Item, Value};
+
+const MEMBER_INDENT: &str = "\n    ";
+
+/// Make the root `Cargo.toml` of the output from the one of the private repository:
+/// workspace members are limited to the composed entries, everything else is kept as is.
+pub fn cargo_root(input: &Path, config: &Config, manifest: &mut Manifest) -> Result<()> {
+    let path = input.join("Cargo.toml");
+    let mut document = if path.exists() {
+        fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {path:?}"))?
+            .parse::<DocumentMut>()
+            .with_context(|| format!("failed to parse {path:?}"))?
+    } else {
+        DocumentMut::new()
+    };
 
-pub fn cargo_root(output: &Path, config: &Config) -> Result<()> {
-    let problems: Vec<_> = config
+    let entries = composed_entries(config)?;
+    let workspace = document
+        .entry("workspace")
+        .or_insert_with(toml_edit::table)
+        .as_table_like_mut()
+        .context("workspace is not a table")?;
+    let members = workspace
+        .entry("members")
+        .or_insert_with(|| toml_edit::value(Array::new()))
+        .as_array_mut()
+        .context("workspace members are not an array")?;
+    *members = filter_members(members, &entries, true);
+    if let Some(Item::Value(Value::Array(default_members))) = workspace.get_mut("default-members") {
+        *default_members = filter_members(default_members, &entries, false);
+    }
+
+    manifest.insert(
+        PathBuf::from("Cargo.toml"),
+        Content::Generated(document.to_string().into_bytes()),
+    );
+    Ok(())
+}
+
+/// Problems, tools and additional entries in the order of the compose config.
+fn composed_entries(config: &Config) -> Result<Vec<String>> {
+    let problems = config
         .get_problems()
         .iter()
-        .map(|path| {
-            let path = PathBuf::from("problems").join(path);
-            path.to_str().unwrap().to_string()
-        })
-        .collect();
-    let tools: Vec<_> = config
+        .map(|path| PathBuf::from("problems").join(path));
+    let tools = config
         .get_tools()
         .iter()
+        .map(|path| PathBuf::from("tools").join(path));
+    let additional = config.get_add_to_toml().iter().cloned();
+    problems
+        .chain(tools)
+        .chain(additional)
         .map(|path| {
-            let path = PathBuf::from("tools").join(path);
-            path.to_str().unwrap().to_string()
+            path.to_str()
+                .map(str::to_string)
+                .with_context(|| format!("non-utf-8 path {path:?}"))
         })
-        .collect();
-    let add_to_toml: Vec<_> = config
-        .get_add_to_toml()
+        .collect()
+}
+
+/// Keep the members that are composed along with their formatting. Comments before
+/// a dropped member go to the next kept one, so the grouping of members survives.
+/// If `add_missing`, the composed entries absent in the original array are appended.
+fn filter_members(members: &Array, entries: &[String], add_missing: bool) -> Array {
+    let composed: HashSet<_> = entries.iter().map(String::as_str).collect();
+    let mut present = HashSet::new();
+    let mut filtered = Array::new();
+    let mut pending_prefix: Option<String> = None;
+    for member in members.iter() {
+        let prefix = member
+            .decor()
+            .prefix()
+            .and_then(|prefix| prefix.as_str())
+            .map(str::to_string);
+        let keep = match member.as_str() {
+            // Globs only match the directories that were composed.
+            Some(name) if name.contains('*') => true,
+            Some(name) => composed.contains(name),
+            None => false,
+        };
+        if !keep {
+            if pending_prefix.is_none() {
+                pending_prefix = prefix.filter(|prefix| prefix.contains('#'));
+            }
+            continue;
+        }
+        let mut member = member.clone();
+        if let Some(name) = member.as_str() {
+            present.insert(name.to_string());
+        }
+        if let Some(pending) = pending_prefix.take() {
+            if !prefix.is_some_and(|prefix| prefix.contains('#')) {
+                member.decor_mut().set_prefix(pending);
+            }
+        }
+        filtered.push_formatted(member);
+    }
+    if add_missing {
+        for entry in entries {
+            if present.contains(entry) {
+                continue;
+            }
+            let mut member = Value::from(entry.as_str());
+            member.decor_mut().set_prefix(MEMBER_INDENT);
+            filtered.push_formatted(member);
+        }
+    }
+    if filtered
         .iter()
-        .map(|path| path.to_str().unwrap().to_string())
-        .collect();
-    let content = format!(
-        r#"[workspace]
+        .any(|member| member.decor().prefix().is_some())
+    {
+        filtered.set_trailing_comma(true);
+        filtered.set_trailing("\n");
+    }
+    *filtered.decor_mut() = members.decor().clone();
+    filtered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn members_are_filtered() {
+        let document = r#"[workspace]
 members = [
     # Problems
-    "{}",
+    "problems/a/private",
+    "problems/a/public",
 
     # Tools
-    "{}",
-
-    # Additional
-    "{}",
+    "tools/rover",
 ]
-"#,
-        problems.join("\",\n    \""),
-        tools.join("\",\n    \""),
-        add_to_toml.join("\",\n    \"")
-    );
-    fs::write(output.join("Cargo.toml"), content).context("failed to write Cargo.toml")
+"#
+        .parse::<DocumentMut>()
+        .unwrap();
+        let members = document["workspace"]["members"].as_array().unwrap();
+        let entries = ["problems/a/public", "problems/b/new"].map(String::from);
+        let filtered = filter_members(members, &entries, true);
+        assert_eq!(
+            filtered.to_string().trim_start(),
+            r#"[
+    # Problems
+    "problems/a/public",
+    "problems/b/new",
+]"#
+        );
+    }
 }
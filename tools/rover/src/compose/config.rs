@@ -6,20 +6,26 @@ use std::{
 };
 
 #[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "kebab-case")]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct Config {
     problems: Vec<PathBuf>,
+    #[serde(default)]
     tools: Vec<PathBuf>,
+    #[serde(default)]
     copy: Vec<PathBuf>,
+    #[serde(default)]
     skip_entries: Vec<PathBuf>,
+    #[serde(default)]
     add_to_toml: Vec<PathBuf>,
+    #[serde(default)]
     do_not_delete: Vec<PathBuf>,
 }
 
 impl Config {
     pub fn from_yml(path: &Path) -> Result<Self> {
         let file = File::open(path).context("no yml file with config")?;
-        let config: Config = serde_yaml::from_reader(file).context("cannot read file from yml")?;
+        let config: Config = serde_yaml::from_reader(file)
+            .with_context(|| format!("invalid compose config {}", path.display()))?;
         for entry in config.get_do_not_delete() {
             if entry.iter().count() != 1 {
                 bail!("compose doesn't support do-not-delete on paths, only directories or files: {entry:?}")
@@ -1,8 +1,9 @@
-use super::file::process_file;
+use super::{file::process_file, manifest::Manifest};
 use anyhow::{Context, Result};
 use std::{fs, path::Path};
 
-pub fn process_dir(input: &Path, output: &Path) -> Result<()> {
+/// Add the files of `input` to the manifest under `output`, which is relative.
+pub fn process_dir(input: &Path, output: &Path, manifest: &mut Manifest) -> Result<()> {
     let dir = fs::read_dir(input).with_context(|| format!("failed to read directory {input:?}"))?;
     for entry in dir {
         let input = entry
@@ -10,9 +11,9 @@ pub fn process_dir(input: &Path, output: &Path) -> Result<()> {
             .path();
         let output = output.join(input.file_name().unwrap());
         if input.is_dir() {
-            process_dir(&input, &output)?;
-        } else {
-            process_file(&input, &output)?;
+            process_dir(&input, &output, manifest)?;
+        } else if let Some(content) = process_file(&input)? {
+            manifest.insert(output, content);
         }
     }
     Ok(())
@@ -1,11 +1,70 @@
+use super::manifest::Content;
 use anyhow::{bail, Context, Result};
 use std::{fs, path::Path};
 
-#[allow(clippy::enum_variant_names)]
+const HINT: &str = "TODO: your code goes here.";
+
+/// How comments are written in the files that may contain compose markers.
+struct Syntax {
+    comment: &'static str,
+    /// The statement inserted by the `unimplemented` property, if the language has one.
+    unimplemented: Option<&'static str>,
+}
+
+const RUST: Syntax = Syntax {
+    comment: "//",
+    unimplemented: Some("unimplemented!()"),
+};
+
+const HASH: Syntax = Syntax {
+    comment: "#",
+    unimplemented: None,
+};
+
+fn syntax(path: &Path) -> Option<&'static Syntax> {
+    match path.extension()?.to_str()? {
+        "rs" => Some(&RUST),
+        "toml" | "yml" | "yaml" | "py" | "sh" => Some(&HASH),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
 enum TokenKind {
     Private,
     BeginPrivate,
     EndPrivate,
+    BeginReplace,
+    With,
+    EndReplace,
+    SkipFile,
+}
+
+impl TokenKind {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "private" => Self::Private,
+            "begin_private" => Self::BeginPrivate,
+            "end_private" => Self::EndPrivate,
+            "begin_replace" => Self::BeginReplace,
+            "with" => Self::With,
+            "end_replace" => Self::EndReplace,
+            "skip_file" => Self::SkipFile,
+            _ => return None,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Private => "private",
+            Self::BeginPrivate => "begin_private",
+            Self::EndPrivate => "end_private",
+            Self::BeginReplace => "begin_replace",
+            Self::With => "with",
+            Self::EndReplace => "end_replace",
+            Self::SkipFile => "skip_file",
+        }
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -19,82 +78,119 @@ struct Token {
     properties: Vec<TokenProperty>,
 }
 
-fn parse_token(line: &str) -> Result<Option<Token>> {
-    let comment = match line.find("//") {
+fn parse_token(line: &str, syntax: &Syntax) -> Result<Option<Token>> {
+    let comment = match line.find(syntax.comment) {
         Some(pos) => &line[pos..],
         None => return Ok(None),
     };
 
     let cmd = match comment.find("compose::") {
-        Some(pos) => &comment[pos + "compose::".len()..],
+        Some(pos) => comment[pos + "compose::".len()..].trim_end(),
         None => return Ok(None),
     };
 
-    let kind = if cmd.starts_with("private") {
-        TokenKind::Private
-    } else if cmd.starts_with("begin_private") {
-        TokenKind::BeginPrivate
-    } else if cmd.starts_with("end_private") {
-        TokenKind::EndPrivate
-    } else {
-        bail!("unknown compose command: {}", cmd);
+    let name_len = cmd
+        .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .unwrap_or(cmd.len());
+    let kind = match TokenKind::from_name(&cmd[..name_len]) {
+        Some(kind) => kind,
+        None => bail!("unknown compose command: {}", cmd),
     };
 
-    let properties_str = match cmd.find('(') {
-        Some(pos) => {
-            if !cmd.trim_end().ends_with(')') {
-                bail!("unclosed '('");
-            }
-            &cmd[pos + 1..cmd.trim_end().len() - 1]
-        }
+    let properties_str = match cmd[name_len..].strip_prefix('(') {
+        Some(rest) => match rest.strip_suffix(')') {
+            Some(properties) => properties,
+            None => bail!("unclosed '('"),
+        },
         None => "",
     };
 
     let mut properties = vec![];
-    for prop in properties_str.split_inclusive(',') {
+    for prop in properties_str.split(',').map(str::trim) {
         match prop {
+            "" => {}
             "no_hint" => properties.push(TokenProperty::NoHint),
             "unimplemented" => properties.push(TokenProperty::Unimplemented),
             s => bail!("unknown property: {}", s),
         }
     }
+    if !properties.is_empty() && !matches!(kind, TokenKind::Private | TokenKind::BeginPrivate) {
+        bail!("'{}' takes no properties", kind.name());
+    }
+    if properties.contains(&TokenProperty::Unimplemented) && syntax.unimplemented.is_none() {
+        bail!("property 'unimplemented' is supported in Rust files only");
+    }
 
     Ok(Some(Token { kind, properties }))
 }
 
-fn find_token(lines: &[&str], start: usize) -> Result<Option<(usize, Token)>> {
-    for (i, line) in lines[start..].iter().enumerate() {
-        let mb_token = parse_token(line)
+fn tokenize(lines: &[&str], syntax: &Syntax) -> Result<Vec<(usize, Token)>> {
+    let mut tokens = vec![];
+    for (i, line) in lines.iter().enumerate() {
+        let mb_token = parse_token(line, syntax)
             .with_context(|| format!("failed to parse token on line {}", i + 1))?;
         if let Some(token) = mb_token {
-            return Ok(Some((i + start, token)));
+            tokens.push((i, token));
         }
     }
-    Ok(None)
+    Ok(tokens)
+}
+
+/// Strip the comment from a line of the replacement body, keeping its indentation.
+fn uncomment(line: &str, syntax: &Syntax) -> Option<String> {
+    let code = line.trim_start();
+    if code.is_empty() {
+        return Some(String::new());
+    }
+    let indent = &line[..line.len() - code.len()];
+    let code = code.strip_prefix(syntax.comment)?;
+    Some(format!(
+        "{indent}{}",
+        code.strip_prefix(' ').unwrap_or(code)
+    ))
 }
 
-fn process_source(src: String) -> Result<String> {
+/// Returns `None` if the file has to be skipped altogether. Files without markers are
+/// returned as is, line endings included.
+fn process_source(src: String, syntax: &Syntax) -> Result<Option<String>> {
     let mut dst = String::new();
 
     let lines = src.lines().collect::<Vec<_>>();
+    let tokens = tokenize(&lines, syntax)?;
+    if tokens.is_empty() {
+        return Ok(Some(src));
+    }
+    if tokens
+        .iter()
+        .any(|(_, token)| token.kind == TokenKind::SkipFile)
+    {
+        return Ok(None);
+    }
+
+    let mut tokens = tokens.into_iter();
     let mut next_pos = 0;
-    while let Some((begin, token)) = find_token(&lines, next_pos)? {
+    while let Some((begin, token)) = tokens.next() {
+        for line in &lines[next_pos..begin] {
+            dst += line;
+            dst += "\n";
+        }
+
         let end = match token.kind {
-            TokenKind::EndPrivate => bail!("unpaired 'end_private' on line {}", begin + 1),
+            TokenKind::EndPrivate | TokenKind::With | TokenKind::EndReplace => {
+                bail!("unpaired '{}' on line {}", token.kind.name(), begin + 1)
+            }
+            TokenKind::SkipFile => unreachable!("files with 'skip_file' are not processed"),
             TokenKind::Private => begin + 1,
             TokenKind::BeginPrivate => {
-                let mut pos = begin + 1;
                 let mut mb_end: Option<usize> = None;
-                while let Some((k, token)) = find_token(&lines, pos)? {
+                for (k, token) in tokens.by_ref() {
                     match token.kind {
-                        TokenKind::BeginPrivate => {
-                            bail!("nested 'begin_private' on line {}", k + 1)
-                        }
-                        TokenKind::Private => pos = k + 1,
+                        TokenKind::Private => {}
                         TokenKind::EndPrivate => {
                             mb_end = Some(k);
                             break;
                         }
+                        kind => bail!("unexpected '{}' on line {}", kind.name(), k + 1),
                     }
                 }
                 match mb_end {
@@ -102,14 +198,39 @@ fn process_source(src: String) -> Result<String> {
                     None => bail!("unclosed 'begin_private' on line {}", begin + 1),
                 }
             }
+            TokenKind::BeginReplace => {
+                let with = match tokens.next() {
+                    Some((k, token)) if token.kind == TokenKind::With => k,
+                    Some((k, token)) => {
+                        bail!(
+                            "expected 'with', found '{}' on line {}",
+                            token.kind.name(),
+                            k + 1
+                        )
+                    }
+                    None => bail!("unclosed 'begin_replace' on line {}", begin + 1),
+                };
+                let end = match tokens.next() {
+                    Some((k, token)) if token.kind == TokenKind::EndReplace => k,
+                    Some((k, token)) => bail!(
+                        "expected 'end_replace', found '{}' on line {}",
+                        token.kind.name(),
+                        k + 1
+                    ),
+                    None => bail!("unclosed 'with' on line {}", with + 1),
+                };
+                for (k, line) in lines.iter().enumerate().take(end).skip(with + 1) {
+                    match uncomment(line, syntax) {
+                        Some(code) => dst += &code,
+                        None => bail!("replacement on line {} is not commented out", k + 1),
+                    }
+                    dst += "\n";
+                }
+                next_pos = end + 1;
+                continue;
+            }
         };
 
-        #[allow(clippy::needless_range_loop)]
-        for i in next_pos..begin {
-            dst += lines[i];
-            dst += "\n";
-        }
-
         let no_hint = token.properties.contains(&TokenProperty::NoHint);
         let unimpl = token.properties.contains(&TokenProperty::Unimplemented);
         if no_hint {
@@ -132,11 +253,12 @@ fn process_source(src: String) -> Result<String> {
                     }
                 }
                 dst.push_str(line);
+                dst.push('\n');
             };
 
-            insert_line("// TODO: your code goes here.\n");
+            insert_line(&format!("{} {HINT}", syntax.comment));
             if unimpl {
-                insert_line("unimplemented!()\n");
+                insert_line(syntax.unimplemented.unwrap());
             }
 
             next_pos = end;
@@ -148,21 +270,106 @@ fn process_source(src: String) -> Result<String> {
         dst += "\n";
     }
 
-    Ok(dst)
+    Ok(Some(dst))
+}
+
+/// Whether a Rust source hides some code from students, i.e. the problem is stubbed.
+pub fn hides_code(src: &str) -> Result<bool> {
+    let tokens = tokenize(&src.lines().collect::<Vec<_>>(), &RUST)?;
+    if tokens
+        .iter()
+        .any(|(_, token)| token.kind == TokenKind::SkipFile)
+    {
+        return Ok(false);
+    }
+    Ok(tokens.iter().any(|(_, token)| {
+        matches!(
+            token.kind,
+            TokenKind::Private | TokenKind::BeginPrivate | TokenKind::BeginReplace
+        )
+    }))
+}
+
+/// The content of the output file, `None` if the file is skipped.
+pub fn process_file(input: &Path) -> Result<Option<Content>> {
+    let syntax = match syntax(input) {
+        Some(syntax) => syntax,
+        None => return Ok(Some(Content::Copy(input.to_path_buf()))),
+    };
+    let content =
+        fs::read_to_string(input).with_context(|| format!("failed to read file {:?}", input))?;
+    let new_content = process_source(content, syntax)
+        .with_context(|| format!("failed to process file {:?}", input))?;
+    Ok(new_content.map(|content| Content::Processed {
+        source: input.to_path_buf(),
+        bytes: content.into_bytes(),
+    }))
 }
 
-pub fn process_file(input: &Path, output: &Path) -> Result<()> {
-    let out_dir = output.parent().unwrap();
-    fs::create_dir_all(out_dir).context("failed to create directory")?;
-    if input.to_str().map(|s| s.ends_with(".rs")).unwrap_or(false) {
-        let content = fs::read_to_string(input)
-            .with_context(|| format!("failed to read file {:?}", input))?;
-        let new_content = process_source(content)
-            .with_context(|| format!("failed to process file {:?}", input))?;
-        fs::write(output, new_content).with_context(|| format!("failed to write file {:?}", input))
-    } else {
-        fs::copy(input, output)
-            .map(|_| ())
-            .with_context(|| format!("failed to copy {:?} to {:?}", input, output))
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(src: &str, syntax: &Syntax) -> Result<Option<String>> {
+        process_source(src.to_string(), syntax)
+    }
+
+    #[test]
+    fn private() {
+        let src = "fn f() -> i32 {\n    // compose::begin_private(unimplemented)\n    42\n    // compose::end_private\n}\n";
+        assert_eq!(
+            process(src, &RUST).unwrap().unwrap(),
+            "fn f() -> i32 {\n    // TODO: your code goes here.\n    unimplemented!()\n}\n"
+        );
+        let src = "a\nb // compose::private\nc\n";
+        assert_eq!(
+            process(src, &RUST).unwrap().unwrap(),
+            "a\n// TODO: your code goes here.\nc\n"
+        );
+    }
+
+    #[test]
+    fn replace() {
+        let src = "fn f() -> i32 {\n    // compose::begin_replace\n    42\n    // compose::with\n    // todo!()\n\n    //0\n    // compose::end_replace\n}\n";
+        assert_eq!(
+            process(src, &RUST).unwrap().unwrap(),
+            "fn f() -> i32 {\n    todo!()\n\n    0\n}\n"
+        );
+        let src = "// compose::begin_replace\nx\n// compose::with\ny\n// compose::end_replace\n";
+        assert!(process(src, &RUST).is_err());
+        let src = "// compose::begin_replace\nx\n// compose::end_replace\n";
+        assert!(process(src, &RUST).is_err());
+    }
+
+    #[test]
+    fn hash_comments() {
+        let src =
+            "[dependencies]\n# compose::begin_private\nrayon = \"1\"\n# compose::end_private\n";
+        assert_eq!(
+            process(src, &HASH).unwrap().unwrap(),
+            "[dependencies]\n# TODO: your code goes here.\n"
+        );
+        let src = "# compose::private(unimplemented)\n";
+        assert!(process(src, &HASH).is_err());
+    }
+
+    #[test]
+    fn skip_file() {
+        let src = "# compose::skip_file\nprint(42)\n";
+        assert!(process(src, &HASH).unwrap().is_none());
+    }
+
+    #[test]
+    fn no_markers() {
+        let src = "[package]\r\nname = \"x\"";
+        assert_eq!(process(src, &HASH).unwrap().unwrap(), src);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(process("// compose::end_private\n", &RUST).is_err());
+        assert!(process("// compose::begin_private\n", &RUST).is_err());
+        assert!(process("// compose::unknown\n", &RUST).is_err());
+        assert!(process("// compose::with(no_hint)\n", &RUST).is_err());
     }
 }
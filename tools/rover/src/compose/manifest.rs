@@ -0,0 +1,193 @@
+use anyhow::{Context, Result};
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    fs::{self, Permissions},
+    io,
+    path::{Path, PathBuf},
+};
+
+/// What an output file consists of.
+pub enum Content {
+    /// Copied from the source as is.
+    Copy(PathBuf),
+    /// Source with compose markers processed, keeps the permissions of the source.
+    Processed { source: PathBuf, bytes: Vec<u8> },
+    /// Made up by compose itself.
+    Generated(Vec<u8>),
+}
+
+/// Every file of the output, keyed by the path relative to the output directory. It's
+/// built anew on each run and compared with the files already in the output directory.
+#[derive(Default)]
+pub struct Manifest {
+    entries: BTreeMap<PathBuf, Content>,
+}
+
+/// Files changed by writing the manifest, relative to the output directory.
+#[derive(Default)]
+pub struct Changes {
+    pub added: Vec<PathBuf>,
+    pub updated: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+    pub unchanged: usize,
+}
+
+impl Manifest {
+    pub fn insert(&mut self, path: PathBuf, content: Content) {
+        self.entries.insert(path, content);
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.entries.contains_key(path)
+    }
+
+    /// Drop the entries for which `keep` returns false.
+    pub fn retain(&mut self, mut keep: impl FnMut(&Path) -> bool) {
+        self.entries.retain(|path, _| keep(path));
+    }
+
+    /// Write the files whose content differs from the one in `output`, the others are
+    /// left untouched along with their timestamps.
+    pub fn write(&self, output: &Path, changes: &mut Changes) -> Result<()> {
+        for (path, content) in &self.entries {
+            let destination = output.join(path);
+            let old = file_state(&destination)?;
+            let (new_bytes, new_permissions) = match content {
+                Content::Copy(source) => {
+                    let (bytes, permissions) = file_state(source)?
+                        .with_context(|| format!("failed to read file {source:?}"))?;
+                    (Cow::Owned(bytes), Some(permissions))
+                }
+                Content::Processed { source, bytes } => {
+                    (Cow::Borrowed(bytes), Some(permissions(source)?))
+                }
+                // Generated files keep the permissions they have.
+                Content::Generated(bytes) => (Cow::Borrowed(bytes), None),
+            };
+            let unchanged = old.as_ref().is_some_and(|(bytes, permissions)| {
+                *bytes == *new_bytes
+                    && new_permissions
+                        .as_ref()
+                        .is_none_or(|new| new == permissions)
+            });
+            if unchanged {
+                changes.unchanged += 1;
+                continue;
+            }
+            if let Some(dir) = destination.parent() {
+                fs::create_dir_all(dir)
+                    .with_context(|| format!("failed to create directory {dir:?}"))?;
+            }
+            match content {
+                Content::Copy(source) => fs::copy(source, &destination)
+                    .map(|_| ())
+                    .with_context(|| format!("failed to copy {source:?} to {destination:?}"))?,
+                Content::Processed { source, bytes } => {
+                    fs::write(&destination, bytes)
+                        .with_context(|| format!("failed to write file {destination:?}"))?;
+                    fs::set_permissions(&destination, permissions(source)?)
+                        .with_context(|| format!("failed to set permissions of {destination:?}"))?;
+                }
+                Content::Generated(bytes) => fs::write(&destination, bytes)
+                    .with_context(|| format!("failed to write file {destination:?}"))?,
+            }
+            if old.is_some() {
+                changes.updated.push(path.clone());
+            } else {
+                changes.added.push(path.clone());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Changes {
+    pub fn print(&self) {
+        for (mark, paths) in [
+            ("A", &self.added),
+            ("M", &self.updated),
+            ("D", &self.removed),
+        ] {
+            for path in paths {
+                println!("{mark} {}", path.display());
+            }
+        }
+        println!(
+            "{} added, {} updated, {} removed, {} unchanged",
+            self.added.len(),
+            self.updated.len(),
+            self.removed.len(),
+            self.unchanged
+        );
+    }
+}
+
+/// The content and the permissions of the file, `None` if there's no such file.
+fn file_state(path: &Path) -> Result<Option<(Vec<u8>, Permissions)>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some((bytes, permissions(path)?))),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).with_context(|| format!("failed to read file {path:?}")),
+    }
+}
+
+fn permissions(path: &Path) -> Result<Permissions> {
+    Ok(fs::metadata(path)
+        .with_context(|| format!("failed to read metadata of {path:?}"))?
+        .permissions())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn permission_changes_are_written() -> Result<()> {
+        let input = tempfile::tempdir()?;
+        let output = tempfile::tempdir()?;
+        let script = input.path().join("test.py");
+        fs::write(&script, "print(42)\n")?;
+        let mut manifest = Manifest::default();
+        manifest.insert(PathBuf::from("copy.py"), Content::Copy(script.clone()));
+        let processed = Content::Processed {
+            source: script.clone(),
+            bytes: b"print(0)\n".to_vec(),
+        };
+        manifest.insert(PathBuf::from("processed.py"), processed);
+        manifest.insert(
+            PathBuf::from("generated.txt"),
+            Content::Generated(b"x".to_vec()),
+        );
+
+        let write = || -> Result<Changes> {
+            let mut changes = Changes::default();
+            manifest.write(output.path(), &mut changes)?;
+            Ok(changes)
+        };
+        assert_eq!(write()?.added.len(), 3);
+        assert_eq!(write()?.unchanged, 3);
+
+        fs::set_permissions(&script, Permissions::from_mode(0o755))?;
+        let changes = write()?;
+        assert_eq!(
+            changes.updated,
+            [Path::new("copy.py"), Path::new("processed.py")]
+        );
+        assert_eq!(changes.unchanged, 1);
+        for file in ["copy.py", "processed.py"] {
+            let mode = fs::metadata(output.path().join(file))?.permissions().mode();
+            assert_eq!(mode & 0o777, 0o755);
+        }
+        assert_eq!(write()?.unchanged, 3);
+
+        fs::write(&script, "print(43)\n")?;
+        assert_eq!(write()?.updated, [Path::new("copy.py")]);
+        assert_eq!(fs::read(output.path().join("copy.py"))?, b"print(43)\n");
+        Ok(())
+    }
+}
@@ -2,7 +2,9 @@ mod cargo_root;
 pub mod config;
 mod dir;
 mod file;
+mod manifest;
 mod process;
 mod prune;
 pub mod run_compose;
 mod skip;
+mod verify;
@@ -1,8 +1,8 @@
-use super::{config::Config, dir::process_dir, file::process_file};
+use super::{config::Config, dir::process_dir, file::process_file, manifest::Manifest};
 use anyhow::Result;
 use std::path::{Path, PathBuf};
 
-pub fn process(input: &Path, output: &Path, config: &Config) -> Result<()> {
+pub fn process(input: &Path, config: &Config, manifest: &mut Manifest) -> Result<()> {
     let to_process = std::iter::empty()
         .chain(
             config
@@ -19,11 +19,10 @@ pub fn process(input: &Path, output: &Path, config: &Config) -> Result<()> {
         .chain(config.get_copy().iter().cloned());
     for entry in to_process {
         let input = input.join(&entry);
-        let output = output.join(&entry);
         if input.is_dir() {
-            process_dir(&input, &output)?;
-        } else {
-            process_file(&input, &output)?;
+            process_dir(&input, &entry, manifest)?;
+        } else if let Some(content) = process_file(&input)? {
+            manifest.insert(entry, content);
         }
     }
     Ok(())
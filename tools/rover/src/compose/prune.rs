@@ -1,4 +1,4 @@
-use super::config::Config;
+use super::{config::Config, manifest::Manifest};
 use anyhow::{Context, Result};
 use std::{
     collections::HashSet,
@@ -6,25 +6,55 @@ use std::{
     path::{Path, PathBuf},
 };
 
-pub fn prune(output: &Path, config: &Config) -> Result<()> {
-    let dir = fs::read_dir(output);
-    if dir.is_err() {
+/// Remove the files of the output that are not in the manifest, along with the
+/// directories left empty. The removed files are added to `removed`.
+pub fn prune(
+    output: &Path,
+    config: &Config,
+    manifest: &Manifest,
+    removed: &mut Vec<PathBuf>,
+) -> Result<()> {
+    if !output.is_dir() {
         return Ok(());
     }
-    let dir = dir.unwrap();
     let do_not_delete: HashSet<_> = config.get_do_not_delete().iter().collect();
-    let output_len = output.iter().count();
+    let dir = fs::read_dir(output).with_context(|| format!("failed to read {output:?}"))?;
     for entry in dir {
         let path = entry.context("cannot read entry")?.path();
-        let relative: PathBuf = path.iter().skip(output_len).collect();
+        let relative = PathBuf::from(path.file_name().unwrap());
         if !do_not_delete.contains(&relative) {
-            if path.is_dir() {
-                fs::remove_dir_all(&path)
-            } else {
-                fs::remove_file(&path)
-            }
-            .with_context(|| format!("failed to remove {path:?}"))?;
+            prune_entry(&path, &relative, manifest, removed)?;
         }
     }
     Ok(())
 }
+
+/// Returns true if the entry was removed completely.
+fn prune_entry(
+    path: &Path,
+    relative: &Path,
+    manifest: &Manifest,
+    removed: &mut Vec<PathBuf>,
+) -> Result<bool> {
+    let metadata =
+        fs::symlink_metadata(path).with_context(|| format!("failed to read {path:?}"))?;
+    if !metadata.is_dir() {
+        if manifest.contains(relative) {
+            return Ok(false);
+        }
+        fs::remove_file(path).with_context(|| format!("failed to remove {path:?}"))?;
+        removed.push(relative.to_path_buf());
+        return Ok(true);
+    }
+    let mut empty = true;
+    let dir = fs::read_dir(path).with_context(|| format!("failed to read {path:?}"))?;
+    for entry in dir {
+        let entry = entry.context("cannot read entry")?.path();
+        let relative = relative.join(entry.file_name().unwrap());
+        empty &= prune_entry(&entry, &relative, manifest, removed)?;
+    }
+    if empty {
+        fs::remove_dir(path).with_context(|| format!("failed to remove {path:?}"))?;
+    }
+    Ok(empty)
+}
@@ -1,9 +1,16 @@
-use super::{cargo_root::cargo_root, process::process, prune::prune, skip::skip};
+use super::{
+    cargo_root::cargo_root,
+    manifest::{Changes, Manifest},
+    process::process,
+    prune::prune,
+    skip::skip,
+    verify::verify,
+};
 use crate::repository::repo::Repository;
 use anyhow::{Context, Result};
 use std::path::Path;
 
-pub fn run_compose(input: &Path, output: &Path) -> Result<()> {
+pub fn run_compose(input: &Path, output: &Path, verify_output: bool) -> Result<()> {
     let repository = Repository::from_path(input)?;
     let config = repository.compose_config()?;
     let input = repository.get_path().to_path_buf();
@@ -17,8 +24,16 @@ pub fn run_compose(input: &Path, output: &Path) -> Result<()> {
                 .file_name()
                 .context("output path has no file name to canonicalize")?,
         );
-    prune(&output, &config)?;
-    process(&input, &output, &config)?;
-    cargo_root(&output, &config)?;
-    skip(&output, &config)
+    let mut manifest = Manifest::default();
+    process(&input, &config, &mut manifest)?;
+    cargo_root(&input, &config, &mut manifest)?;
+    skip(&mut manifest, &config);
+    let mut changes = Changes::default();
+    prune(&output, &config, &manifest, &mut changes.removed)?;
+    manifest.write(&output, &mut changes)?;
+    changes.print();
+    if verify_output {
+        verify(&input, &output, &config)?;
+    }
+    Ok(())
 }
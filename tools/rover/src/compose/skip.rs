@@ -1,16 +1,7 @@
-use super::config::Config;
-use anyhow::{Context, Result};
-use std::{fs, path::Path};
+use super::{config::Config, manifest::Manifest};
 
-pub fn skip(output: &Path, config: &Config) -> Result<()> {
-    for entry in config.get_skipped() {
-        let path = output.join(entry);
-        if path.is_dir() {
-            fs::remove_dir_all(&path)
-        } else {
-            fs::remove_file(&path)
-        }
-        .with_context(|| format!("failed to remove {path:?}"))?;
-    }
-    Ok(())
+/// Leave the entries to skip out of the output.
+pub fn skip(manifest: &mut Manifest, config: &Config) {
+    let skipped = config.get_skipped();
+    manifest.retain(|path| !skipped.iter().any(|entry| path.starts_with(entry)));
 }
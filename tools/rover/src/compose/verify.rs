@@ -0,0 +1,181 @@
+use super::{config::Config, file::hides_code};
+use anyhow::{bail, Context, Result};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{self, Output},
+};
+
+const TAIL_LINES: usize = 20;
+
+enum Verdict {
+    /// Stubbed problem compiles and its tests fail.
+    Stubbed,
+    /// Problem without compose markers compiles.
+    Compiles,
+    /// Stubbed problem passes its tests, the solution is probably leaked.
+    Leaked,
+    Broken(String),
+}
+
+/// Check that the composed repository compiles, and that the tests of every problem
+/// with compose markers fail, i.e. the solution was hidden.
+pub fn verify(input: &Path, output: &Path, config: &Config) -> Result<()> {
+    // Build artifacts must not end up in the public repository.
+    let target_dir = env::temp_dir().join("rover-compose-verify");
+    let had_lock = output.join("Cargo.lock").exists();
+
+    let mut failures = vec![];
+    println!("checking workspace...");
+    let check = cargo(
+        output,
+        &target_dir,
+        &["check", "--workspace", "--all-targets"],
+    )?;
+    if !check.status.success() {
+        println!("workspace: does not compile\n{}", tail(&check.stderr));
+        failures.push("workspace".to_string());
+    }
+
+    for problem in config.get_problems() {
+        let path = PathBuf::from("problems").join(problem);
+        let stubbed = has_markers(&input.join(&path), &output.join(&path))?;
+        let verdict = verify_problem(&output.join(&path), &target_dir, stubbed)?;
+        let name = problem.display();
+        match verdict {
+            Verdict::Stubbed => println!("{name}: ok, tests fail"),
+            Verdict::Compiles => println!("{name}: ok, no compose markers"),
+            Verdict::Leaked => {
+                println!("{name}: tests pass, the solution may be leaked");
+                failures.push(name.to_string());
+            }
+            Verdict::Broken(stderr) => {
+                println!("{name}: does not compile\n{stderr}");
+                failures.push(name.to_string());
+            }
+        }
+    }
+
+    if !had_lock {
+        let _ = fs::remove_file(output.join("Cargo.lock"));
+    }
+    if !failures.is_empty() {
+        bail!(
+            "verification failed for {} entries: {}",
+            failures.len(),
+            failures.join(", ")
+        );
+    }
+    Ok(())
+}
+
+fn verify_problem(path: &Path, target_dir: &Path, stubbed: bool) -> Result<Verdict> {
+    let manifest = path.join("Cargo.toml");
+    let manifest = manifest.to_str().context("non-utf-8 path")?;
+    let build = cargo(
+        path,
+        target_dir,
+        &["test", "--no-run", "--manifest-path", manifest],
+    )?;
+    if !build.status.success() {
+        return Ok(Verdict::Broken(tail(&build.stderr)));
+    }
+    if !stubbed {
+        return Ok(Verdict::Compiles);
+    }
+    let test = cargo(path, target_dir, &["test", "--manifest-path", manifest])?;
+    if test.status.success() {
+        Ok(Verdict::Leaked)
+    } else {
+        Ok(Verdict::Stubbed)
+    }
+}
+
+fn cargo(workdir: &Path, target_dir: &Path, args: &[&str]) -> Result<Output> {
+    process::Command::new("cargo")
+        .args(args)
+        .current_dir(workdir)
+        .env("CARGO_TARGET_DIR", target_dir)
+        .output()
+        .with_context(|| format!("failed to launch cargo {}", args.join(" ")))
+}
+
+/// Whether some Rust source of the problem that ends up in `output` hides code.
+fn has_markers(input: &Path, output: &Path) -> Result<bool> {
+    for entry in
+        fs::read_dir(input).with_context(|| format!("failed to read directory {input:?}"))?
+    {
+        let path = entry
+            .with_context(|| format!("failed to read entry in directory {input:?}"))?
+            .path();
+        let name = path.file_name().context("entry has no file name")?;
+        let destination = output.join(name);
+        if !destination.exists() {
+            continue;
+        }
+        if path.is_dir() {
+            if has_markers(&path, &destination)? {
+                return Ok(true);
+            }
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("failed to read file {path:?}"))?;
+            if hides_code(&content).with_context(|| format!("failed to process file {path:?}"))? {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+fn tail(stderr: &[u8]) -> String {
+    let stderr = String::from_utf8_lossy(stderr);
+    let lines: Vec<_> = stderr.lines().collect();
+    lines[lines.len().saturating_sub(TAIL_LINES)..].join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markers() -> Result<()> {
+        let input = tempfile::tempdir()?;
+        let output = tempfile::tempdir()?;
+        let write = |dir: &Path, path: &str, content: &str| -> Result<()> {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap())?;
+            Ok(fs::write(path, content)?)
+        };
+
+        write(
+            input.path(),
+            "README.md",
+            "Use `// compose::private` to hide a line.\n",
+        )?;
+        write(
+            input.path(),
+            "Cargo.toml",
+            "# compose::begin_private\nrayon = \"1\"\n# compose::end_private\n",
+        )?;
+        write(input.path(), "src/lib.rs", "pub fn f() {}\n")?;
+        write(
+            input.path(),
+            "tests/tests.rs",
+            "// compose::skip_file\nfn main() {} // compose::private\n",
+        )?;
+        write(
+            input.path(),
+            "src/solution.rs",
+            "fn g() {} // compose::private\n",
+        )?;
+        for path in ["README.md", "Cargo.toml", "src/lib.rs", "tests/tests.rs"] {
+            write(output.path(), path, "")?;
+        }
+        assert!(!has_markers(input.path(), output.path())?);
+
+        write(output.path(), "src/solution.rs", "")?;
+        assert!(has_markers(input.path(), output.path())?);
+        Ok(())
+    }
+}
@@ -1,13 +1,25 @@
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use clap::{Arg, Command};
 use compose::run_compose::run_compose;
-use repository::repo::Repository;
-use std::path::PathBuf;
-use submitting::submit::submit_problem;
-use testing::{report::ReportType, test::test_problem};
+use glob::Pattern;
+use repository::{check::check_configs, limits, repo::Repository};
+use scaffold::new::{new_problem, TEMPLATES};
+use std::{
+    path::{Path, PathBuf},
+    thread,
+};
+use submitting::{status::print_status, submit::submit_problem};
+use testing::{
+    batch::{print_matrix, test_all, BatchOptions},
+    report::{reporter_from_name, ReporterOptions},
+    results::{ReportFormat, TestReport},
+    test::{test_problem, TestOptions},
+    watch::watch_problem,
+};
 
 mod compose;
 mod repository;
+mod scaffold;
 mod submitting;
 mod testing;
 mod util;
@@ -42,6 +54,74 @@ fn main() -> Result<()> {
                         .required(false)
                         .takes_value(true)
                 )
+                .arg(
+                    Arg::new("dry-run")
+                        .long("dry-run")
+                        .help("Show the diff with the last submission instead of submitting")
+                        .required(false)
+                        .takes_value(false)
+                )
+        )
+        .subcommand(
+            Command::new("status")
+                .about("Show which problems are untouched, modified, committed or pushed")
+                .arg(
+                    Arg::new("path")
+                        .long("path")
+                        .help("Path within the course repository")
+                        .required(false)
+                        .default_value(".")
+                        .hide_default_value(true)
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::new("solutions-repo")
+                        .long("solutions-repo")
+                        .help("Path to the solutions repository")
+                        .required(false)
+                        .takes_value(true)
+                )
+        )
+        .subcommand(
+            Command::new("new")
+                .about("Create a problem from a template and register it in the workspace")
+                .arg(
+                    Arg::new("problem")
+                        .help("The problem as GROUP/TITLE")
+                        .required(true)
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::new("template")
+                        .long("template")
+                        .help("Kind of the problem crate")
+                        .required(false)
+                        .possible_values(TEMPLATES)
+                        .default_value("lib")
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::new("path")
+                        .long("path")
+                        .help("Path within the course repository")
+                        .required(false)
+                        .default_value(".")
+                        .hide_default_value(true)
+                        .takes_value(true)
+                )
+        )
+        .subcommand(
+            Command::new("check-config")
+                .about("Validate the config of every problem and the compose config")
+                .arg(
+                    Arg::new("path")
+                        .long("path")
+                        .help("Path within the course repository")
+                        .required(false)
+                        .default_value(".")
+                        .hide_default_value(true)
+                        .takes_value(true)
+                )
         )
         .subcommand(
             Command::new("test")
@@ -79,6 +159,105 @@ fn main() -> Result<()> {
                         .default_value("no-report")
                         .takes_value(true)
                 )
+                .arg(
+                    Arg::new("report-url")
+                        .long("report-url")
+                        .help("URL to post the results to with \"webhook\" reporter")
+                        .required(false)
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::new("report-header")
+                        .long("report-header")
+                        .help("Additional \"Name: value\" header of \"webhook\" reporter requests")
+                        .required(false)
+                        .multiple_occurrences(true)
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::new("report-template")
+                        .long("report-template")
+                        .help("Path to the JSON body template of \"webhook\" reporter requests")
+                        .required(false)
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::new("report-jsonl-file")
+                        .long("report-jsonl-file")
+                        .help("Path to the file that \"jsonl\" reporter appends the results to")
+                        .required(false)
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::new("report-file")
+                        .long("report-file")
+                        .help("Path to the file to write per-step results of testing to")
+                        .required(false)
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::new("report-format")
+                        .long("report-format")
+                        .help("Format of the report file: \"json\" or \"junit\", guessed from the file extension by default")
+                        .required(false)
+                        .requires("report-file")
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::new("all")
+                        .long("all")
+                        .help("Test every problem of the course repository containing the path")
+                        .required(false)
+                        .conflicts_with("move-files")
+                        .takes_value(false)
+                )
+                .arg(
+                    Arg::new("jobs")
+                        .long("jobs")
+                        .help("Number of problems tested at once with --all, number of CPUs by default")
+                        .required(false)
+                        .requires("all")
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::new("group")
+                        .long("group")
+                        .help("Test only problems of the group with --all, may be repeated")
+                        .required(false)
+                        .requires("all")
+                        .multiple_occurrences(true)
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::new("filter")
+                        .long("filter")
+                        .help("Test only problems whose \"GROUP/TITLE\" matches the glob pattern with --all")
+                        .required(false)
+                        .requires("all")
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::new("hidden-tests")
+                        .long("hidden-tests")
+                        .help("Directory with hidden tests of the problems in GROUP/TITLE subdirectories")
+                        .required(false)
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::new("watch")
+                        .long("watch")
+                        .help("Test the problem again each time its allowed files change")
+                        .required(false)
+                        .conflicts_with("all")
+                        .takes_value(false)
+                )
+                .arg(
+                    Arg::new("keep-going")
+                        .long("keep-going")
+                        .help("Continue testing after a failed command instead of skipping the rest")
+                        .required(false)
+                        .takes_value(false)
+                )
         )
         .subcommand(
             Command::new("compose")
@@ -98,6 +277,13 @@ fn main() -> Result<()> {
                         .required(true)
                         .takes_value(true)
                 )
+                .arg(
+                    Arg::new("verify")
+                        .long("verify")
+                        .help("Check that the output compiles and the tests of stubbed problems fail")
+                        .required(false)
+                        .takes_value(false)
+                )
         )
         .arg_required_else_help(true)
         .get_matches();
@@ -107,27 +293,115 @@ fn main() -> Result<()> {
             let path: PathBuf = submit_matches.value_of("path").unwrap().into();
             let message = submit_matches.value_of("message").unwrap();
             let solutions_repo = submit_matches.value_of("solutions-repo").map(PathBuf::from);
-            submit_problem(&path, message, solutions_repo)
+            let dry_run = submit_matches.is_present("dry-run");
+            submit_problem(&path, message, solutions_repo, dry_run)
+        }
+        Some(("status", status_matches)) => {
+            let path: PathBuf = status_matches.value_of("path").unwrap().into();
+            let solutions_repo = status_matches.value_of("solutions-repo").map(PathBuf::from);
+            print_status(&path, solutions_repo)
+        }
+        Some(("new", new_matches)) => {
+            let path: PathBuf = new_matches.value_of("path").unwrap().into();
+            new_problem(
+                &path,
+                new_matches.value_of("problem").unwrap(),
+                new_matches.value_of("template").unwrap(),
+            )
+        }
+        Some(("check-config", check_matches)) => {
+            let path: PathBuf = check_matches.value_of("path").unwrap().into();
+            check_configs(&path)
         }
         Some(("test", test_matches)) => {
+            // Commands are killed on Ctrl+C, so that hidden tests are removed before exiting.
+            limits::handle_interrupts();
             let path: PathBuf = test_matches.value_of("path").unwrap().into();
             let repository = Repository::from_path(&path)?;
+            let reporter = reporter_from_name(
+                test_matches.value_of("report-to").unwrap(),
+                ReporterOptions {
+                    url: test_matches.value_of("report-url").map(str::to_string),
+                    headers: test_matches
+                        .values_of("report-header")
+                        .map(|values| values.map(str::to_string).collect())
+                        .unwrap_or_default(),
+                    template: test_matches.value_of("report-template").map(PathBuf::from),
+                    file: test_matches
+                        .value_of("report-jsonl-file")
+                        .map(PathBuf::from),
+                },
+            )?;
+            let report_format = |report_file: &Path| match test_matches.value_of("report-format") {
+                Some(name) => ReportFormat::from_name(name),
+                None => Ok(ReportFormat::from_path(report_file)),
+            };
+            let options = TestOptions {
+                keep_going: test_matches.is_present("keep-going"),
+                echo: !test_matches.is_present("all"),
+                cancel: None,
+                hidden_tests: test_matches.value_of("hidden-tests").map(PathBuf::from),
+                target_dir: None,
+            };
+            if test_matches.is_present("all") {
+                let jobs = match test_matches.value_of("jobs") {
+                    Some(jobs) => jobs.parse().context("--jobs is not a number")?,
+                    None => thread::available_parallelism().map_or(1, |jobs| jobs.get()),
+                };
+                let batch_options = BatchOptions {
+                    jobs,
+                    groups: test_matches
+                        .values_of("group")
+                        .map(|values| values.map(str::to_string).collect())
+                        .unwrap_or_default(),
+                    filter: test_matches
+                        .value_of("filter")
+                        .map(Pattern::new)
+                        .transpose()
+                        .context("--filter is not a valid glob pattern")?,
+                    test: options,
+                };
+                let test_reports = test_all(&repository, &batch_options)?;
+                print_matrix(&test_reports);
+                if let Some(report_file) = test_matches.value_of("report-file") {
+                    let report_file = PathBuf::from(report_file);
+                    let format = report_format(&report_file)?;
+                    TestReport::write_all_to_file(&test_reports, &report_file, format)?;
+                }
+                for test_report in &test_reports {
+                    reporter.push_report(test_report)?;
+                }
+                if test_reports.iter().any(TestReport::failed) {
+                    bail!("testing failed")
+                }
+                return Ok(());
+            }
             let problem = repository.problem_from_path(&path)?;
-            let report = ReportType::from_name(test_matches.value_of("report-to").unwrap())?;
             if let Some(solutions_repo) = test_matches.value_of("move-files") {
                 let checkout_branch = test_matches.value_of("checkout-branch").is_some();
                 let solutions_repo: PathBuf = solutions_repo.into();
                 problem.move_solution_files_from(&solutions_repo, checkout_branch)?;
             }
-            // TODO: Make testing errors more clear
-            let testing_result = test_problem(problem);
-            let report_push = report.push_report(testing_result.is_err());
-            testing_result.and(report_push)
+            if test_matches.is_present("watch") {
+                return watch_problem(&problem, &options);
+            }
+            let test_report = test_problem(&problem, &options)?;
+            test_report.print_summary();
+            if let Some(report_file) = test_matches.value_of("report-file") {
+                let report_file = PathBuf::from(report_file);
+                let format = report_format(&report_file)?;
+                test_report.write_to_file(&report_file, format)?;
+            }
+            let report_push = reporter.push_report(&test_report);
+            if test_report.failed() {
+                bail!("testing failed")
+            }
+            report_push
         }
         Some(("compose", compose_matches)) => {
             let input: PathBuf = compose_matches.value_of("input").unwrap().into();
             let output: PathBuf = compose_matches.value_of("output").unwrap().into();
-            run_compose(&input, &output)
+            run_compose(&input, &output, compose_matches.is_present("verify"))
         }
         _ => unreachable!(),
     }
@@ -0,0 +1,255 @@
+use super::{
+    capture::OutputTail,
+    context::CommandContext,
+    yml::{Ordered, Scalar},
+};
+use anyhow::{bail, Context, Result};
+use serde::{de, Deserialize, Deserializer};
+use std::{fs::File, path::PathBuf};
+
+const DEFAULT_BASELINE: &str = ".bench-baseline.yml";
+const DEFAULT_MAX_SLOWDOWN: f64 = 2.;
+
+/// Where `cargo-bench` takes the baseline from and how much slower a solution may be.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "BenchYml")]
+pub struct BenchPolicy {
+    baseline: PathBuf,
+    max_slowdown: f64,
+}
+
+/// The `bench` section of the config.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct BenchYml {
+    baseline: Option<PathBuf>,
+    max_slowdown: Option<f64>,
+}
+
+/// Time of one iteration in nanoseconds, as written in the baseline.
+struct Nanos(f64);
+
+/// A benchmark found in the output of `cargo bench`.
+#[derive(Debug, PartialEq)]
+struct Measurement {
+    name: String,
+    nanos: f64,
+}
+
+impl Default for BenchPolicy {
+    fn default() -> Self {
+        Self {
+            baseline: PathBuf::from(DEFAULT_BASELINE),
+            max_slowdown: DEFAULT_MAX_SLOWDOWN,
+        }
+    }
+}
+
+impl TryFrom<BenchYml> for BenchPolicy {
+    type Error = String;
+
+    fn try_from(yml: BenchYml) -> Result<Self, Self::Error> {
+        let max_slowdown = yml.max_slowdown.unwrap_or(DEFAULT_MAX_SLOWDOWN);
+        if max_slowdown.is_nan() || max_slowdown < 1. {
+            return Err(format!(
+                "max-slowdown must be at least 1, got {max_slowdown}"
+            ));
+        }
+        Ok(Self {
+            baseline: yml
+                .baseline
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_BASELINE)),
+            max_slowdown,
+        })
+    }
+}
+
+/// Either a number of nanoseconds or a number with `ps`, `ns`, `us`, `ms` or `s` suffix.
+impl<'de> Deserialize<'de> for Nanos {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let Scalar(value) = Scalar::deserialize(deserializer)?;
+        parse_time(&value)
+            .map(Nanos)
+            .map_err(|err| de::Error::custom(format!("invalid bench time: {err:#}")))
+    }
+}
+
+impl BenchPolicy {
+    /// Compare the benchmarks of `cargo bench` output with the baseline. The benchmarks
+    /// absent in the baseline are only reported.
+    pub fn check(&self, context: &CommandContext, output: &mut OutputTail) -> Result<()> {
+        let measurements = parse_output(&output.take_full_stdout());
+        let path = context.get_workdir().join(&self.baseline);
+        if !path.exists() {
+            output.println(format!("# {}", self.baseline.display()));
+            for Measurement { name, nanos } in &measurements {
+                output.println(format!("{name}: {}", format_time(*nanos)));
+            }
+            bail!(
+                "no baseline {}, the measured times above may be saved to it",
+                self.baseline.display()
+            );
+        }
+        let file =
+            File::open(&path).with_context(|| format!("failed to open baseline {path:?}"))?;
+        let baseline: Ordered<Nanos> = serde_yaml::from_reader(file)
+            .with_context(|| format!("invalid baseline {}", path.display()))?;
+
+        let mut regressions = Vec::new();
+        for (name, Nanos(expected)) in &baseline.0 {
+            let Some(measured) = measurements.iter().find(|m| &m.name == name) else {
+                output.println(format!("{name}: not found in the output"));
+                regressions.push(name.as_str());
+                continue;
+            };
+            let slowdown = measured.nanos / expected;
+            let verdict = if slowdown > self.max_slowdown {
+                regressions.push(name.as_str());
+                format!(", slower than allowed {}x", self.max_slowdown)
+            } else {
+                String::new()
+            };
+            output.println(format!(
+                "{name}: {}, baseline {}, {slowdown:.2}x{verdict}",
+                format_time(measured.nanos),
+                format_time(*expected),
+            ));
+        }
+        for Measurement { name, nanos } in &measurements {
+            if !baseline.0.iter().any(|(key, _)| key == name) {
+                output.println(format!("{name}: {}, no baseline", format_time(*nanos)));
+            }
+        }
+        if !regressions.is_empty() {
+            bail!(
+                "{} of {} benchmarks regressed: {}",
+                regressions.len(),
+                baseline.0.len(),
+                regressions.join(", ")
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Find the benchmarks in the output of libtest (`test NAME ... bench: N ns/iter`)
+/// and criterion (`NAME time: [LOW ESTIMATE HIGH]`, the name may be on the line above).
+fn parse_output(stdout: &str) -> Vec<Measurement> {
+    let mut measurements = Vec::new();
+    let mut last_name = None;
+    for line in stdout.lines() {
+        if let Some((name, time)) = line
+            .strip_prefix("test ")
+            .and_then(|rest| rest.split_once(" ... bench:"))
+        {
+            let nanos = time
+                .split_whitespace()
+                .next()
+                .and_then(|number| number.replace(',', "").parse().ok());
+            if let Some(nanos) = nanos {
+                measurements.push(Measurement {
+                    name: name.trim().to_string(),
+                    nanos,
+                });
+            }
+            continue;
+        }
+        let Some((name, time)) = line.split_once("time:") else {
+            if !line.is_empty() && !line.starts_with(char::is_whitespace) {
+                last_name = Some(line.trim().to_string());
+            }
+            continue;
+        };
+        let name = match name.trim() {
+            "" => last_name.take(),
+            name => Some(name.to_string()),
+        };
+        // Lines of criterion's change report have percents instead of times.
+        let estimate = time
+            .trim()
+            .strip_prefix('[')
+            .and_then(|time| time.strip_suffix(']'))
+            .and_then(|time| {
+                let parts: Vec<_> = time.split_whitespace().collect();
+                (parts.len() == 6).then(|| format!("{}{}", parts[2], parts[3]))
+            })
+            .and_then(|estimate| parse_time(&estimate).ok());
+        if let (Some(name), Some(nanos)) = (name, estimate) {
+            measurements.push(Measurement { name, nanos });
+        }
+    }
+    measurements
+}
+
+fn parse_time(value: &str) -> Result<f64> {
+    let (number, unit) = value.split_at(
+        value.len()
+            - value
+                .trim_start_matches(|c: char| c.is_ascii_digit() || c == '.')
+                .len(),
+    );
+    let number: f64 = number
+        .parse()
+        .with_context(|| format!("time \"{value}\" does not start with a number"))?;
+    Ok(number
+        * match unit.trim() {
+            "ps" => 1e-3,
+            "" | "ns" => 1.,
+            "us" | "µs" => 1e3,
+            "ms" => 1e6,
+            "s" => 1e9,
+            unit => bail!("unknown time unit \"{unit}\""),
+        })
+}
+
+fn format_time(nanos: f64) -> String {
+    let (value, unit) = if nanos >= 1e9 {
+        (nanos / 1e9, "s")
+    } else if nanos >= 1e6 {
+        (nanos / 1e6, "ms")
+    } else if nanos >= 1e3 {
+        (nanos / 1e3, "us")
+    } else {
+        (nanos, "ns")
+    };
+    format!("{value:.4}{unit}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn criterion_and_libtest_output_is_parsed() {
+        let stdout = "\
+running 1 test
+test bench_push   ... bench:       1,234 ns/iter (+/- 56)
+test result: ok. 0 passed; 0 failed; 0 ignored; 1 measured
+
+100k_random_lookup_hits/flat_map
+                        time:   [2.0286 ms 2.0377 ms 2.0478 ms]
+                        change: [-1.2% +0.5% +2.0%] (p = 0.51 > 0.05)
+short/name              time:   [812.50 ns 815.00 ns 818.10 ns]
+";
+        let measurements = parse_output(stdout);
+        let expected = [
+            ("bench_push", 1234.),
+            ("100k_random_lookup_hits/flat_map", 2.0377e6),
+            ("short/name", 815.),
+        ];
+        assert_eq!(measurements.len(), expected.len());
+        for (measurement, (name, nanos)) in measurements.iter().zip(expected) {
+            assert_eq!(measurement.name, name);
+            assert!((measurement.nanos - nanos).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn times_are_parsed() {
+        assert_eq!(parse_time("1500").unwrap(), 1500.);
+        assert_eq!(parse_time("2ms").unwrap(), 2e6);
+        assert_eq!(parse_time("1.5 µs").unwrap(), 1500.);
+        assert!(parse_time("5 min").is_err());
+        assert!(parse_time("fast").is_err());
+    }
+}
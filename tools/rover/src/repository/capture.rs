@@ -0,0 +1,203 @@
+use super::limits;
+use anyhow::{Context, Result};
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, BufReader, Read, Write},
+    process::{Child, ExitStatus},
+    sync::{atomic::AtomicBool, Arc},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+const TAIL_LINES: usize = 30;
+
+/// The last lines of stdout and stderr of the commands launched by a step command.
+#[derive(Debug)]
+pub struct OutputTail {
+    echo: bool,
+    stdout: VecDeque<String>,
+    /// The whole stdout, kept for the commands whose output is parsed.
+    full_stdout: Option<String>,
+    stderr: VecDeque<String>,
+    cancel: Option<Arc<AtomicBool>>,
+}
+
+impl OutputTail {
+    /// If `echo` is false, the output is only captured, not forwarded to ours.
+    pub fn new(echo: bool) -> Self {
+        Self {
+            echo,
+            stdout: VecDeque::new(),
+            full_stdout: None,
+            stderr: VecDeque::new(),
+            cancel: None,
+        }
+    }
+
+    /// Kill the commands as soon as `cancel` is set.
+    pub fn with_cancel(self, cancel: Option<Arc<AtomicBool>>) -> Self {
+        Self { cancel, ..self }
+    }
+
+    /// Keep the whole stdout of the following commands besides the tail, see `take_full_stdout`.
+    pub fn keep_full_stdout(&mut self) {
+        self.full_stdout.get_or_insert_with(String::new);
+    }
+
+    /// The whole stdout kept since `keep_full_stdout`, which stops keeping it.
+    pub fn take_full_stdout(&mut self) -> String {
+        self.full_stdout.take().unwrap_or_default()
+    }
+
+    /// The flag that cancels the commands, see `with_cancel`.
+    pub fn cancel(&self) -> Option<&AtomicBool> {
+        self.cancel.as_deref()
+    }
+
+    pub fn stdout(&self) -> String {
+        Self::join(&self.stdout)
+    }
+
+    pub fn stderr(&self) -> String {
+        Self::join(&self.stderr)
+    }
+
+    /// Print a line of rover's own output as a part of the command output.
+    pub fn println(&mut self, line: String) {
+        if self.echo {
+            println!("{line}");
+        }
+        Self::push(&mut self.stdout, line);
+    }
+
+    /// Wait for the child with piped stdout and stderr, keeping the tail of its output.
+    /// The output is collected even if the child is killed on timeout or cancellation.
+    pub fn wait_child(
+        &mut self,
+        child: &mut Child,
+        timeout: Option<Duration>,
+    ) -> Result<ExitStatus> {
+        let echo = self.echo;
+        let stdout_limit = match self.full_stdout {
+            Some(_) => usize::MAX,
+            None => TAIL_LINES,
+        };
+        let stdout = child
+            .stdout
+            .take()
+            .map(|out| tee(out, echo, stdout_limit, io::stdout));
+        let stderr = child
+            .stderr
+            .take()
+            .map(|err| tee(err, echo, TAIL_LINES, io::stderr));
+        let status = limits::wait(child, timeout, self.cancel.as_deref());
+        if let Some(handle) = stdout {
+            Self::extend(&mut self.stdout, self.full_stdout.as_mut(), handle)?;
+        }
+        if let Some(handle) = stderr {
+            Self::extend(&mut self.stderr, None, handle)?;
+        }
+        status
+    }
+
+    fn extend(
+        tail: &mut VecDeque<String>,
+        mut full: Option<&mut String>,
+        handle: JoinHandle<io::Result<Vec<String>>>,
+    ) -> Result<()> {
+        let lines = handle
+            .join()
+            .expect("output forwarding thread panicked")
+            .context("failed to forward command output")?;
+        for line in lines {
+            if let Some(full) = full.as_deref_mut() {
+                *full += &line;
+                full.push('\n');
+            }
+            Self::push(tail, line);
+        }
+        Ok(())
+    }
+
+    fn push(tail: &mut VecDeque<String>, line: String) {
+        if tail.len() == TAIL_LINES {
+            tail.pop_front();
+        }
+        tail.push_back(line);
+    }
+
+    fn join(lines: &VecDeque<String>) -> String {
+        lines
+            .iter()
+            .fold(String::new(), |acc, line| acc + line + "\n")
+    }
+}
+
+fn tee<R, W, F>(
+    reader: R,
+    echo: bool,
+    limit: usize,
+    writer: F,
+) -> JoinHandle<io::Result<Vec<String>>>
+where
+    R: Read + Send + 'static,
+    W: Write,
+    F: Fn() -> W + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        let mut tail = VecDeque::with_capacity(limit.min(TAIL_LINES));
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            if reader.read_until(b'\n', &mut buf)? == 0 {
+                break;
+            }
+            if echo {
+                let mut writer = writer();
+                writer.write_all(&buf)?;
+                writer.flush()?;
+            }
+            if tail.len() == limit {
+                tail.pop_front();
+            }
+            tail.push_back(String::from_utf8_lossy(&buf).trim_end().to_string());
+        }
+        Ok(tail.into())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::{Command, Stdio};
+
+    fn run(output: &mut OutputTail, script: &str) -> Result<()> {
+        let mut child = Command::new("sh")
+            .args(["-c", script])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        output.wait_child(&mut child, None)?;
+        Ok(())
+    }
+
+    #[test]
+    fn tail() -> Result<()> {
+        let mut output = OutputTail::new(false);
+        run(&mut output, "seq 100; seq 50 >&2")?;
+        assert_eq!(output.stdout().lines().count(), TAIL_LINES);
+        assert!(output.stdout().ends_with("99\n100\n"));
+        assert_eq!(output.stderr().lines().count(), TAIL_LINES);
+        assert_eq!(output.take_full_stdout(), "");
+
+        output.keep_full_stdout();
+        run(&mut output, "seq 100")?;
+        run(&mut output, "echo last")?;
+        assert_eq!(output.stdout().lines().count(), TAIL_LINES);
+        let full = output.take_full_stdout();
+        assert_eq!(full.lines().count(), 101);
+        assert!(full.starts_with("1\n2\n") && full.ends_with("100\nlast\n"));
+        Ok(())
+    }
+}
@@ -0,0 +1,119 @@
+use super::repo::{Repository, COMPOSE_CONFIG};
+use anyhow::{bail, Result};
+use std::path::{Path, PathBuf};
+
+/// Load the config of every problem and the compose config of the course repository
+/// containing `path`, printing the errors.
+pub fn check_configs(path: &Path) -> Result<()> {
+    let repository = Repository::from_path(path)?;
+    let (checked, errors) = config_errors(&repository)?;
+    for (config, error) in &errors {
+        println!("{config}: {error}");
+    }
+    let mut invalid: Vec<_> = errors.iter().map(|(config, _)| config).collect();
+    invalid.dedup();
+    if !invalid.is_empty() {
+        bail!("{} of {checked} configs are invalid", invalid.len());
+    }
+    println!("All {checked} configs are valid");
+    Ok(())
+}
+
+/// The number of checked configs and the errors, each with the problem or the compose
+/// config it's in.
+fn config_errors(repository: &Repository) -> Result<(usize, Vec<(String, String)>)> {
+    let problems = repository.problems()?;
+    let mut errors = Vec::new();
+    for problem in &problems {
+        if let Err(err) = problem.config() {
+            errors.push((problem.branch_name(), format!("{err:#}")));
+        }
+    }
+    let mut checked = problems.len();
+    if repository.get_path().join(COMPOSE_CONFIG).exists() {
+        checked += 1;
+        let compose_errors = match repository.compose_config() {
+            Ok(config) => {
+                let problems = config
+                    .get_problems()
+                    .iter()
+                    .map(|problem| PathBuf::from("problems").join(problem));
+                let tools = config
+                    .get_tools()
+                    .iter()
+                    .map(|tool| PathBuf::from("tools").join(tool));
+                problems
+                    .chain(tools)
+                    .filter(|entry| !repository.get_path().join(entry).is_dir())
+                    .map(|entry| format!("{} does not exist", entry.display()))
+                    .collect()
+            }
+            Err(err) => vec![format!("{err:#}")],
+        };
+        errors.extend(
+            compose_errors
+                .into_iter()
+                .map(|error| (COMPOSE_CONFIG.to_string(), error)),
+        );
+    }
+    Ok((checked, errors))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::repo::create_problem;
+    use std::fs;
+
+    const VALID: &str =
+        "toolchain: stable\nallowed-patterns: [src/lib.rs]\nsteps:\n  testing: [cargo-test]\n";
+
+    #[test]
+    fn every_error_is_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        create_problem(dir.path(), "intro/add", VALID);
+        create_problem(
+            dir.path(),
+            "intro/sub",
+            &VALID.replace("stable", "my-toolchain"),
+        );
+        create_problem(
+            dir.path(),
+            "traits/shape",
+            &VALID.replace("cargo-test", "cargo-tset"),
+        );
+        let repository = Repository::from_path(&dir.path().join("mipt-rust")).unwrap();
+        let compose = repository.get_path().join(COMPOSE_CONFIG);
+        fs::write(
+            &compose,
+            "problems:\n  - intro/add\n  - intro/mul\ntools:\n  - rover\n",
+        )
+        .unwrap();
+
+        let (checked, errors) = config_errors(&repository).unwrap();
+        assert_eq!(checked, 4);
+        let configs: Vec<_> = errors.iter().map(|(config, _)| config.as_str()).collect();
+        assert_eq!(
+            configs,
+            ["intro/sub", "traits/shape", COMPOSE_CONFIG, COMPOSE_CONFIG]
+        );
+        assert!(errors[0]
+            .1
+            .contains("toolchain \"my-toolchain\" is not supported"));
+        assert!(errors[1]
+            .1
+            .contains("command \"cargo-tset\" is not supported"));
+        assert_eq!(errors[2].1, "problems/intro/mul does not exist");
+        assert_eq!(errors[3].1, "tools/rover does not exist");
+        assert!(check_configs(repository.get_path())
+            .unwrap_err()
+            .to_string()
+            .contains("3 of 4 configs are invalid"));
+
+        // An unparsable compose config is reported as a whole.
+        fs::write(&compose, "problems: 1\n").unwrap();
+        let (_, errors) = config_errors(&repository).unwrap();
+        assert_eq!(errors.len(), 3);
+        assert!(errors[2].1.starts_with("invalid compose config"));
+    }
+}
@@ -1,19 +1,53 @@
-use anyhow::{bail, Result};
+use super::{
+    bench::BenchPolicy,
+    coverage::CoveragePolicy,
+    forbid::ForbidPolicy,
+    limits::{self, Limits},
+    yml::{scalars, Ordered, Scalar},
+};
+use anyhow::{bail, Context, Result};
+use serde::{
+    de::{self, value::MapAccessDeserializer, MapAccess, Unexpected, Visitor},
+    Deserialize, Deserializer,
+};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum Command {
     ForbidUnsafe,
     ForbidCollections,
     ForbidStd,
-    CargoFmt,
-    CargoClippy,
-    CargoTest,
-    CargoTestDebug,
-    CargoMiriTest,
-    CargoCompileTestMiniFrunk,
-    CargoCompileTestOrm,
-    CargoCompileTestSnapshot,
-    PythonTest,
+    /// The `forbid` section of the config.
+    Forbid(ForbidPolicy),
+    Process(ProcessCommand),
+    HiddenTest(HiddenTest),
+    /// `cargo bench` compared with the baseline, the `bench` section of the config sets it.
+    Bench(BenchPolicy),
+    /// `cargo test` with coverage checked, the `coverage` section of the config sets it.
+    Coverage(CoveragePolicy),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExpectedStatus {
+    Success,
+    Failure,
+    Code(i32),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(from = "ProcessYml")]
+pub struct ProcessCommand {
+    name: String,
+    program: String,
+    args: Vec<String>,
+    env: Vec<(String, String)>,
+    expected_status: ExpectedStatus,
+    features: Vec<String>,
+    limits: Limits,
 }
 
 impl Command {
@@ -22,33 +56,404 @@ impl Command {
             "forbid-unsafe" => Self::ForbidUnsafe,
             "forbid-collections" => Self::ForbidCollections,
             "forbid-std" => Self::ForbidStd,
-            "cargo-fmt" => Self::CargoFmt,
-            "cargo-clippy" => Self::CargoClippy,
-            "cargo-test" => Self::CargoTest,
-            "cargo-test-debug" => Self::CargoTestDebug,
-            "cargo-miri-test" => Self::CargoMiriTest,
-            "cargo-compile-test-mini-frunk" => Self::CargoCompileTestMiniFrunk,
-            "cargo-compile-test-orm" => Self::CargoCompileTestOrm,
-            "cargo-compile-test-snapshot" => Self::CargoCompileTestSnapshot,
-            "python-test" => Self::PythonTest,
+            "cargo-fmt" => Self::preset(name, "cargo fmt --check"),
+            "cargo-clippy" => Self::preset(name, "cargo clippy --release -- -D warnings"),
+            "cargo-test" => Self::preset(name, "cargo test --release"),
+            "cargo-test-debug" => Self::preset(name, "cargo test"),
+            "cargo-miri-test" => Self::preset(name, "cargo miri test --release"),
+            "cargo-bench" => Self::Bench(BenchPolicy::default()),
+            "cargo-coverage" => Self::Coverage(CoveragePolicy::default()),
+            "cargo-compile-test-mini-frunk" => Self::compile_fail_preset(
+                name,
+                &[
+                    "compilation-fail-generic",
+                    "compilation-fail-labelled",
+                    "compilation-fail-transmogrify",
+                ],
+            ),
+            "cargo-compile-test-orm" => {
+                Self::compile_fail_preset(name, &["test-lifetimes-create", "test-lifetimes-get"])
+            }
+            "cargo-compile-test-snapshot" => Self::compile_fail_preset(name, &["test-lifetime"]),
+            "python-test" => Self::preset(name, "python3 test.py"),
             name => bail!("command \"{name}\" is not supported"),
         })
     }
 
-    pub fn get_shell_line(&self) -> Result<String> {
-        Ok(match self {
-            Self::ForbidUnsafe => bail!("no shell line for ForbidUnsafe"),
-            Self::ForbidCollections => bail!("no shell line for ForbidCollections"),
-            Self::ForbidStd => bail!("no shell line for ForbidStd"),
-            Self::CargoFmt => "cargo fmt --check".to_string(),
-            Self::CargoClippy => "cargo clippy --release -- -D warnings".to_string(),
-            Self::CargoTest => "cargo test --release".to_string(),
-            Self::CargoTestDebug => "cargo test".to_string(),
-            Self::CargoMiriTest => "cargo miri test --release".to_string(),
-            Self::CargoCompileTestMiniFrunk => bail!("no shell line for CargoCompileTestMiniFrunk"),
-            Self::CargoCompileTestOrm => bail!("no shell line for CargoCompileTestOrm"),
-            Self::CargoCompileTestSnapshot => bail!("no shell line for CargoCompileTestSnapshot"),
-            Self::PythonTest => "python3 test.py".to_string(),
+    pub fn name(&self) -> &str {
+        match self {
+            Self::ForbidUnsafe => "forbid-unsafe",
+            Self::ForbidCollections => "forbid-collections",
+            Self::ForbidStd => "forbid-std",
+            Self::Forbid(_) => "forbid",
+            Self::Process(process) => process.name(),
+            Self::HiddenTest(test) => test.process.name(),
+            Self::Bench(_) => "cargo-bench",
+            Self::Coverage(_) => "cargo-coverage",
+        }
+    }
+
+    /// Limits set for the command itself, the ones of its step apply if unset.
+    pub fn limits(&self) -> Limits {
+        match self {
+            Self::Process(process) => process.limits,
+            _ => Limits::default(),
+        }
+    }
+
+    /// Run `cargo test` on a test file that is not in the problem.
+    pub fn hidden_test(source: PathBuf) -> Result<Self> {
+        let file_name = source
+            .file_name()
+            .context("hidden test has no file name")?
+            .to_str()
+            .context("non-utf-8 hidden test name")?
+            .to_string();
+        let target = file_name
+            .strip_suffix(".rs")
+            .with_context(|| format!("hidden test {file_name} is not a Rust file"))?;
+        let mut process = ProcessCommand::preset(
+            &format!("hidden-test-{target}"),
+            &format!("cargo test --release --test {target}"),
+        );
+        process.name = format!("hidden test {file_name}");
+        Ok(Self::HiddenTest(HiddenTest {
+            source,
+            file_name,
+            process,
+        }))
+    }
+
+    fn preset(name: &str, shell_line: &str) -> Self {
+        Self::Process(ProcessCommand::preset(name, shell_line))
+    }
+
+    fn compile_fail_preset(name: &str, features: &[&str]) -> Self {
+        Self::Process(ProcessCommand {
+            name: name.to_string(),
+            program: "cargo".to_string(),
+            args: vec!["test".to_string()],
+            env: Vec::new(),
+            expected_status: ExpectedStatus::Failure,
+            features: features.iter().map(|f| f.to_string()).collect(),
+            limits: Limits::default(),
         })
     }
 }
+
+/// A test file kept outside of the repository. It's copied to `tests/` of the problem
+/// only while the test runs, so public steps never see it.
+#[derive(Clone, Debug)]
+pub struct HiddenTest {
+    source: PathBuf,
+    file_name: String,
+    process: ProcessCommand,
+}
+
+/// Removes the injected test file, and `tests/` if it was created for it.
+pub struct InjectedTest {
+    file: Option<PathBuf>,
+    created_dir: Option<PathBuf>,
+}
+
+impl HiddenTest {
+    pub fn process(&self) -> &ProcessCommand {
+        &self.process
+    }
+
+    /// Copy the test to `tests/` of the problem, it's removed once the result is dropped.
+    /// A copy left by a killed run is taken over, any other file with the name is an error.
+    pub fn inject(&self, workdir: &Path) -> Result<InjectedTest> {
+        let dir = workdir.join("tests");
+        let created_dir = if dir.exists() {
+            None
+        } else {
+            fs::create_dir(&dir).with_context(|| format!("failed to create {dir:?}"))?;
+            Some(dir.clone())
+        };
+        let file = dir.join(&self.file_name);
+        let mut injected = InjectedTest {
+            file: None,
+            created_dir,
+        };
+        if file.exists() {
+            let content = fs::read(&self.source)
+                .with_context(|| format!("failed to read {:?}", self.source))?;
+            if fs::read(&file).ok() != Some(content) {
+                bail!("hidden test {file:?} would overwrite a test of the problem");
+            }
+        }
+        injected.file = Some(file.clone());
+        fs::copy(&self.source, &file)
+            .with_context(|| format!("failed to copy hidden test {:?}", self.source))?;
+        Ok(injected)
+    }
+}
+
+impl Drop for InjectedTest {
+    fn drop(&mut self) {
+        if let Some(file) = &self.file {
+            let _ = fs::remove_file(file);
+        }
+        if let Some(dir) = &self.created_dir {
+            let _ = fs::remove_dir(dir);
+        }
+    }
+}
+
+impl ExpectedStatus {
+    pub fn matches(&self, code: Option<i32>) -> bool {
+        match self {
+            Self::Success => code == Some(0),
+            Self::Failure => code != Some(0),
+            Self::Code(expected) => code == Some(*expected),
+        }
+    }
+}
+
+impl ProcessCommand {
+    /// A command expected to succeed, launched with the space-separated `shell_line`.
+    pub fn preset(name: &str, shell_line: &str) -> Self {
+        let mut iter = shell_line.split(' ').map(str::to_string);
+        Self {
+            name: name.to_string(),
+            program: iter.next().unwrap(),
+            args: iter.collect(),
+            env: Vec::new(),
+            expected_status: ExpectedStatus::Success,
+            features: Vec::new(),
+            limits: Limits::default(),
+        }
+    }
+
+    /// Additional environment variables, overriding the ones with the same name.
+    pub fn with_env(mut self, env: Vec<(String, String)>) -> Self {
+        self.env.extend(env);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn program(&self) -> &str {
+        &self.program
+    }
+
+    pub fn env(&self) -> &[(String, String)] {
+        self.env.as_slice()
+    }
+
+    pub fn expected_status(&self) -> ExpectedStatus {
+        self.expected_status
+    }
+
+    /// Argument lists to launch the program with: one per feature, or the
+    /// bare arguments if no features are listed. Features are passed before
+    /// the `--` separator so they don't end up in the test binary arguments.
+    pub fn invocations(&self) -> Vec<Vec<String>> {
+        if self.features.is_empty() {
+            return vec![self.args.clone()];
+        }
+        let separator = self
+            .args
+            .iter()
+            .position(|arg| arg == "--")
+            .unwrap_or(self.args.len());
+        self.features
+            .iter()
+            .map(|feature| {
+                let mut args = self.args.clone();
+                args.splice(
+                    separator..separator,
+                    ["--features".to_string(), feature.clone()],
+                );
+                args
+            })
+            .collect()
+    }
+}
+
+/// A command with a program to launch as written in the config.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct ProcessYml {
+    name: Option<String>,
+    program: String,
+    #[serde(default)]
+    args: Vec<Scalar>,
+    #[serde(default)]
+    env: Ordered<Scalar>,
+    expect: Option<ExpectedStatus>,
+    #[serde(default)]
+    features: Vec<Scalar>,
+    #[serde(default, deserialize_with = "limits::timeout")]
+    timeout: Option<Duration>,
+    #[serde(default, deserialize_with = "limits::memory")]
+    memory_limit: Option<u64>,
+    #[serde(default, deserialize_with = "limits::cpu")]
+    cpu_limit: Option<u64>,
+}
+
+impl From<ProcessYml> for ProcessCommand {
+    fn from(yml: ProcessYml) -> Self {
+        let args = scalars(yml.args);
+        let name = yml.name.unwrap_or_else(|| {
+            std::iter::once(yml.program.as_str())
+                .chain(args.iter().map(String::as_str))
+                .collect::<Vec<_>>()
+                .join(" ")
+        });
+        Self {
+            name,
+            program: yml.program,
+            args,
+            env: yml
+                .env
+                .0
+                .into_iter()
+                .map(|(key, Scalar(value))| (key, value))
+                .collect(),
+            expected_status: yml.expect.unwrap_or(ExpectedStatus::Success),
+            features: scalars(yml.features),
+            limits: Limits {
+                timeout: yml.timeout,
+                memory: yml.memory_limit,
+                cpu: yml.cpu_limit,
+            },
+        }
+    }
+}
+
+/// Either a name of a preset or a mapping with a program to launch.
+impl<'de> Deserialize<'de> for Command {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct CommandVisitor;
+
+        impl<'de> Visitor<'de> for CommandVisitor {
+            type Value = Command;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a command name or a mapping with a program")
+            }
+
+            fn visit_str<E: de::Error>(self, name: &str) -> Result<Self::Value, E> {
+                Command::from_name(name).map_err(E::custom)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                ProcessCommand::deserialize(MapAccessDeserializer::new(map)).map(Command::Process)
+            }
+        }
+
+        deserializer.deserialize_any(CommandVisitor)
+    }
+}
+
+/// Either `success`, `failure` or an exit code.
+impl<'de> Deserialize<'de> for ExpectedStatus {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct StatusVisitor;
+
+        impl<'de> Visitor<'de> for StatusVisitor {
+            type Value = ExpectedStatus;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "\"success\", \"failure\" or an exit code")
+            }
+
+            fn visit_i64<E: de::Error>(self, code: i64) -> Result<Self::Value, E> {
+                i32::try_from(code)
+                    .map(ExpectedStatus::Code)
+                    .map_err(|_| E::invalid_value(Unexpected::Signed(code), &self))
+            }
+
+            fn visit_u64<E: de::Error>(self, code: u64) -> Result<Self::Value, E> {
+                i32::try_from(code)
+                    .map(ExpectedStatus::Code)
+                    .map_err(|_| E::invalid_value(Unexpected::Unsigned(code), &self))
+            }
+
+            fn visit_str<E: de::Error>(self, status: &str) -> Result<Self::Value, E> {
+                match status {
+                    "success" => Ok(ExpectedStatus::Success),
+                    "failure" => Ok(ExpectedStatus::Failure),
+                    status => Err(E::invalid_value(Unexpected::Str(status), &self)),
+                }
+            }
+        }
+
+        deserializer.deserialize_any(StatusVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invocations(yml: &str) -> Vec<String> {
+        let command: ProcessCommand = serde_yaml::from_str(yml).unwrap();
+        command
+            .invocations()
+            .iter()
+            .map(|args| args.join(" "))
+            .collect()
+    }
+
+    #[test]
+    fn features_before_separator() {
+        assert_eq!(
+            invocations("{program: cargo, args: [test, --release]}"),
+            ["test --release"]
+        );
+        assert_eq!(
+            invocations("{program: cargo, args: [test, --release], features: [a, b]}"),
+            ["test --release --features a", "test --release --features b"]
+        );
+        assert_eq!(
+            invocations("{program: cargo, args: [test, --, --nocapture], features: [a]}"),
+            ["test --features a -- --nocapture"]
+        );
+        assert_eq!(
+            invocations("{program: cargo, args: [test, --, x, --, y], features: [a]}"),
+            ["test --features a -- x -- y"]
+        );
+    }
+
+    #[test]
+    fn hidden_test_injection() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("stress.rs");
+        fs::write(&source, "#[test]\nfn stress() {}\n").unwrap();
+        let Command::HiddenTest(test) = Command::hidden_test(source.clone()).unwrap() else {
+            panic!("not a hidden test");
+        };
+        let workdir = dir.path().join("problem");
+        let tests = workdir.join("tests");
+        let injected = tests.join("stress.rs");
+
+        // The directory created for the test is removed along with it.
+        fs::create_dir(&workdir).unwrap();
+        let guard = test.inject(&workdir).unwrap();
+        assert_eq!(fs::read(&injected).unwrap(), fs::read(&source).unwrap());
+        drop(guard);
+        assert!(!tests.exists());
+
+        fs::create_dir(&tests).unwrap();
+        fs::write(tests.join("tests.rs"), "").unwrap();
+        drop(test.inject(&workdir).unwrap());
+        assert!(!injected.exists());
+        assert!(tests.join("tests.rs").exists());
+
+        // A copy left by a killed run is taken over, a test of the problem is kept.
+        fs::copy(&source, &injected).unwrap();
+        drop(test.inject(&workdir).unwrap());
+        assert!(!injected.exists());
+        fs::write(&injected, "// the student's test\n").unwrap();
+        assert!(test.inject(&workdir).is_err());
+        assert_eq!(
+            fs::read_to_string(&injected).unwrap(),
+            "// the student's test\n"
+        );
+    }
+}
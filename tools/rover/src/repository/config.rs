@@ -1,12 +1,34 @@
-#![allow(clippy::needless_question_mark)]
-
-use super::{command::Command, context::CommandContext, step::Step, toolchain::Toolchain};
-use anyhow::{Context, Result};
-use glob::{glob_with, MatchOptions};
-use serde_yaml::{from_reader, Value};
+use super::{
+    bench::BenchPolicy,
+    command::Command,
+    context::CommandContext,
+    coverage::CoveragePolicy,
+    forbid::ForbidPolicy,
+    limits::{self, Limits},
+    step::Step,
+    toolchain::Toolchain,
+    yml::Ordered,
+};
+use anyhow::{bail, Context, Result};
+use glob::{glob_with, MatchOptions, Pattern};
+use serde::{
+    de::{self, value::MapAccessDeserializer, value::SeqAccessDeserializer, MapAccess, SeqAccess},
+    Deserialize, Deserializer,
+};
 use std::{
+    fmt,
     fs::File,
     path::{Path, PathBuf},
+    time::Duration,
+};
+
+const HIDDEN_STEP: &str = "hidden-tests";
+const FORBID_STEP: &str = "forbid";
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: false,
+    require_literal_separator: false,
+    require_literal_leading_dot: false,
 };
 
 #[derive(Debug)]
@@ -15,52 +37,142 @@ pub struct Config {
     toolchain: Toolchain,
     relative_user_files: Vec<PathBuf>,
     absolute_user_files: Vec<PathBuf>,
+    /// `allowed-patterns` relative to the filesystem root.
+    user_patterns: Vec<Pattern>,
     steps: Vec<Step>,
+    hidden_tests: Vec<PathBuf>,
+}
+
+/// The config as written in the file. Values are validated while deserializing,
+/// so that errors point to the line and column of the invalid one.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct ConfigYml {
+    toolchain: Toolchain,
+    allowed_patterns: Vec<String>,
+    steps: Ordered<StepYml>,
+    forbid: Option<ForbidPolicy>,
+    bench: Option<BenchPolicy>,
+    coverage: Option<CoveragePolicy>,
+    #[serde(default)]
+    hidden_tests: Vec<PathBuf>,
+}
+
+/// Either a list of commands or a mapping with commands and limits.
+struct StepYml {
+    commands: Vec<Command>,
+    limits: Limits,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct StepWithLimitsYml {
+    commands: Vec<Command>,
+    #[serde(default, deserialize_with = "limits::timeout")]
+    timeout: Option<Duration>,
+    #[serde(default, deserialize_with = "limits::memory")]
+    memory_limit: Option<u64>,
+    #[serde(default, deserialize_with = "limits::cpu")]
+    cpu_limit: Option<u64>,
+}
+
+impl<'de> Deserialize<'de> for StepYml {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct StepVisitor;
+
+        impl<'de> de::Visitor<'de> for StepVisitor {
+            type Value = StepYml;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a list of commands or a mapping with commands")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+                Ok(StepYml {
+                    commands: Vec::deserialize(SeqAccessDeserializer::new(seq))?,
+                    limits: Limits::default(),
+                })
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let step = StepWithLimitsYml::deserialize(MapAccessDeserializer::new(map))?;
+                Ok(StepYml {
+                    commands: step.commands,
+                    limits: Limits {
+                        timeout: step.timeout,
+                        memory: step.memory_limit,
+                        cpu: step.cpu_limit,
+                    },
+                })
+            }
+        }
+
+        deserializer.deserialize_any(StepVisitor)
+    }
 }
 
 impl Config {
     pub fn from_yml(path: &Path) -> Result<Self> {
         let file = File::open(path).context("no yml file with config")?;
-        let yml: Value = from_reader(file).context("unable to read yml file with config")?;
-        let yml = yml.as_mapping().context("the yml is not a mapping")?;
+        let yml: ConfigYml = serde_yaml::from_reader(file)
+            .with_context(|| format!("invalid config {}", path.display()))?;
         let workdir = path.parent().context("yml has no parent")?.to_path_buf();
-        let toolchain = Toolchain::from_name(
-            yml[&Value::String("toolchain".to_string())]
-                .as_str()
-                .context("toolchain is not a string")?,
-        )?;
-        let patterns = yml[&Value::String("allowed-patterns".to_string())]
-            .as_sequence()
-            .context("user files are not in a list")?
-            .iter()
-            .map(|value| Ok(value.as_str().context("user file path is not a string")?))
-            .collect::<Result<Vec<_>>>()?;
+        let patterns: Vec<_> = yml.allowed_patterns.iter().map(String::as_str).collect();
         let (relative_user_files, absolute_user_files) =
             Self::get_matching_user_files(&workdir, patterns.as_slice())?;
-        let steps = yml[&Value::String("steps".to_string())]
-            .as_mapping()
-            .context("steps is not a mapping")?
+        let user_patterns = patterns
             .iter()
-            .map(|(key, value)| {
-                let name = key
-                    .as_str()
-                    .context("name of the step is not a string")?
-                    .to_string();
-                let commands = value
-                    .as_sequence()
-                    .context("the step commands are not a sequence")?
-                    .iter()
-                    .map(|value| Command::from_name(value.as_str().unwrap()).unwrap())
-                    .collect();
-                Ok(Step::new(name, commands))
+            .map(|pattern| {
+                let pattern = workdir.join(pattern);
+                Pattern::new(pattern.to_str().context("non-utf-8 path")?)
+                    .context("pattern is invalid")
             })
-            .collect::<Result<Vec<_>>>()?;
+            .collect::<Result<_>>()?;
+        let mut steps: Vec<_> = yml
+            .steps
+            .0
+            .into_iter()
+            .map(|(name, mut step)| {
+                for command in &mut step.commands {
+                    match (command, &yml.bench, &yml.coverage) {
+                        (Command::Bench(policy), Some(bench), _) => *policy = bench.clone(),
+                        (Command::Coverage(policy), _, Some(coverage)) => {
+                            *policy = coverage.clone()
+                        }
+                        _ => {}
+                    }
+                }
+                Step::new(name, step.commands, step.limits)
+            })
+            .collect();
+        if !yml.hidden_tests.is_empty() && steps.iter().any(|step| step.name() == HIDDEN_STEP) {
+            bail!(
+                "invalid config {}: step \"{HIDDEN_STEP}\" is reserved for the hidden tests",
+                path.display()
+            );
+        }
+        // The forbidden items are checked before anything else.
+        if let Some(policy) = yml.forbid {
+            if steps.iter().any(|step| step.name() == FORBID_STEP) {
+                bail!(
+                    "invalid config {}: step \"{FORBID_STEP}\" is reserved for the forbid section",
+                    path.display()
+                );
+            }
+            let commands = vec![Command::Forbid(policy)];
+            steps.insert(
+                0,
+                Step::new(FORBID_STEP.to_string(), commands, Limits::default()),
+            );
+        }
         Ok(Self {
             workdir,
-            toolchain,
+            toolchain: yml.toolchain,
             relative_user_files,
             absolute_user_files,
+            user_patterns,
             steps,
+            hidden_tests: yml.hidden_tests,
         })
     }
 
@@ -68,6 +180,23 @@ impl Config {
         self.steps.as_slice()
     }
 
+    /// The step running each hidden test from `dir`, `None` if there are no hidden tests.
+    pub fn get_hidden_step(&self, dir: &Path) -> Result<Option<Step>> {
+        if self.hidden_tests.is_empty() {
+            return Ok(None);
+        }
+        let commands = self
+            .hidden_tests
+            .iter()
+            .map(|test| Command::hidden_test(dir.join(test)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Some(Step::new(
+            HIDDEN_STEP.to_string(),
+            commands,
+            Limits::default(),
+        )))
+    }
+
     pub fn get_toolchain(&self) -> &Toolchain {
         &self.toolchain
     }
@@ -76,9 +205,11 @@ impl Config {
         self.relative_user_files.as_slice()
     }
 
-    #[allow(dead_code)]
-    pub fn get_absolute_user_files(&self) -> &[PathBuf] {
-        self.absolute_user_files.as_slice()
+    /// Whether the absolute path matches `allowed-patterns`, the file may not exist yet.
+    pub fn is_user_file(&self, path: &Path) -> bool {
+        self.user_patterns
+            .iter()
+            .any(|pattern| pattern.matches_path_with(path, MATCH_OPTIONS))
     }
 
     pub fn get_command_context(&self) -> CommandContext {
@@ -89,18 +220,13 @@ impl Config {
         workdir: &Path,
         patterns: &[&str],
     ) -> Result<(Vec<PathBuf>, Vec<PathBuf>)> {
-        let options = MatchOptions {
-            case_sensitive: false,
-            require_literal_separator: false,
-            require_literal_leading_dot: false,
-        };
         let workdir_len = workdir.iter().count();
         let mut relative_user_files = Vec::new();
         let mut absolute_user_files = Vec::new();
         for pattern in patterns {
             let pattern = workdir.join(pattern).to_path_buf();
             let pattern = pattern.to_str().context("non-utf-8 path")?;
-            for entry in glob_with(pattern, options).context("pattern is invalid")? {
+            for entry in glob_with(pattern, MATCH_OPTIONS).context("pattern is invalid")? {
                 let entry = entry?;
                 absolute_user_files.push(entry.clone());
                 relative_user_files.push(entry.iter().skip(workdir_len).collect());
@@ -109,3 +235,65 @@ impl Config {
         Ok((relative_user_files, absolute_user_files))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn load(yml: &str) -> Result<Config> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".config.yml");
+        fs::write(&path, yml).unwrap();
+        Config::from_yml(&path)
+    }
+
+    /// The error of loading the config with its causes, and the path of the config.
+    fn error(yml: &str) -> (String, String) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".config.yml");
+        fs::write(&path, yml).unwrap();
+        let err = Config::from_yml(&path).unwrap_err();
+        (format!("{err:#}"), path.display().to_string())
+    }
+
+    #[test]
+    fn error_locations() {
+        let (err, path) = error(
+            "toolchain: stable\nallowed-patterns: [src/lib.rs]\nsteps:\n  testing:\n    - program: cargo\n      argz: [test]\n",
+        );
+        assert_eq!(err, format!("invalid config {path}: steps.testing[0]: unknown field `argz`, expected one of `name`, `program`, `args`, `env`, `expect`, `features`, `timeout`, `memory-limit`, `cpu-limit` at line 6 column 7"));
+        let (err, path) = error("toolchain: stable\nallowed-patterns: src/lib.rs\nsteps: {}\n");
+        assert_eq!(err, format!("invalid config {path}: allowed-patterns: invalid type: string \"src/lib.rs\", expected a sequence at line 2 column 19"));
+    }
+
+    #[test]
+    fn duplicate_steps() {
+        let (err, path) = error(
+            "toolchain: empty\nallowed-patterns: []\nsteps:\n  testing: [cargo-test]\n  testing: [cargo-clippy]\n",
+        );
+        assert_eq!(
+            err,
+            format!("invalid config {path}: steps: duplicate key \"testing\" at line 5 column 3")
+        );
+    }
+
+    #[test]
+    fn forbid_step() {
+        let yml = "toolchain: empty\nallowed-patterns: []\nforbid:\n  crates: [rand]\nsteps:\n";
+        let config = load(&format!("{yml}  testing: [cargo-test]\n")).unwrap();
+        let names: Vec<_> = config.get_steps().iter().map(Step::name).collect();
+        assert_eq!(names, [FORBID_STEP, "testing"]);
+
+        let err = load(&format!("{yml}  forbid: [cargo-test]\n")).unwrap_err();
+        assert!(err.to_string().contains("reserved"), "{err:#}");
+    }
+
+    #[test]
+    fn hidden_step() {
+        let yml = "toolchain: empty\nallowed-patterns: []\nsteps:\n  hidden-tests: [cargo-test]\n";
+        load(yml).unwrap();
+        let err = load(&format!("{yml}hidden-tests: [stress.rs]\n")).unwrap_err();
+        assert!(err.to_string().contains("reserved"), "{err:#}");
+    }
+}
@@ -3,6 +3,7 @@ use std::path::{Path, PathBuf};
 pub struct CommandContext {
     workdir: PathBuf,
     user_files: Vec<PathBuf>,
+    target_dir: Option<PathBuf>,
 }
 
 impl CommandContext {
@@ -10,9 +11,15 @@ impl CommandContext {
         Self {
             workdir: workdir.to_path_buf(),
             user_files: user_files.to_vec(),
+            target_dir: None,
         }
     }
 
+    /// Build with cargo in `target_dir` instead of the target directory of the workspace.
+    pub fn with_target_dir(self, target_dir: Option<PathBuf>) -> Self {
+        Self { target_dir, ..self }
+    }
+
     pub fn get_workdir(&self) -> &Path {
         &self.workdir
     }
@@ -20,4 +27,8 @@ impl CommandContext {
     pub fn get_user_files(&self) -> &[PathBuf] {
         &self.user_files
     }
+
+    pub fn get_target_dir(&self) -> Option<&Path> {
+        self.target_dir.as_deref()
+    }
 }
@@ -0,0 +1,397 @@
+use super::{
+    capture::OutputTail,
+    command::ProcessCommand,
+    context::CommandContext,
+    limits::{self, Limits},
+    toolchain::Toolchain,
+};
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::{
+    env, fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process::{self, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+const DEFAULT_MIN_LINES: f64 = 80.;
+
+/// Numbers the runs, problems tested at once are measured by the same process.
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+/// Minimal coverage of each allowed file, in percents, required by `cargo-coverage`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "CoverageYml")]
+pub struct CoveragePolicy {
+    lines: f64,
+    regions: Option<f64>,
+}
+
+/// The `coverage` section of the config.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct CoverageYml {
+    lines: Option<f64>,
+    regions: Option<f64>,
+}
+
+/// The part of `llvm-cov export -summary-only` output that is used.
+#[derive(Deserialize)]
+struct Export {
+    data: Vec<ExportData>,
+}
+
+#[derive(Deserialize)]
+struct ExportData {
+    files: Vec<FileExport>,
+}
+
+#[derive(Deserialize)]
+struct FileExport {
+    filename: PathBuf,
+    summary: Summary,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+struct Summary {
+    lines: Counts,
+    regions: Counts,
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+struct Counts {
+    count: u64,
+    covered: u64,
+}
+
+impl Default for CoveragePolicy {
+    fn default() -> Self {
+        Self {
+            lines: DEFAULT_MIN_LINES,
+            regions: None,
+        }
+    }
+}
+
+impl TryFrom<CoverageYml> for CoveragePolicy {
+    type Error = String;
+
+    fn try_from(yml: CoverageYml) -> Result<Self, Self::Error> {
+        let lines = yml.lines.unwrap_or(DEFAULT_MIN_LINES);
+        for (key, percent) in [("lines", Some(lines)), ("regions", yml.regions)] {
+            if percent.is_some_and(|percent| !(0. ..=100.).contains(&percent)) {
+                return Err(format!("coverage {key} must be a percent from 0 to 100"));
+            }
+        }
+        Ok(Self {
+            lines,
+            regions: yml.regions,
+        })
+    }
+}
+
+impl Counts {
+    fn percent(&self) -> f64 {
+        if self.count == 0 {
+            100.
+        } else {
+            self.covered as f64 * 100. / self.count as f64
+        }
+    }
+}
+
+impl CoveragePolicy {
+    /// Run the tests built with `-C instrument-coverage` and check the coverage of every
+    /// allowed Rust file. Profiles are merged and exported with the `llvm-tools` component.
+    /// The timeout of the step covers all of it.
+    pub fn run(
+        &self,
+        toolchain: &Toolchain,
+        context: &CommandContext,
+        limits: Limits,
+        output: &mut OutputTail,
+    ) -> Result<()> {
+        // Instrumented artifacts would otherwise evict the regular ones from the target dir.
+        let target_dir = match context.get_target_dir() {
+            Some(target_dir) => target_dir.join("coverage"),
+            None => env::temp_dir().join("rover-coverage"),
+        };
+        let profiles = target_dir.join(format!(
+            "profiles-{}-{}",
+            process::id(),
+            RUNS.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_dir_all(&profiles);
+        fs::create_dir_all(&profiles)
+            .with_context(|| format!("failed to create directory {profiles:?}"))?;
+        let deadline = limits.timeout.map(|timeout| Instant::now() + timeout);
+        let measure = Measure {
+            toolchain,
+            context,
+            limits,
+            deadline,
+            target_dir: &target_dir,
+            profiles: &profiles,
+        };
+        let result = measure.export(output);
+        let _ = fs::remove_dir_all(&profiles);
+        let summaries = result?;
+
+        let mut failures = Vec::new();
+        for path in context.get_user_files() {
+            if path.extension().is_none_or(|ext| ext != "rs") {
+                continue;
+            }
+            let name = path
+                .strip_prefix(context.get_workdir())
+                .unwrap_or(path)
+                .display()
+                .to_string();
+            let summary = path.canonicalize().ok().and_then(|path| {
+                summaries
+                    .iter()
+                    .find(|file| file.filename == path)
+                    .map(|file| file.summary)
+            });
+            // Files that are not compiled into the tests, e.g. unused modules.
+            let Some(summary) = summary else {
+                output.println(format!("{name}: not measured"));
+                continue;
+            };
+            let (lines, regions) = (summary.lines.percent(), summary.regions.percent());
+            let failed = lines < self.lines || self.regions.is_some_and(|min| regions < min);
+            if failed {
+                failures.push(name.clone());
+            }
+            output.println(format!(
+                "{name}: lines {lines:.1}% ({}/{}), regions {regions:.1}% ({}/{}){}",
+                summary.lines.covered,
+                summary.lines.count,
+                summary.regions.covered,
+                summary.regions.count,
+                if failed { ", below the threshold" } else { "" }
+            ));
+        }
+        if !failures.is_empty() {
+            let mut thresholds = format!("{}% of lines", self.lines);
+            if let Some(regions) = self.regions {
+                thresholds += &format!(" and {regions}% of regions");
+            }
+            bail!("coverage of {} is below {thresholds}", failures.join(", "));
+        }
+        Ok(())
+    }
+}
+
+/// A coverage measurement in progress.
+struct Measure<'a> {
+    toolchain: &'a Toolchain,
+    context: &'a CommandContext,
+    /// Limits of the step, the helper commands are subject to them as well.
+    limits: Limits,
+    /// The end of the step timeout, if any.
+    deadline: Option<Instant>,
+    target_dir: &'a Path,
+    profiles: &'a Path,
+}
+
+impl Measure<'_> {
+    /// Summaries of the files measured by the tests.
+    fn export(&self, output: &mut OutputTail) -> Result<Vec<FileExport>> {
+        let tools = llvm_tools(self, output)?;
+        let rustflags = env::var("RUSTFLAGS").unwrap_or_default() + " -C instrument-coverage";
+        let env = [
+            ("RUSTFLAGS", rustflags.trim_start()),
+            ("CARGO_TARGET_DIR", path_str(self.target_dir)?),
+            (
+                "LLVM_PROFILE_FILE",
+                path_str(&self.profiles.join("%p-%m.profraw"))?,
+            ),
+        ]
+        .map(|(key, value)| (key.to_string(), value.to_string()));
+        let test = ProcessCommand::preset("cargo-coverage", "cargo test").with_env(env.to_vec());
+        let limits = Limits {
+            timeout: self.remaining(),
+            ..self.limits
+        };
+        self.toolchain.launch(&test, self.context, limits, output)?;
+
+        // Everything is built already, this only lists the test binaries.
+        let build = self.run(
+            self.toolchain
+                .command("cargo")
+                .args(["test", "--no-run", "--message-format=json"])
+                .current_dir(self.context.get_workdir())
+                .envs(env.iter().cloned()),
+            output,
+        )?;
+        let objects: Vec<_> = String::from_utf8_lossy(&build)
+            .lines()
+            .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+            .filter_map(|message| message["executable"].as_str().map(str::to_string))
+            .collect();
+        if objects.is_empty() {
+            bail!("no test binaries to measure coverage of");
+        }
+
+        let raw: Vec<_> = fs::read_dir(self.profiles)
+            .with_context(|| format!("failed to read directory {:?}", self.profiles))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "profraw"))
+            .collect();
+        if raw.is_empty() {
+            bail!("tests produced no coverage profiles");
+        }
+        let merged = self.profiles.join("merged.profdata");
+        self.run(
+            process::Command::new(tools.join("llvm-profdata"))
+                .args(["merge", "-sparse", "-o"])
+                .arg(&merged)
+                .args(&raw),
+            output,
+        )?;
+
+        let mut export = process::Command::new(tools.join("llvm-cov"));
+        export
+            .args(["export", "-summary-only", "-instr-profile"])
+            .arg(&merged)
+            .arg(&objects[0]);
+        for object in &objects[1..] {
+            export.arg("-object").arg(object);
+        }
+        let export = self.run(&mut export, output)?;
+        let export: Export = serde_json::from_slice(&export)
+            .context("failed to parse the output of llvm-cov export")?;
+        Ok(export
+            .data
+            .into_iter()
+            .flat_map(|data| data.files)
+            .collect())
+    }
+
+    fn remaining(&self) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Run a helper command within the step limits, killing it on cancellation,
+    /// and return its stdout.
+    fn run(&self, cmd: &mut process::Command, output: &OutputTail) -> Result<Vec<u8>> {
+        let program = cmd.get_program().to_string_lossy().into_owned();
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+        limits::apply(cmd, self.limits);
+        let mut child = cmd
+            .spawn()
+            .with_context(|| format!("failed to launch {program}"))?;
+        let stdout = child.stdout.take().map(read_all);
+        let stderr = child.stderr.take().map(read_all);
+        let status = limits::wait(&mut child, self.remaining(), output.cancel());
+        let stdout = join(stdout)?;
+        let stderr = join(stderr)?;
+        let status = status?;
+        if !status.success() {
+            bail!(
+                "{program} failed with {status}: {}",
+                String::from_utf8_lossy(&stderr).trim()
+            );
+        }
+        Ok(stdout)
+    }
+}
+
+/// The directory with `llvm-profdata` and `llvm-cov` of the `llvm-tools` component.
+fn llvm_tools(measure: &Measure, output: &OutputTail) -> Result<PathBuf> {
+    let version = measure.run(measure.toolchain.command("rustc").arg("-vV"), output)?;
+    let version = String::from_utf8_lossy(&version);
+    let host = version
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .context("rustc -vV printed no host")?;
+    let sysroot = measure.run(
+        measure
+            .toolchain
+            .command("rustc")
+            .args(["--print", "sysroot"]),
+        output,
+    )?;
+    let tools = PathBuf::from(String::from_utf8_lossy(&sysroot).trim())
+        .join("lib/rustlib")
+        .join(host)
+        .join("bin");
+    if !tools.join("llvm-profdata").exists() {
+        bail!("llvm-profdata is not found, add llvm-tools to the components of the toolchain");
+    }
+    Ok(tools)
+}
+
+fn read_all<R: Read + Send + 'static>(mut reader: R) -> JoinHandle<io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf)?;
+        Ok(buf)
+    })
+}
+
+fn join(handle: Option<JoinHandle<io::Result<Vec<u8>>>>) -> Result<Vec<u8>> {
+    match handle {
+        Some(handle) => handle
+            .join()
+            .expect("output reading thread panicked")
+            .context("failed to read command output"),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn path_str(path: &Path) -> Result<&str> {
+    path.to_str()
+        .with_context(|| format!("non-utf-8 path {path:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_is_parsed() {
+        let export = r#"{"data": [{"files": [{"filename": "/p/src/lib.rs", "summary": {
+            "lines": {"count": 20, "covered": 15, "percent": 75},
+            "regions": {"count": 8, "covered": 8, "notcovered": 0, "percent": 100},
+            "functions": {"count": 2, "covered": 2, "percent": 100}
+        }}], "totals": {}}], "type": "llvm.coverage.json.export", "version": "2.0.1"}"#;
+        let export: Export = serde_json::from_str(export).unwrap();
+        let file = &export.data[0].files[0];
+        assert_eq!(file.filename, PathBuf::from("/p/src/lib.rs"));
+        assert_eq!(file.summary.lines.percent(), 75.);
+        assert_eq!(file.summary.regions.percent(), 100.);
+        assert_eq!(Counts::default().percent(), 100.);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn helpers_are_limited() {
+        let dir = tempfile::tempdir().unwrap();
+        let context = CommandContext::new(dir.path(), &[]);
+        let measure = Measure {
+            toolchain: &Toolchain::Empty,
+            context: &context,
+            limits: Limits {
+                timeout: Some(Duration::from_secs(10)),
+                memory: Some(256 << 20),
+                cpu: Some(5),
+            },
+            deadline: None,
+            target_dir: dir.path(),
+            profiles: dir.path(),
+        };
+        let stdout = measure
+            .run(
+                process::Command::new("sh").args(["-c", "ulimit -v; ulimit -t"]),
+                &OutputTail::new(false),
+            )
+            .unwrap();
+        assert_eq!(String::from_utf8(stdout).unwrap(), "262144\n5\n");
+    }
+}
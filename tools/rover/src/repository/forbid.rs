@@ -0,0 +1,595 @@
+use super::{
+    context::CommandContext,
+    source::{SourceFile, Usage, UsageKind},
+};
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::{fmt, fs, path::Path};
+
+/// The attribute required by `forbid-unsafe`, it makes the compiler reject unsafe code.
+const FORBID_UNSAFE_LINE: &str = "#![forbid(unsafe_code)]";
+const NO_STD_LINE: &str = "#![no_std]";
+
+/// Collection types banned by `forbid-collections`: the module of `std::collections`
+/// they are defined in and their name.
+const FORBIDDEN_COLLECTIONS: [(&str, &str); 7] = [
+    ("btree_map", "BTreeMap"),
+    ("btree_set", "BTreeSet"),
+    ("hash_map", "HashMap"),
+    ("hash_set", "HashSet"),
+    ("vec_deque", "VecDeque"),
+    ("linked_list", "LinkedList"),
+    ("binary_heap", "BinaryHeap"),
+];
+
+/// Items that must not appear in user files, or the only ones that may appear
+/// in the allow-list mode. Paths are matched by prefix, so `std::sync` covers
+/// `std::sync::Mutex`, and `core` or `alloc` are the same as `std`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(from = "ForbidYml")]
+pub struct ForbidPolicy {
+    allow_list: bool,
+    paths: Vec<Vec<String>>,
+    macros: Vec<Vec<String>>,
+    crates: Vec<String>,
+}
+
+/// A forbidden item found in a user file.
+#[derive(Debug)]
+pub struct Violation {
+    file: String,
+    line: usize,
+    column: usize,
+    message: String,
+}
+
+impl Violation {
+    fn new(context: &CommandContext, path: &Path, line: usize, column: usize) -> Self {
+        Self {
+            file: path
+                .strip_prefix(context.get_workdir())
+                .unwrap_or(path)
+                .display()
+                .to_string(),
+            line,
+            // Columns of spans start from zero, but editors count them from one.
+            column: column + 1,
+            message: String::new(),
+        }
+    }
+
+    fn at_usage(context: &CommandContext, file: &SourceFile, usage: &Usage, verdict: &str) -> Self {
+        let what = match usage.kind {
+            UsageKind::Path => "path",
+            UsageKind::Macro => "macro",
+            UsageKind::Crate => "crate",
+        };
+        Self {
+            message: format!("{what} `{}` {verdict}", usage.path_string()),
+            ..Self::new(
+                context,
+                file.path(),
+                usage.location.line,
+                usage.location.column,
+            )
+        }
+    }
+}
+
+/// The `forbid` section of the config.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ForbidYml {
+    #[serde(default)]
+    mode: ForbidMode,
+    #[serde(default)]
+    paths: Vec<String>,
+    #[serde(default)]
+    macros: Vec<String>,
+    #[serde(default)]
+    crates: Vec<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum ForbidMode {
+    #[default]
+    DenyList,
+    AllowList,
+}
+
+impl From<ForbidYml> for ForbidPolicy {
+    fn from(yml: ForbidYml) -> Self {
+        Self {
+            allow_list: matches!(yml.mode, ForbidMode::AllowList),
+            paths: yml.paths.iter().map(|path| split_path(path)).collect(),
+            macros: yml.macros.iter().map(|path| split_path(path)).collect(),
+            crates: yml.crates,
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.file, self.line, self.column, self.message
+        )
+    }
+}
+
+impl ForbidPolicy {
+    /// The policy of `forbid-collections`.
+    fn collections() -> Self {
+        let mut paths = vec![split_path("std::vec::Vec")];
+        for (module, name) in FORBIDDEN_COLLECTIONS {
+            paths.push(split_path(&format!("std::collections::{name}")));
+            paths.push(split_path(&format!("std::collections::{module}::{name}")));
+        }
+        Self {
+            paths,
+            macros: vec![split_path("std::vec")],
+            ..Self::default()
+        }
+    }
+
+    pub fn check(&self, context: &CommandContext) -> Result<()> {
+        check_user_files(
+            context,
+            |context, file| self.violations(context, file),
+            |_, content| self.text_violations(content),
+        )
+    }
+
+    /// Line by line check of a file that failed to parse: only the last segments of the
+    /// listed items are looked for, so names from `use` aliases are missed.
+    fn text_violations(&self, content: &str) -> Vec<(usize, String)> {
+        if self.allow_list {
+            return vec![(1, "allowed items can't be checked".to_string())];
+        }
+        let mut words = Vec::new();
+        for path in &self.paths {
+            words.push((path.last().unwrap().clone(), path.join("::")));
+        }
+        for path in &self.macros {
+            let name = path.last().unwrap();
+            words.push((format!("{name}!"), format!("{}!", path.join("::"))));
+        }
+        for name in &self.crates {
+            words.push((format!("{name}::"), name.clone()));
+        }
+        text_lines(content)
+            .flat_map(|(line, code)| {
+                words
+                    .iter()
+                    .filter(move |(word, _)| contains_word(code, word))
+                    .map(move |(_, item)| (line, format!("`{item}` is forbidden")))
+            })
+            .collect()
+    }
+
+    fn violations(&self, context: &CommandContext, file: &SourceFile) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        let mut last_location = None;
+        for usage in file.usages() {
+            // A path is reported once even if several of its possible resolutions are forbidden.
+            if last_location == Some(usage.location) {
+                continue;
+            }
+            let listed = self.lists(&usage);
+            // Prelude items and names from glob imports are always allowed, but the
+            // glob imports themselves are checked.
+            let (violated, verdict) = if self.allow_list {
+                let violated = !listed && !usage.implicit && usage.external;
+                (violated, "is not allowed")
+            } else {
+                (listed, "is forbidden")
+            };
+            if violated {
+                last_location = Some(usage.location);
+                violations.push(Violation::at_usage(context, file, &usage, verdict));
+            }
+        }
+        violations
+    }
+
+    fn lists(&self, usage: &Usage) -> bool {
+        let path = usage.canonical_path();
+        let matches = |prefixes: &[Vec<String>]| {
+            prefixes.iter().any(|prefix| {
+                path.len() >= prefix.len() && path.iter().zip(prefix).all(|(a, b)| a == b)
+            })
+        };
+        let listed = match usage.kind {
+            UsageKind::Path => matches(&self.paths),
+            UsageKind::Macro => matches(&self.macros),
+            UsageKind::Crate => false,
+        };
+        listed
+            || usage
+                .crate_name()
+                .is_some_and(|name| self.crates.iter().any(|c| c == name))
+    }
+}
+
+/// Fail if a user file has no `#![forbid(unsafe_code)]` or there's any unsafe code in it.
+pub fn forbid_unsafe(context: &CommandContext) -> Result<()> {
+    check_user_files(context, unsafe_violations, |_, content| {
+        // The attribute is enough, the compiler will reject the rest.
+        if text_lines(content).any(|(_, code)| code.trim() == FORBID_UNSAFE_LINE) {
+            vec![]
+        } else {
+            vec![(1, format!("file has no {FORBID_UNSAFE_LINE} line"))]
+        }
+    })
+}
+
+/// Fail if a crate root among user files has no `#![no_std]` or if any user file
+/// refers to `std` explicitly.
+pub fn forbid_std(context: &CommandContext) -> Result<()> {
+    check_user_files(context, std_violations, |path, content| {
+        let mut violations = Vec::new();
+        if is_crate_root(path) && !text_lines(content).any(|(_, code)| code.trim() == NO_STD_LINE) {
+            violations.push((1, format!("file has no {NO_STD_LINE} line")));
+        }
+        for (line, code) in text_lines(content) {
+            if contains_word(code, "std::") || code.contains("extern crate std") {
+                violations.push((line, "`std` is forbidden".to_string()));
+            }
+        }
+        violations
+    })
+}
+
+/// Fail if user files refer to `Vec`, `vec!` or any collection of `std::collections`.
+pub fn forbid_collections(context: &CommandContext) -> Result<()> {
+    ForbidPolicy::collections().check(context)
+}
+
+fn unsafe_violations(context: &CommandContext, file: &SourceFile) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    if !file.forbids_unsafe_code() {
+        violations.push(Violation {
+            message: format!("file has no {FORBID_UNSAFE_LINE} attribute"),
+            ..Violation::new(context, file.path(), 1, 0)
+        });
+    }
+    violations.extend(
+        file.unsafe_code()
+            .into_iter()
+            .map(|(location, what)| Violation {
+                message: format!("{what} is forbidden"),
+                ..Violation::new(context, file.path(), location.line, location.column)
+            }),
+    );
+    violations
+}
+
+fn std_violations(context: &CommandContext, file: &SourceFile) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    if is_crate_root(file.path()) && !file.has_inner_attribute("no_std") {
+        violations.push(Violation {
+            message: "crate root has no #![no_std] attribute".to_string(),
+            ..Violation::new(context, file.path(), 1, 0)
+        });
+    }
+    for usage in file.usages() {
+        if !usage.implicit && usage.path[0] == "std" {
+            violations.push(Violation::at_usage(context, file, &usage, "is forbidden"));
+        }
+    }
+    violations
+}
+
+fn is_crate_root(path: &Path) -> bool {
+    let in_bin = path
+        .parent()
+        .and_then(Path::file_name)
+        .is_some_and(|dir| dir == "bin");
+    in_bin || matches!(path.file_name(), Some(name) if name == "lib.rs" || name == "main.rs")
+}
+
+/// Split a path like `std::mem::transmute`, replacing `core` and `alloc` with `std`.
+fn split_path(path: &str) -> Vec<String> {
+    path.trim_start_matches("::")
+        .split("::")
+        .enumerate()
+        .map(|(i, segment)| match segment.trim() {
+            "core" | "alloc" if i == 0 => "std".to_string(),
+            segment => segment.to_string(),
+        })
+        .collect()
+}
+
+/// Lines of the file without `//` comments, numbered from one.
+fn text_lines(content: &str) -> impl Iterator<Item = (usize, &str)> {
+    content.lines().enumerate().map(|(i, line)| {
+        let code = line.find("//").map_or(line, |pos| &line[..pos]);
+        (i + 1, code)
+    })
+}
+
+/// Whether the word occurs in the line not as a part of a longer identifier.
+fn contains_word(line: &str, word: &str) -> bool {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    line.match_indices(word).any(|(pos, _)| {
+        let glued_before = line[..pos].ends_with(is_ident);
+        let glued_after =
+            word.ends_with(is_ident) && line[pos + word.len()..].starts_with(is_ident);
+        !glued_before && !glued_after
+    })
+}
+
+/// Parse every user file and fail with the list of violations if there are any.
+/// Files that fail to parse are checked line by line with `check_text`, which returns
+/// line numbers and messages.
+fn check_user_files(
+    context: &CommandContext,
+    check: impl Fn(&CommandContext, &SourceFile) -> Vec<Violation>,
+    check_text: impl Fn(&Path, &str) -> Vec<(usize, String)>,
+) -> Result<()> {
+    let mut violations = Vec::new();
+    let mut notes = Vec::new();
+    for path in context.get_user_files() {
+        if path.extension().is_none_or(|ext| ext != "rs") {
+            continue;
+        }
+        let content =
+            fs::read_to_string(path).with_context(|| format!("failed to read {path:?}"))?;
+        match SourceFile::parse_str(path, &content) {
+            Ok(file) => violations.extend(check(context, &file)),
+            Err(err) => {
+                let text_violations = check_text(path, &content);
+                if !text_violations.is_empty() {
+                    notes.push(format!("{err:#}, the file was checked line by line"));
+                }
+                violations.extend(
+                    text_violations
+                        .into_iter()
+                        .map(|(line, message)| Violation {
+                            message,
+                            ..Violation::new(context, path, line, 0)
+                        }),
+                );
+            }
+        }
+    }
+    if violations.is_empty() {
+        return Ok(());
+    }
+    let list: Vec<_> = violations.iter().map(Violation::to_string).collect();
+    bail!(
+        "found {} forbidden item(s):\n{}{}",
+        violations.len(),
+        list.join("\n"),
+        notes
+            .iter()
+            .map(|note| format!("\nnote: {note}"))
+            .collect::<String>()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn check(
+        name: &str,
+        source: &str,
+        check: impl Fn(&CommandContext, &SourceFile) -> Vec<Violation>,
+    ) -> Vec<String> {
+        let context = CommandContext::new(Path::new("/problem"), &[]);
+        let path = PathBuf::from("/problem/src").join(name);
+        let file = SourceFile::parse_str(&path, source).unwrap();
+        check(&context, &file)
+            .iter()
+            .map(Violation::to_string)
+            .collect()
+    }
+
+    fn collection_violations(context: &CommandContext, file: &SourceFile) -> Vec<Violation> {
+        ForbidPolicy::collections().violations(context, file)
+    }
+
+    fn policy(yml: &str) -> ForbidPolicy {
+        serde_yaml::from_str(yml).unwrap()
+    }
+
+    #[test]
+    fn collections_in_comments_and_identifiers_are_fine() {
+        let source = r#"
+            // Vec and HashMap are forbidden here.
+            struct Vector { len: usize }
+            fn vec_len(vector: &Vector) -> usize { vector.len }
+            const NAME: &str = "HashMap";
+        "#;
+        assert!(check("lib.rs", source, collection_violations).is_empty());
+    }
+
+    #[test]
+    fn collections_are_resolved() {
+        let source = r#"
+            use std::collections::*;
+            use std::collections::{hash_map, BTreeMap as Map};
+            use alloc::collections::VecDeque;
+
+            fn f() -> Map<i32, i32> {
+                let set = HashSet::new();
+                let map: hash_map::HashMap<i32, i32> = Default::default();
+                let deque = VecDeque::<i32>::new();
+                let v = vec![0; 10];
+                let w = std::vec::Vec::<u8>::new();
+                println!("{:?}", Vec::<i32>::new());
+                todo!()
+            }
+        "#;
+        assert_eq!(
+            check("lib.rs", source, collection_violations),
+            [
+                "src/lib.rs:3:46: path `std::collections::BTreeMap` is forbidden",
+                "src/lib.rs:4:37: path `alloc::collections::VecDeque` is forbidden",
+                "src/lib.rs:6:23: path `std::collections::BTreeMap` is forbidden",
+                "src/lib.rs:7:27: path `std::collections::HashSet::new` is forbidden",
+                "src/lib.rs:8:26: path `std::collections::hash_map::HashMap` is forbidden",
+                "src/lib.rs:9:29: path `alloc::collections::VecDeque::new` is forbidden",
+                "src/lib.rs:10:25: macro `std::vec` is forbidden",
+                "src/lib.rs:11:25: path `std::vec::Vec::new` is forbidden",
+                "src/lib.rs:12:34: path `std::vec::Vec::new` is forbidden",
+            ]
+        );
+    }
+
+    #[test]
+    fn local_items_shadow_prelude() {
+        let source = r#"
+            struct Vec<T>(T);
+            fn f() -> Vec<i32> { Vec(0) }
+        "#;
+        assert!(check("lib.rs", source, collection_violations).is_empty());
+    }
+
+    #[test]
+    fn unsafe_code_is_found() {
+        let source = r#"
+            #![forbid(unsafe_code)] // unsafe { comments are fine }
+            unsafe fn f() {}
+            unsafe impl Send for S {}
+            fn g() {
+                let s = "unsafe";
+                unsafe { f() }
+            }
+            #[no_mangle]
+            extern "C" fn h() {}
+            macro_rules! m { () => { unsafe { f() } } }
+        "#;
+        assert_eq!(
+            check("lib.rs", source, unsafe_violations),
+            [
+                "src/lib.rs:3:13: unsafe fn is forbidden",
+                "src/lib.rs:4:13: unsafe impl is forbidden",
+                "src/lib.rs:7:17: unsafe block is forbidden",
+                "src/lib.rs:9:15: attribute allowing unsafe code is forbidden",
+                "src/lib.rs:11:38: unsafe in macro is forbidden",
+            ]
+        );
+        assert_eq!(
+            check("lib.rs", "fn f() {}", unsafe_violations),
+            ["src/lib.rs:1:1: file has no #![forbid(unsafe_code)] attribute"]
+        );
+        let source = "#![forbid(dead_code, unsafe_code)]\nfn f() {}";
+        assert!(check("module.rs", source, unsafe_violations).is_empty());
+    }
+
+    #[test]
+    fn unparsable_files_are_checked_line_by_line() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let files = [dir.path().join("lib.rs")];
+        let (path, context) = (&files[0], CommandContext::new(dir.path(), &files));
+        // Valid Rust that syn fails to parse.
+        let source = "impl T for str {\n    type I<'a> where Self: 'a = Chars<'a>;\n}\n";
+        fs::write(path, source)?;
+        assert!(forbid_collections(&context).is_ok());
+        let err = forbid_unsafe(&context).unwrap_err().to_string();
+        assert!(err.contains("lib.rs:1:1: file has no #![forbid(unsafe_code)] line"));
+        assert!(err.contains("the file was checked line by line"));
+
+        fs::write(
+            path,
+            format!("#![forbid(unsafe_code)]\n{source}// Vec\nfn f(v: Vector) -> Vec<u8> {{ vec![] }}\n"),
+        )?;
+        assert!(forbid_unsafe(&context).is_ok());
+        let err = forbid_collections(&context).unwrap_err().to_string();
+        assert!(err.contains("found 2 forbidden item(s)"));
+        assert!(err.contains("lib.rs:6:1: `std::vec::Vec` is forbidden"));
+        assert!(err.contains("lib.rs:6:1: `std::vec!` is forbidden"));
+        assert!(policy("mode: allow-list").check(&context).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn std_is_found() {
+        let source = r#"
+            #![allow(dead_code)]
+            #![no_std]
+            extern crate std;
+            use core::mem;
+            fn f() -> Option<std::string::String> { None }
+        "#;
+        assert_eq!(
+            check("lib.rs", source, std_violations),
+            [
+                "src/lib.rs:4:26: crate `std` is forbidden",
+                "src/lib.rs:6:30: path `std::string::String` is forbidden",
+            ]
+        );
+        assert_eq!(
+            check("lib.rs", "fn f() {}", std_violations),
+            ["src/lib.rs:1:1: crate root has no #![no_std] attribute"]
+        );
+        assert!(check("module.rs", "fn f() {}", std_violations).is_empty());
+    }
+
+    #[test]
+    fn policy_forbids_listed_items() {
+        let policy = policy(
+            r#"
+            paths: [std::sync::Mutex, std::rc, core::mem::transmute]
+            macros: [std::dbg]
+            crates: [hashbrown]
+            "#,
+        );
+        let source = r#"
+            use std::{rc::Rc, sync::{Arc, Mutex as Lock}};
+            use std::mem;
+            fn f(x: Lock<i32>) -> Arc<i32> {
+                let y: u64 = unsafe { mem::transmute(1.0f64) };
+                let map = hashbrown::HashMap::<i32, i32>::new();
+                dbg!(Rc::new(y));
+                todo!()
+            }
+        "#;
+        assert_eq!(
+            check("lib.rs", source, |context, file| policy
+                .violations(context, file)),
+            [
+                "src/lib.rs:2:27: path `std::rc::Rc` is forbidden",
+                "src/lib.rs:2:43: path `std::sync::Mutex` is forbidden",
+                "src/lib.rs:4:21: path `std::sync::Mutex` is forbidden",
+                "src/lib.rs:5:39: path `std::mem::transmute` is forbidden",
+                "src/lib.rs:6:27: path `hashbrown::HashMap::new` is forbidden",
+                "src/lib.rs:7:17: macro `std::dbg` is forbidden",
+                "src/lib.rs:7:22: path `std::rc::Rc::new` is forbidden",
+            ]
+        );
+    }
+
+    #[test]
+    fn policy_allows_listed_items_only() {
+        let policy = policy(
+            r#"
+            mode: allow-list
+            paths: [std::cell::RefCell, std::rc::Rc]
+            crates: [itertools]
+            "#,
+        );
+        let source = r#"
+            use std::{cell::RefCell, rc::{Rc, Weak}};
+            use itertools::Itertools;
+            struct Node<T> { next: Option<Rc<RefCell<Node<T>>>>, value: T }
+            impl<T: Default> Node<T> {
+                fn new() -> Self { Self { next: None, value: T::default() } }
+                fn len(&self) -> usize { std::iter::successors(Some(1), |x| Some(x + 1)).count() }
+            }
+        "#;
+        assert_eq!(
+            check("lib.rs", source, |context, file| policy
+                .violations(context, file)),
+            [
+                "src/lib.rs:2:47: path `std::rc::Weak` is not allowed",
+                "src/lib.rs:7:42: path `std::iter::successors` is not allowed",
+            ]
+        );
+    }
+}
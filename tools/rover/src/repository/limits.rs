@@ -0,0 +1,376 @@
+use anyhow::{bail, Context, Result};
+use serde::{de, Deserialize, Deserializer};
+use serde_yaml::Value;
+use std::{
+    fmt, io,
+    process::{self, Child, ExitStatus},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Once,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Limits of the commands: `timeout`, `memory-limit` and `cpu-limit` keys of a command or a step.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Limits {
+    pub timeout: Option<Duration>,
+    /// Address space limit in bytes of every process the command launches.
+    pub memory: Option<u64>,
+    /// CPU time limit in seconds of every process the command launches.
+    pub cpu: Option<u64>,
+}
+
+/// The error of a command killed after its timeout expired.
+#[derive(Debug)]
+pub struct TimedOut {
+    pub timeout: Duration,
+}
+
+/// The error of a command killed because the testing was cancelled.
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl Limits {
+    /// Limits of `self` with the unset ones taken from `defaults`.
+    pub fn or(self, defaults: Self) -> Self {
+        Self {
+            timeout: self.timeout.or(defaults.timeout),
+            memory: self.memory.or(defaults.memory),
+            cpu: self.cpu.or(defaults.cpu),
+        }
+    }
+}
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "timed out after {:.1}s", self.timeout.as_secs_f64())
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Deserialize `timeout` key of a command or a step.
+pub fn timeout<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    let value = Value::deserialize(deserializer)?;
+    parse_duration(&value)
+        .map(Some)
+        .map_err(|err| de::Error::custom(format!("invalid timeout: {err:#}")))
+}
+
+/// Deserialize `memory-limit` key of a command or a step.
+pub fn memory<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    let value = Value::deserialize(deserializer)?;
+    parse_memory(&value)
+        .map(Some)
+        .map_err(|err| de::Error::custom(format!("invalid memory-limit: {err:#}")))
+}
+
+/// Deserialize `cpu-limit` key of a command or a step.
+pub fn cpu<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    let value = Value::deserialize(deserializer)?;
+    parse_duration(&value)
+        .map(|cpu| Some(cpu.as_secs().max(1)))
+        .map_err(|err| de::Error::custom(format!("invalid cpu-limit: {err:#}")))
+}
+
+/// Split a string like `30s` or `2 G` into the number and the trimmed unit.
+fn split_number<'a>(value: &'a str, what: &str) -> Result<(u64, &'a str)> {
+    let (number, unit) =
+        value.split_at(value.len() - value.trim_start_matches(char::is_numeric).len());
+    let number = number
+        .parse()
+        .with_context(|| format!("{what} \"{value}\" does not start with a number"))?;
+    Ok((number, unit.trim()))
+}
+
+/// Either a number of seconds or a number with `s`, `m` or `h` suffix.
+fn parse_duration(value: &Value) -> Result<Duration> {
+    if let Some(secs) = value.as_f64() {
+        if secs < 0. {
+            bail!("duration is negative")
+        }
+        return Duration::try_from_secs_f64(secs).context("duration is too long");
+    }
+    let value = value
+        .as_str()
+        .context("duration is neither a number nor a string")?;
+    let (number, unit) = split_number(value, "duration")?;
+    let multiplier = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        unit => bail!("unknown duration unit \"{unit}\""),
+    };
+    number
+        .checked_mul(multiplier)
+        .map(Duration::from_secs)
+        .context("duration is too long")
+}
+
+/// Either a number of mebibytes or a number with `K`, `M` or `G` suffix.
+fn parse_memory(value: &Value) -> Result<u64> {
+    let (number, multiplier) = match value.as_u64() {
+        Some(mebibytes) => (mebibytes, 1 << 20),
+        None => {
+            let value = value
+                .as_str()
+                .context("memory is neither a number nor a string")?;
+            let (number, unit) = split_number(value, "memory")?;
+            let multiplier = match unit {
+                "K" | "KB" | "KiB" => 1 << 10,
+                "" | "M" | "MB" | "MiB" => 1 << 20,
+                "G" | "GB" | "GiB" => 1 << 30,
+                unit => bail!("unknown memory unit \"{unit}\""),
+            };
+            (number, multiplier)
+        }
+    };
+    number
+        .checked_mul(multiplier)
+        .context("memory is too large")
+}
+
+/// Apply memory and CPU limits to the command. The limits are inherited by every process
+/// the command launches, so for `cargo test` they also cover cargo itself, rustc and the
+/// linker, each of them limited separately. The command starts in its own process group
+/// without stdin, so that the whole tree can be killed on timeout, cancellation or interrupt.
+pub fn apply(cmd: &mut process::Command, limits: Limits) {
+    cmd.stdin(process::Stdio::null());
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        cmd.process_group(0);
+    }
+    #[cfg(target_os = "linux")]
+    if limits.memory.is_some() || limits.cpu.is_some() {
+        use std::os::unix::process::CommandExt;
+        // SAFETY: setrlimit is async-signal-safe and nothing is allocated in the closure.
+        unsafe {
+            cmd.pre_exec(move || {
+                if let Some(memory) = limits.memory {
+                    set_rlimit(libc::RLIMIT_AS, memory)?;
+                }
+                if let Some(cpu) = limits.cpu {
+                    set_rlimit(libc::RLIMIT_CPU, cpu)?;
+                }
+                Ok(())
+            });
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    if limits.memory.is_some() || limits.cpu.is_some() {
+        eprintln!("Memory and CPU limits are supported on Linux only, ignoring them");
+    }
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type Resource = libc::__rlimit_resource_t;
+#[cfg(all(target_os = "linux", not(target_env = "gnu")))]
+type Resource = libc::c_int;
+
+#[cfg(target_os = "linux")]
+fn set_rlimit(resource: Resource, value: u64) -> io::Result<()> {
+    let limit = libc::rlimit {
+        rlim_cur: value,
+        rlim_max: value,
+    };
+    // SAFETY: the pointer is valid for the duration of the call.
+    if unsafe { libc::setrlimit(resource, &limit) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Set by the SIGINT and SIGTERM handler, see `handle_interrupts`.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Make SIGINT and SIGTERM only set a flag instead of killing rover. The waited commands
+/// are then killed along with their process groups and fail with [`Cancelled`], so that
+/// rover cleans up after them, e.g. removes injected hidden tests, before exiting.
+pub fn handle_interrupts() {
+    #[cfg(unix)]
+    {
+        static HANDLER: Once = Once::new();
+        HANDLER.call_once(|| {
+            extern "C" fn on_interrupt(_: libc::c_int) {
+                INTERRUPTED.store(true, Ordering::SeqCst);
+            }
+            let handler = on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            for signal in [libc::SIGINT, libc::SIGTERM] {
+                // SAFETY: the handler only stores to an atomic, which is async-signal-safe.
+                unsafe {
+                    // Keep ignoring the signal if it was ignored, e.g. when running in background.
+                    if libc::signal(signal, handler) == libc::SIG_IGN {
+                        libc::signal(signal, libc::SIG_IGN);
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// Whether rover has been interrupted since `handle_interrupts`.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Wait for the child, killing its process group if it doesn't finish in time,
+/// `cancel` is set or rover is interrupted.
+pub fn wait(
+    child: &mut Child,
+    timeout: Option<Duration>,
+    cancel: Option<&AtomicBool>,
+) -> Result<ExitStatus> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    loop {
+        if let Some(status) = child.try_wait().context("failed to wait for command")? {
+            return Ok(status);
+        }
+        let error = match (deadline, timeout) {
+            (Some(deadline), Some(timeout)) if Instant::now() >= deadline => {
+                Some(TimedOut { timeout }.into())
+            }
+            _ if interrupted() || cancel.is_some_and(|cancel| cancel.load(Ordering::SeqCst)) => {
+                Some(Cancelled.into())
+            }
+            _ => None,
+        };
+        if let Some(error) = error {
+            kill_tree(child);
+            child.wait().context("failed to wait for killed command")?;
+            return Err(error);
+        }
+        let remaining = deadline.map_or(WAIT_POLL_INTERVAL, |deadline| {
+            deadline.saturating_duration_since(Instant::now())
+        });
+        thread::sleep(WAIT_POLL_INTERVAL.min(remaining));
+    }
+}
+
+fn kill_tree(child: &mut Child) {
+    #[cfg(unix)]
+    {
+        // The child is the leader of its own process group, see `apply`.
+        // SAFETY: kill has no memory safety preconditions.
+        if unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) } == 0 {
+            return;
+        }
+    }
+    // The process has already exited or we are not on Unix.
+    let _ = child.kill();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(yaml: &str) -> Value {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn durations() {
+        for (yaml, secs) in [
+            ("30", 30.),
+            ("1.5", 1.5),
+            ("30s", 30.),
+            ("2m", 120.),
+            ("1 h", 3600.),
+            ("15", 15.),
+        ] {
+            assert_eq!(
+                parse_duration(&value(yaml)).unwrap(),
+                Duration::from_secs_f64(secs),
+                "{yaml}"
+            );
+        }
+        for yaml in [
+            "-1",
+            "s",
+            "10d",
+            "[1]",
+            "1.5m",
+            ".inf",
+            ".nan",
+            "1e30",
+            "99999999999999999999h",
+            "9999999999999999999h",
+        ] {
+            assert!(parse_duration(&value(yaml)).is_err(), "{yaml}");
+        }
+    }
+
+    #[test]
+    fn memory() {
+        for (yaml, bytes) in [
+            ("512", 512 << 20),
+            ("64K", 64 << 10),
+            ("100 MiB", 100 << 20),
+            ("2G", 2 << 30),
+            ("3GB", 3 << 30),
+        ] {
+            assert_eq!(parse_memory(&value(yaml)).unwrap(), bytes, "{yaml}");
+        }
+        for yaml in [
+            "-1",
+            "G",
+            "1T",
+            "1.5G",
+            "{}",
+            "17179869184G",
+            "17592186044416",
+        ] {
+            assert!(parse_memory(&value(yaml)).is_err(), "{yaml}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn timeout_kills_group() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("marker");
+        // The background child would create the marker if it outlived the timeout.
+        let mut cmd = process::Command::new("sh");
+        cmd.arg("-c")
+            .arg(format!("(sleep 1; touch {}) & sleep 10", marker.display()));
+        apply(&mut cmd, Limits::default());
+        let mut child = cmd.spawn().unwrap();
+        let start = Instant::now();
+        let timeout = Duration::from_millis(200);
+        let err = wait(&mut child, Some(timeout), None).unwrap_err();
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(err.downcast_ref::<TimedOut>().unwrap().timeout, timeout);
+        thread::sleep(Duration::from_millis(1500));
+        assert!(!marker.exists());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn rlimits_are_applied() {
+        let mut cmd = process::Command::new("sh");
+        cmd.args(["-c", "ulimit -v; ulimit -t"])
+            .stdout(process::Stdio::piped());
+        let limits = Limits {
+            timeout: None,
+            memory: Some(512 << 20),
+            cpu: Some(3),
+        };
+        apply(&mut cmd, limits);
+        let output = cmd.output().unwrap();
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "524288\n3\n");
+    }
+}
@@ -1,8 +1,16 @@
+mod bench;
+pub mod capture;
+pub mod check;
 mod command;
-mod config;
+pub mod config;
 mod context;
 mod copying;
+mod coverage;
+mod forbid;
+pub mod limits;
 pub mod problem;
 pub mod repo;
+mod source;
 mod step;
 mod toolchain;
+mod yml;
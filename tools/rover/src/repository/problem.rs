@@ -1,13 +1,13 @@
 use super::config::Config;
 use super::repo::PROBLEMS_FOLDER;
-use crate::{launch_git, repository::copying::copy_files};
+use crate::{launch_git, repository::copying::copy_files, util::git::git_output};
 use anyhow::{bail, Result};
 use std::{
     path::{Path, PathBuf},
     process,
 };
 
-const DEFAULT_YML_NAME: &str = ".config.yml";
+pub const DEFAULT_YML_NAME: &str = ".config.yml";
 
 pub struct Problem {
     path: PathBuf,
@@ -20,6 +20,10 @@ impl Problem {
         }
     }
 
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
     pub fn title(&self) -> String {
         self.path.file_name().unwrap().to_str().unwrap().to_string()
     }
@@ -45,20 +49,20 @@ impl Problem {
             .join(self.title())
     }
 
-    pub fn config(&self) -> Result<Config> {
-        Config::from_yml(&self.path.join(DEFAULT_YML_NAME))
+    /// Allowed files of the problem relative to the root of the course
+    /// repository, the same as in the solutions repository.
+    pub fn repo_user_files(&self) -> Result<Vec<PathBuf>> {
+        let relative_path = self.relative_path();
+        Ok(self
+            .config()?
+            .get_relative_user_files()
+            .iter()
+            .map(|file| relative_path.join(file))
+            .collect())
     }
 
-    pub fn launch_all_steps(&self) -> Result<()> {
-        let config = self.config()?;
-        let toolchain = config.get_toolchain();
-        let context = config.get_command_context();
-        for step in config.get_steps() {
-            for command in step.commands() {
-                toolchain.run_command(command, &context)?;
-            }
-        }
-        Ok(())
+    pub fn config(&self) -> Result<Config> {
+        Config::from_yml(&self.path.join(DEFAULT_YML_NAME))
     }
 
     pub fn move_solution_files_from(
@@ -88,22 +92,30 @@ impl Problem {
         let branch_name = self.branch_name();
         let solutions_problem_path = solutions_repo.join(relative_path);
         let repository_problem_path = self.path.clone();
-        if !launch_git!(solutions_repo, "checkout", &branch_name)
-            && !launch_git!(
+        // Probed quietly, so that git doesn't print errors for the expected misses.
+        let exists = |reference: &str| -> Result<bool> {
+            Ok(git_output(
                 solutions_repo,
-                "checkout",
-                "-b",
-                &branch_name,
-                "origin/master"
-            )
-            && !launch_git!(
-                solutions_repo,
-                "checkout",
-                "-b",
-                &branch_name,
-                "origin/main"
-            )
-        {
+                &["rev-parse", "--verify", "--quiet", reference],
+            )?
+            .is_some())
+        };
+        let checked_out = if exists(&format!("refs/heads/{branch_name}"))? {
+            launch_git!(solutions_repo, "checkout", &branch_name)
+        } else {
+            let mut base = None;
+            for remote_branch in ["origin/master", "origin/main"] {
+                if exists(remote_branch)? {
+                    base = Some(remote_branch);
+                    break;
+                }
+            }
+            match base {
+                Some(base) => launch_git!(solutions_repo, "checkout", "-b", &branch_name, base),
+                None => false,
+            }
+        };
+        if !checked_out {
             bail!("failed to both checkout and create branch in solutions repository")
         }
         copy_files(
@@ -1,6 +1,9 @@
-use super::problem::Problem;
+use super::problem::{Problem, DEFAULT_YML_NAME};
 use crate::compose;
 use anyhow::{bail, Context, Result};
+use glob::glob;
+#[cfg(test)]
+use std::fs;
 use std::path::{Path, PathBuf};
 
 pub const COMPOSE_CONFIG: &str = ".compose.yml";
@@ -54,6 +57,28 @@ impl Repository {
         }
     }
 
+    /// All the problems of the repository, that is `problems/GROUP/TITLE` with a config, sorted by path.
+    pub fn problems(&self) -> Result<Vec<Problem>> {
+        let pattern = self
+            .path
+            .join(PROBLEMS_FOLDER)
+            .join("*")
+            .join("*")
+            .join(DEFAULT_YML_NAME);
+        let pattern = pattern.to_str().context("non-utf-8 path")?;
+        let mut paths = glob(pattern)
+            .context("pattern is invalid")?
+            .map(|entry| {
+                Ok(entry?
+                    .parent()
+                    .context("config has no parent")?
+                    .to_path_buf())
+            })
+            .collect::<Result<Vec<_>>>()?;
+        paths.sort();
+        Ok(paths.iter().map(|path| Problem::from_path(path)).collect())
+    }
+
     pub fn solutions_repo(&self) -> Result<PathBuf> {
         let path = self.path.parent().unwrap().join(SOLUTIONS_REPO_FOLDER);
         if path.is_dir() {
@@ -67,8 +92,20 @@ impl Repository {
         compose::config::Config::from_yml(&self.path.join(COMPOSE_CONFIG))
     }
 
-    #[allow(unused)]
     pub fn get_path(&self) -> &Path {
         &self.path
     }
 }
+
+/// Create `problems/GROUP/TITLE` with the config in the course repository `mipt-rust`
+/// inside `dir`, creating the repository if needed.
+#[cfg(test)]
+pub fn create_problem(dir: &Path, problem: &str, config: &str) -> Problem {
+    let path = dir
+        .join(REPOSITORY_NAMES[0])
+        .join(PROBLEMS_FOLDER)
+        .join(problem);
+    fs::create_dir_all(&path).unwrap();
+    fs::write(path.join(DEFAULT_YML_NAME), config).unwrap();
+    Problem::from_path(&path.canonicalize().unwrap())
+}
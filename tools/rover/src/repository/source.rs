@@ -0,0 +1,507 @@
+use anyhow::{anyhow, Result};
+use proc_macro2::{LineColumn, Span, TokenStream, TokenTree};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+use syn::{
+    punctuated::Punctuated,
+    spanned::Spanned,
+    visit::{self, Visit},
+    Block, Expr, Token, UseTree,
+};
+
+/// Items that are in scope without a `use`, with the paths they are defined at.
+const PRELUDE: &[(&str, &str)] = &[
+    ("Box", "std::boxed::Box"),
+    ("String", "std::string::String"),
+    ("ToString", "std::string::ToString"),
+    ("Vec", "std::vec::Vec"),
+    ("ToOwned", "std::borrow::ToOwned"),
+    ("Option", "std::option::Option"),
+    ("Some", "std::option::Option::Some"),
+    ("None", "std::option::Option::None"),
+    ("Result", "std::result::Result"),
+    ("Ok", "std::result::Result::Ok"),
+    ("Err", "std::result::Result::Err"),
+    ("Clone", "std::clone::Clone"),
+    ("Copy", "std::marker::Copy"),
+    ("Send", "std::marker::Send"),
+    ("Sync", "std::marker::Sync"),
+    ("Sized", "std::marker::Sized"),
+    ("Unpin", "std::marker::Unpin"),
+    ("Drop", "std::ops::Drop"),
+    ("Fn", "std::ops::Fn"),
+    ("FnMut", "std::ops::FnMut"),
+    ("FnOnce", "std::ops::FnOnce"),
+    ("drop", "std::mem::drop"),
+    ("Default", "std::default::Default"),
+    ("Eq", "std::cmp::Eq"),
+    ("PartialEq", "std::cmp::PartialEq"),
+    ("Ord", "std::cmp::Ord"),
+    ("PartialOrd", "std::cmp::PartialOrd"),
+    ("AsRef", "std::convert::AsRef"),
+    ("AsMut", "std::convert::AsMut"),
+    ("From", "std::convert::From"),
+    ("Into", "std::convert::Into"),
+    ("TryFrom", "std::convert::TryFrom"),
+    ("TryInto", "std::convert::TryInto"),
+    ("Iterator", "std::iter::Iterator"),
+    ("IntoIterator", "std::iter::IntoIterator"),
+    ("DoubleEndedIterator", "std::iter::DoubleEndedIterator"),
+    ("ExactSizeIterator", "std::iter::ExactSizeIterator"),
+    ("Extend", "std::iter::Extend"),
+    ("FromIterator", "std::iter::FromIterator"),
+];
+
+/// Names that may start a path but never refer to a crate.
+const NOT_CRATES: &[&str] = &[
+    "crate", "self", "super", "Self", "bool", "char", "str", "i8", "i16", "i32", "i64", "i128",
+    "isize", "u8", "u16", "u32", "u64", "u128", "usize", "f32", "f64",
+];
+
+/// Attributes that make the `unsafe_code` lint fire.
+const UNSAFE_ATTRIBUTES: [&str; 3] = ["no_mangle", "export_name", "link_section"];
+
+/// Maximal length of a chain of `use` aliases, protects from cycles.
+const MAX_ALIAS_DEPTH: usize = 16;
+
+/// A parsed user file.
+pub struct SourceFile {
+    path: PathBuf,
+    ast: syn::File,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UsageKind {
+    /// A type, a trait, a function or any other item referred by a path.
+    Path,
+    Macro,
+    /// An `extern crate` item.
+    Crate,
+}
+
+/// A reference to an item in a file, e.g. `HashMap::new()` in the scope of
+/// `use std::collections::*` is a usage of `std::collections::HashMap::new`.
+#[derive(Clone, Debug)]
+pub struct Usage {
+    /// The path with `use` aliases resolved.
+    pub path: Vec<String>,
+    pub kind: UsageKind,
+    /// The path is a guess: the name is taken from the prelude or a glob import.
+    pub implicit: bool,
+    /// The path starts with the name of a crate, e.g. `std` or `rand`,
+    /// rather than a local item, a primitive type or a variable.
+    pub external: bool,
+    pub location: LineColumn,
+}
+
+impl SourceFile {
+    pub fn parse_str(path: &Path, content: &str) -> Result<Self> {
+        let ast = syn::parse_file(content).map_err(|err| {
+            let start = err.span().start();
+            anyhow!(
+                "{}:{}:{}: failed to parse: {err}",
+                path.display(),
+                start.line,
+                start.column + 1
+            )
+        })?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            ast,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the file has an inner attribute like `#![no_std]` among the ones at its top.
+    pub fn has_inner_attribute(&self, name: &str) -> bool {
+        self.ast.attrs.iter().any(|attr| {
+            matches!(attr.style, syn::AttrStyle::Inner(_)) && attr.path().is_ident(name)
+        })
+    }
+
+    /// Whether the file has `#![forbid(unsafe_code)]` among the attributes at its top.
+    pub fn forbids_unsafe_code(&self) -> bool {
+        self.ast.attrs.iter().any(|attr| {
+            matches!(attr.style, syn::AttrStyle::Inner(_))
+                && attr.path().is_ident("forbid")
+                && attr
+                    .parse_args_with(Punctuated::<syn::Path, Token![,]>::parse_terminated)
+                    .is_ok_and(|lints| lints.iter().any(|lint| lint.is_ident("unsafe_code")))
+        })
+    }
+
+    /// Locations of unsafe blocks, functions, impls, traits and extern blocks,
+    /// along with attributes like `#[no_mangle]` and `unsafe` in macro bodies.
+    pub fn unsafe_code(&self) -> Vec<(LineColumn, &'static str)> {
+        let mut finder = UnsafeFinder::default();
+        finder.visit_file(&self.ast);
+        finder
+            .found
+            .sort_by_key(|(location, _)| (location.line, location.column));
+        finder.found
+    }
+
+    /// All the paths and macros the file refers to, including the ones in `use` items.
+    pub fn usages(&self) -> Vec<Usage> {
+        let mut scope = Scope::default();
+        scope.visit_file(&self.ast);
+        let mut collector = UsageCollector {
+            scope: &scope,
+            usages: Vec::new(),
+        };
+        collector.visit_file(&self.ast);
+        collector
+            .usages
+            .sort_by_key(|usage| (usage.location.line, usage.location.column));
+        collector.usages
+    }
+}
+
+impl Usage {
+    /// The path with `core` and `alloc` replaced by `std` which re-exports them.
+    pub fn canonical_path(&self) -> Vec<&str> {
+        self.path
+            .iter()
+            .enumerate()
+            .map(|(i, segment)| match segment.as_str() {
+                "core" | "alloc" if i == 0 => "std",
+                segment => segment,
+            })
+            .collect()
+    }
+
+    pub fn crate_name(&self) -> Option<&str> {
+        self.external.then(|| self.path[0].as_str())
+    }
+
+    pub fn path_string(&self) -> String {
+        self.path.join("::")
+    }
+}
+
+#[derive(Default)]
+struct UnsafeFinder {
+    found: Vec<(LineColumn, &'static str)>,
+}
+
+impl UnsafeFinder {
+    fn push(&mut self, span: Span, what: &'static str) {
+        self.found.push((span.start(), what));
+    }
+
+    fn find_in_tokens(&mut self, tokens: TokenStream) {
+        for token in tokens {
+            match token {
+                TokenTree::Ident(ident) if ident == "unsafe" => {
+                    self.push(ident.span(), "unsafe in macro")
+                }
+                TokenTree::Group(group) => self.find_in_tokens(group.stream()),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for UnsafeFinder {
+    fn visit_expr_unsafe(&mut self, expr: &'ast syn::ExprUnsafe) {
+        self.push(expr.unsafe_token.span, "unsafe block");
+        visit::visit_expr_unsafe(self, expr);
+    }
+
+    fn visit_signature(&mut self, sig: &'ast syn::Signature) {
+        if let Some(unsafety) = sig.unsafety {
+            self.push(unsafety.span, "unsafe fn");
+        }
+        visit::visit_signature(self, sig);
+    }
+
+    fn visit_item_impl(&mut self, item: &'ast syn::ItemImpl) {
+        if let Some(unsafety) = item.unsafety {
+            self.push(unsafety.span, "unsafe impl");
+        }
+        visit::visit_item_impl(self, item);
+    }
+
+    fn visit_item_trait(&mut self, item: &'ast syn::ItemTrait) {
+        if let Some(unsafety) = item.unsafety {
+            self.push(unsafety.span, "unsafe trait");
+        }
+        visit::visit_item_trait(self, item);
+    }
+
+    fn visit_item_foreign_mod(&mut self, item: &'ast syn::ItemForeignMod) {
+        self.push(item.abi.extern_token.span, "extern block");
+        visit::visit_item_foreign_mod(self, item);
+    }
+
+    fn visit_attribute(&mut self, attr: &'ast syn::Attribute) {
+        let path = attr.path();
+        if path.is_ident("unsafe") {
+            self.push(path.span(), "unsafe attribute");
+        } else if UNSAFE_ATTRIBUTES.iter().any(|name| path.is_ident(name)) {
+            self.push(path.span(), "attribute allowing unsafe code");
+        }
+        visit::visit_attribute(self, attr);
+    }
+
+    fn visit_macro(&mut self, mac: &'ast syn::Macro) {
+        self.find_in_tokens(mac.tokens.clone());
+        visit::visit_macro(self, mac);
+    }
+}
+
+/// Names introduced by `use` items and item definitions. Scopes of modules and
+/// blocks are not tracked, all the names are considered to be visible in the whole file.
+#[derive(Default)]
+struct Scope {
+    aliases: HashMap<String, Vec<String>>,
+    globs: Vec<Vec<String>>,
+    items: HashSet<String>,
+    macros: HashSet<String>,
+}
+
+impl Scope {
+    fn add_use_tree(&mut self, prefix: &mut Vec<String>, tree: &UseTree) {
+        match tree {
+            UseTree::Path(path) => {
+                prefix.push(path.ident.to_string());
+                self.add_use_tree(prefix, &path.tree);
+                prefix.pop();
+            }
+            UseTree::Name(name) if name.ident == "self" => {
+                if let Some(last) = prefix.last() {
+                    self.aliases.insert(last.clone(), prefix.clone());
+                }
+            }
+            UseTree::Name(name) => {
+                let name = name.ident.to_string();
+                let path = prefix.iter().cloned().chain([name.clone()]).collect();
+                self.aliases.insert(name, path);
+            }
+            UseTree::Rename(rename) if rename.rename == "_" => {}
+            UseTree::Rename(rename) => {
+                let path = match rename.ident.to_string().as_str() {
+                    "self" => prefix.clone(),
+                    name => prefix.iter().cloned().chain([name.to_string()]).collect(),
+                };
+                self.aliases.insert(rename.rename.to_string(), path);
+            }
+            UseTree::Glob(_) => self.globs.push(prefix.clone()),
+            UseTree::Group(group) => {
+                for tree in &group.items {
+                    self.add_use_tree(prefix, tree);
+                }
+            }
+        }
+    }
+
+    fn is_local(&self, name: &str, kind: UsageKind) -> bool {
+        match kind {
+            UsageKind::Macro => self.macros.contains(name),
+            _ => self.items.contains(name),
+        }
+    }
+
+    /// Whether the resolved path starts with a crate name. A single name is
+    /// considered to be a variable or a function argument unless it's an `extern crate`.
+    fn is_external(&self, path: &[String], kind: UsageKind) -> bool {
+        let first = path[0].as_str();
+        !self.is_local(first, kind)
+            && !NOT_CRATES.contains(&first)
+            && (path.len() > 1 || kind == UsageKind::Crate)
+    }
+
+    /// Possible full paths of the path written in the file, explicit ones go first.
+    /// The flag is set for the implicit ones.
+    fn resolve(&self, path: Vec<String>, kind: UsageKind) -> Vec<(Vec<String>, bool)> {
+        let mut path = path;
+        for _ in 0..MAX_ALIAS_DEPTH {
+            match self.aliases.get(&path[0]) {
+                Some(alias) if alias.len() > 1 || alias[0] != path[0] => {
+                    path = alias.iter().chain(&path[1..]).cloned().collect();
+                }
+                _ => break,
+            }
+        }
+        let first = path[0].as_str();
+        if self.aliases.contains_key(first)
+            || self.is_local(first, kind)
+            || NOT_CRATES.contains(&first)
+        {
+            return vec![(path, false)];
+        }
+        let mut resolved = vec![(path.clone(), false)];
+        for glob in &self.globs {
+            resolved.push((glob.iter().chain(&path).cloned().collect(), true));
+        }
+        match kind {
+            UsageKind::Macro if path.len() == 1 => {
+                resolved.push((vec!["std".to_string(), path[0].clone()], true));
+            }
+            UsageKind::Path => {
+                if let Some((_, full)) = PRELUDE.iter().find(|(name, _)| *name == first) {
+                    let full = full.split("::").map(str::to_string);
+                    resolved.push((full.chain(path[1..].iter().cloned()).collect(), true));
+                }
+            }
+            _ => {}
+        }
+        resolved
+    }
+}
+
+impl<'ast> Visit<'ast> for Scope {
+    fn visit_item_use(&mut self, item: &'ast syn::ItemUse) {
+        self.add_use_tree(&mut Vec::new(), &item.tree);
+    }
+
+    fn visit_item_extern_crate(&mut self, item: &'ast syn::ItemExternCrate) {
+        if let Some((_, rename)) = &item.rename {
+            if rename != "_" {
+                let name = item.ident.to_string();
+                self.aliases.insert(rename.to_string(), vec![name]);
+            }
+        }
+    }
+
+    fn visit_generic_param(&mut self, param: &'ast syn::GenericParam) {
+        let ident = match param {
+            syn::GenericParam::Type(param) => &param.ident,
+            syn::GenericParam::Const(param) => &param.ident,
+            syn::GenericParam::Lifetime(_) => return,
+        };
+        self.items.insert(ident.to_string());
+        visit::visit_generic_param(self, param);
+    }
+
+    fn visit_item(&mut self, item: &'ast syn::Item) {
+        let ident = match item {
+            syn::Item::Const(item) => Some(&item.ident),
+            syn::Item::Enum(item) => Some(&item.ident),
+            syn::Item::Fn(item) => Some(&item.sig.ident),
+            syn::Item::Mod(item) => Some(&item.ident),
+            syn::Item::Static(item) => Some(&item.ident),
+            syn::Item::Struct(item) => Some(&item.ident),
+            syn::Item::Trait(item) => Some(&item.ident),
+            syn::Item::TraitAlias(item) => Some(&item.ident),
+            syn::Item::Type(item) => Some(&item.ident),
+            syn::Item::Union(item) => Some(&item.ident),
+            syn::Item::Macro(item) => {
+                if let Some(ident) = &item.ident {
+                    self.macros.insert(ident.to_string());
+                }
+                None
+            }
+            _ => None,
+        };
+        if let Some(ident) = ident {
+            self.items.insert(ident.to_string());
+        }
+        visit::visit_item(self, item);
+    }
+}
+
+struct UsageCollector<'a> {
+    scope: &'a Scope,
+    usages: Vec<Usage>,
+}
+
+impl UsageCollector<'_> {
+    fn push(&mut self, path: Vec<String>, kind: UsageKind, span: Span) {
+        if path.is_empty() {
+            return;
+        }
+        for (path, implicit) in self.scope.resolve(path, kind) {
+            self.usages.push(Usage {
+                external: self.scope.is_external(&path, kind),
+                path,
+                kind,
+                implicit,
+                location: span.start(),
+            });
+        }
+    }
+
+    fn push_use_tree(&mut self, prefix: &mut Vec<String>, tree: &UseTree) {
+        match tree {
+            UseTree::Path(path) => {
+                prefix.push(path.ident.to_string());
+                self.push_use_tree(prefix, &path.tree);
+                prefix.pop();
+            }
+            UseTree::Name(name) if name.ident == "self" => {
+                self.push(prefix.clone(), UsageKind::Path, name.ident.span())
+            }
+            UseTree::Name(name) => {
+                let path = prefix.iter().cloned().chain([name.ident.to_string()]);
+                self.push(path.collect(), UsageKind::Path, name.ident.span());
+            }
+            UseTree::Rename(rename) => {
+                let path = match rename.ident.to_string().as_str() {
+                    "self" => prefix.clone(),
+                    name => prefix.iter().cloned().chain([name.to_string()]).collect(),
+                };
+                self.push(path, UsageKind::Path, rename.ident.span());
+            }
+            UseTree::Glob(glob) => self.push(prefix.clone(), UsageKind::Path, glob.star_token.span),
+            UseTree::Group(group) => {
+                for tree in &group.items {
+                    self.push_use_tree(prefix, tree);
+                }
+            }
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for UsageCollector<'_> {
+    fn visit_item_use(&mut self, item: &'ast syn::ItemUse) {
+        // The aliases are already known, paths of a `use` are resolved the same way.
+        self.push_use_tree(&mut Vec::new(), &item.tree);
+    }
+
+    fn visit_item_extern_crate(&mut self, item: &'ast syn::ItemExternCrate) {
+        self.usages.push(Usage {
+            path: vec![item.ident.to_string()],
+            kind: UsageKind::Crate,
+            implicit: false,
+            external: true,
+            location: item.ident.span().start(),
+        });
+    }
+
+    fn visit_path(&mut self, path: &'ast syn::Path) {
+        let segments = path
+            .segments
+            .iter()
+            .map(|segment| segment.ident.to_string())
+            .collect();
+        self.push(segments, UsageKind::Path, path.span());
+        visit::visit_path(self, path);
+    }
+
+    fn visit_macro(&mut self, mac: &'ast syn::Macro) {
+        let segments = mac
+            .path
+            .segments
+            .iter()
+            .map(|segment| segment.ident.to_string())
+            .collect();
+        self.push(segments, UsageKind::Macro, mac.path.span());
+        // The body of a macro is just tokens, but most of the macros take
+        // either a list of expressions or statements.
+        if let Ok(exprs) = mac.parse_body_with(Punctuated::<Expr, Token![,]>::parse_terminated) {
+            for expr in &exprs {
+                self.visit_expr(expr);
+            }
+        } else if let Ok(stmts) = mac.parse_body_with(Block::parse_within) {
+            for stmt in &stmts {
+                self.visit_stmt(stmt);
+            }
+        }
+    }
+}
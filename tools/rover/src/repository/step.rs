@@ -1,18 +1,21 @@
-use super::command::Command;
+use super::{command::Command, limits::Limits};
 
 #[derive(Debug)]
 pub struct Step {
-    #[allow(unused)]
     name: String,
     commands: Vec<Command>,
+    limits: Limits,
 }
 
 impl Step {
-    pub fn new(name: String, commands: Vec<Command>) -> Self {
-        Self { name, commands }
+    pub fn new(name: String, commands: Vec<Command>, limits: Limits) -> Self {
+        Self {
+            name,
+            commands,
+            limits,
+        }
     }
 
-    #[allow(unused)]
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -20,4 +23,9 @@ impl Step {
     pub fn commands(&self) -> &[Command] {
         self.commands.as_slice()
     }
+
+    /// Timeout of the whole step, memory and CPU limits of each command.
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
 }
@@ -1,216 +1,374 @@
-use super::{command::Command, context::CommandContext};
+use super::{
+    capture::OutputTail,
+    command::{Command, ExpectedStatus, ProcessCommand},
+    context::CommandContext,
+    forbid,
+    limits::{self, Limits},
+};
 use anyhow::{bail, Context, Result};
+use serde::{
+    de::{self, value::MapAccessDeserializer, MapAccess, Visitor},
+    Deserialize, Deserializer,
+};
 use std::{
-    fs::File,
-    io::{BufRead, BufReader},
-    process,
+    env, fmt,
+    path::Path,
+    process::{self, Stdio},
+    sync::Mutex,
+    time::Instant,
 };
 
-const FORBID_UNSAFE_LINE: &str = "#![forbid(unsafe_code)]";
-const FORBID_STD_LINE: &str = "#![no_std]";
-const FORBID_COLLECTIONS_PATTERNS: [&str; 8] = [
-    "BTreeMap",
-    "BTreeSet",
-    "HashMap",
-    "HashSet",
-    "Vec",
-    "VecDeque",
-    "LinkedList",
-    "BinaryHeap",
-];
-
-macro_rules! launch {
-    ($toolchain: expr, $command: expr, $context: expr) => {{
-        let toolchain_shell_line = $toolchain.get_shell_line()?;
-        let command_shell_line = $command.get_shell_line()?;
-        let toolchain_shell_iter = toolchain_shell_line.split(' ');
-        let command_shell_iter = command_shell_line.split(' ');
-        let mut iter = toolchain_shell_iter.chain(command_shell_iter);
-        let mut cmd = if let Some(program) = iter.next() {
-            let mut cmd = process::Command::new(program);
-            cmd.current_dir($context.get_workdir());
-            cmd
-        } else {
-            bail!("toolchain and command are empty")
-        };
-        while let Some(arg) = iter.next() {
-            cmd.arg(arg);
-        }
-        if cmd.status().context("command failed")?.success() {
-            Ok(())
-        } else {
-            bail!("command failed")
-        }
-    }};
-}
+/// Missing toolchains and components are installed from this rustup dist server if set,
+/// a local directory works as well.
+const MIRROR_ENV: &str = "ROVER_RUSTUP_MIRROR";
 
-#[derive(Clone, Copy, Debug)]
+/// Toolchains with components that are known to be installed.
+static CHECKED: Mutex<Vec<Toolchain>> = Mutex::new(Vec::new());
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Toolchain {
+    /// Commands are launched as is.
     Empty,
-    Stable,
-    Nightly,
+    /// Commands are launched with `rustup run NAME`.
+    Rustup {
+        name: String,
+        components: Vec<String>,
+    },
+}
+
+/// The toolchain with components, as written in the config.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ToolchainYml {
+    channel: String,
+    #[serde(default)]
+    components: Vec<String>,
+}
+
+/// Either a name or a mapping with a channel and components.
+impl<'de> Deserialize<'de> for Toolchain {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ToolchainVisitor;
+
+        impl<'de> Visitor<'de> for ToolchainVisitor {
+            type Value = Toolchain;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(
+                    f,
+                    "a toolchain name or a mapping with a channel and components"
+                )
+            }
+
+            fn visit_str<E: de::Error>(self, name: &str) -> Result<Self::Value, E> {
+                Toolchain::from_name(name).map_err(E::custom)
+            }
+
+            fn visit_f64<E: de::Error>(self, version: f64) -> Result<Self::Value, E> {
+                Err(E::custom(format!(
+                    "toolchain version {version} must be quoted, otherwise it's a number"
+                )))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let yml = ToolchainYml::deserialize(MapAccessDeserializer::new(map))?;
+                match Toolchain::from_name(&yml.channel).map_err(de::Error::custom)? {
+                    Toolchain::Empty if !yml.components.is_empty() => {
+                        Err(de::Error::custom("empty toolchain has no components"))
+                    }
+                    Toolchain::Empty => Ok(Toolchain::Empty),
+                    Toolchain::Rustup { name, .. } => Ok(Toolchain::Rustup {
+                        name,
+                        components: yml.components,
+                    }),
+                }
+            }
+        }
+
+        deserializer.deserialize_any(ToolchainVisitor)
+    }
 }
 
 impl Toolchain {
+    /// `empty`, a channel (`stable`, `beta`, `nightly`), a version (`1.75`, `1.75.0`)
+    /// or a dated channel (`nightly-2024-01-01`), any of them optionally with a host
+    /// triple (`1.75.0-aarch64-apple-darwin`).
     pub fn from_name(name: &str) -> Result<Self> {
-        Ok(match name {
-            "empty" => Self::Empty,
-            "stable" => Self::Stable,
-            "nightly" => Self::Nightly,
-            name => bail!("toolchain \"{name}\" is not supported"),
+        if name == "empty" {
+            return Ok(Self::Empty);
+        }
+        if !is_channel(name) {
+            bail!("toolchain \"{name}\" is not supported");
+        }
+        Ok(Self::Rustup {
+            name: name.to_string(),
+            components: Vec::new(),
         })
     }
 
-    pub fn get_shell_line(&self) -> Result<String> {
-        Ok(match self {
-            Self::Empty => "".to_string(),
-            Self::Stable => "rustup run stable".to_string(),
-            Self::Nightly => "rustup run nightly".to_string(),
-        })
+    /// A command launching `program` with the toolchain.
+    pub fn command(&self, program: &str) -> process::Command {
+        match self {
+            Self::Empty => process::Command::new(program),
+            Self::Rustup { name, .. } => {
+                let mut cmd = process::Command::new("rustup");
+                cmd.args(["run", name, program]);
+                cmd
+            }
+        }
     }
 
-    pub fn run_command(&self, command: &Command, context: &CommandContext) -> Result<()> {
-        match command {
-            Command::ForbidUnsafe => {
-                for file in context.get_user_files() {
-                    if let Some(line) = BufReader::new(File::open(file)?)
-                        .lines()
-                        .next()
-                        .transpose()?
-                    {
-                        if line != FORBID_UNSAFE_LINE {
-                            bail!(format!(
-                                "file {file:?} does not contain line '{FORBID_UNSAFE_LINE}'"
-                            ))
-                        }
-                    } else {
-                        // TODO: ForbidUnsafe shouldn't check whether file is empty
-                        bail!(format!("file {file:?} is empty"))
-                    }
-                }
-                Ok(())
-            }
-            Command::ForbidCollections => {
-                for file in context.get_user_files() {
-                    for line in BufReader::new(File::open(file)?).lines() {
-                        let line = line.context("error reading file line")?;
-                        for pattern in FORBID_COLLECTIONS_PATTERNS {
-                            if line.contains(pattern) {
-                                bail!(format!("file {file:?} contains line '{pattern}'"))
-                            }
-                        }
-                    }
-                }
-                Ok(())
-            }
-            Command::ForbidStd => {
-                for file in context.get_user_files() {
-                    if let Some(line) = BufReader::new(File::open(file)?)
-                        .lines()
-                        .next()
-                        .transpose()?
-                    {
-                        if line != FORBID_STD_LINE {
-                            bail!(format!(
-                                "file {file:?} does not contain line '{FORBID_STD_LINE}'"
-                            ))
-                        }
-                    } else {
-                        // TODO: ForbidStd shouldn't check whether file is empty
-                        bail!(format!("file {file:?} is empty"))
-                    }
+    /// Make sure the toolchain and its components are installed. The missing ones are
+    /// installed from the mirror if it's set, otherwise it's an error.
+    pub fn preflight(&self) -> Result<()> {
+        let (name, components) = match self {
+            Self::Empty => return Ok(()),
+            Self::Rustup { name, components } => (name, components),
+        };
+        // Held during installation, so that problems tested at once don't install the same.
+        let mut checked = CHECKED.lock().unwrap();
+        if checked.contains(self) {
+            return Ok(());
+        }
+        let mirror = env::var(MIRROR_ENV)
+            .ok()
+            .filter(|mirror| !mirror.is_empty());
+        let host = match host(name)? {
+            Some(host) => host,
+            None => {
+                let Some(mirror) = &mirror else {
+                    bail!(
+                        "toolchain {name} is not installed, install it with \"rustup toolchain install {name}\" \
+                         or set {MIRROR_ENV} to install it automatically"
+                    );
+                };
+                let mut args = vec!["toolchain", "install", name, "--profile", "minimal"];
+                for component in components {
+                    args.extend(["--component", component]);
                 }
-                Ok(())
+                rustup(&args, Some(mirror))?;
+                checked.push(self.clone());
+                return Ok(());
             }
-            Command::CargoCompileTestMiniFrunk => {
-                // TODO: hardcoded, better to refactor all the code
-                if process::Command::new("cargo")
-                    .current_dir(context.get_workdir())
-                    .arg("test")
-                    .arg("--features")
-                    .arg("compilation-fail-generic")
-                    .status()
-                    .context("command failed")?
-                    .success()
-                {
-                    bail!("command failed")
-                }
-                if process::Command::new("cargo")
-                    .current_dir(context.get_workdir())
-                    .arg("test")
-                    .arg("--features")
-                    .arg("compilation-fail-labelled")
-                    .status()
-                    .context("command failed")?
-                    .success()
-                {
-                    bail!("command failed")
-                }
-                if process::Command::new("cargo")
-                    .current_dir(context.get_workdir())
-                    .arg("test")
-                    .arg("--features")
-                    .arg("compilation-fail-transmogrify")
-                    .status()
-                    .context("command failed")?
-                    .success()
-                {
-                    bail!("command failed")
-                }
-                println!("Compile tests passed, don't worry :)");
-                Ok(())
+        };
+        let installed = rustup(
+            &["component", "list", "--installed", "--toolchain", name],
+            None,
+        )?;
+        let missing: Vec<_> = components
+            .iter()
+            .filter(|component| {
+                let full_name = format!("{component}-{host}");
+                !installed
+                    .lines()
+                    .any(|line| line == component.as_str() || line == full_name)
+            })
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            let Some(mirror) = &mirror else {
+                bail!(
+                    "components {} of toolchain {name} are not installed, install them with \
+                     \"rustup component add --toolchain {name} {}\" or set {MIRROR_ENV} to install them automatically",
+                    missing.join(", "),
+                    missing.join(" ")
+                );
+            };
+            let mut args = vec!["component", "add", "--toolchain", name];
+            args.extend(missing);
+            rustup(&args, Some(mirror))?;
+        }
+        checked.push(self.clone());
+        Ok(())
+    }
+
+    pub fn launch(
+        &self,
+        command: &ProcessCommand,
+        context: &CommandContext,
+        limits: Limits,
+        output: &mut OutputTail,
+    ) -> Result<()> {
+        let deadline = limits.timeout.map(|timeout| Instant::now() + timeout);
+        let expected_status = command.expected_status();
+        for args in command.invocations() {
+            let mut cmd = self.command(command.program());
+            if let Some(target_dir) = context.get_target_dir() {
+                cmd.env("CARGO_TARGET_DIR", target_dir);
             }
-            Command::CargoCompileTestOrm => {
-                // TODO: hardcoded, better to refactor all the code
-                if process::Command::new("cargo")
-                    .current_dir(context.get_workdir())
-                    .arg("test")
-                    .arg("--features")
-                    .arg("test-lifetimes-create")
-                    .status()
-                    .context("command failed")?
-                    .success()
-                {
-                    bail!("command failed")
-                }
-                if process::Command::new("cargo")
-                    .current_dir(context.get_workdir())
-                    .arg("test")
-                    .arg("--features")
-                    .arg("test-lifetimes-get")
-                    .status()
-                    .context("command failed")?
-                    .success()
-                {
-                    bail!("command failed")
-                }
-                println!("Compile tests passed, don't worry :)");
-                Ok(())
+            cmd.current_dir(context.get_workdir())
+                .args(args)
+                .envs(command.env().iter().cloned())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped());
+            limits::apply(&mut cmd, limits);
+            let timeout =
+                deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            let mut child = cmd
+                .spawn()
+                .with_context(|| format!("failed to launch command {}", command.name()))?;
+            let status = output.wait_child(&mut child, timeout)?;
+            if !expected_status.matches(status.code()) {
+                bail!(
+                    "command {} exited with {status}, expected {expected_status:?}",
+                    command.name()
+                )
             }
-            Command::CargoCompileTestSnapshot => {
-                // TODO: hardcoded, better to refactor all the code
-                if process::Command::new("cargo")
-                    .current_dir(context.get_workdir())
-                    .arg("test")
-                    .arg("--features")
-                    .arg("test-lifetime")
-                    .status()
-                    .context("command failed")?
-                    .success()
-                {
-                    bail!("command failed")
-                }
-                println!("Compile tests passed, don't worry :)");
-                Ok(())
+        }
+        if expected_status == ExpectedStatus::Failure {
+            output.println(format!(
+                "Command {} failed as expected, don't worry :)",
+                command.name()
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn run_command(
+        &self,
+        command: &Command,
+        context: &CommandContext,
+        limits: Limits,
+        output: &mut OutputTail,
+    ) -> Result<()> {
+        match command {
+            Command::ForbidUnsafe => forbid::forbid_unsafe(context),
+            Command::ForbidCollections => forbid::forbid_collections(context),
+            Command::ForbidStd => forbid::forbid_std(context),
+            Command::Forbid(policy) => policy.check(context),
+            Command::Process(process) => self.launch(process, context, limits, output),
+            Command::HiddenTest(test) => {
+                let _injected = test.inject(context.get_workdir())?;
+                self.launch(test.process(), context, limits, output)
             }
-            Command::CargoFmt
-            | Command::CargoClippy
-            | Command::CargoTest
-            | Command::CargoTestDebug
-            | Command::PythonTest
-            | Command::CargoMiriTest => {
-                launch!(self, command, context)
+            Command::Bench(policy) => {
+                output.keep_full_stdout();
+                let process = ProcessCommand::preset(command.name(), "cargo bench");
+                self.launch(&process, context, limits, output)?;
+                policy.check(context, output)
             }
+            Command::Coverage(policy) => policy.run(self, context, limits, output),
+        }
+    }
+}
+
+/// A channel, a version or a dated channel, optionally followed by a host triple,
+/// e.g. `nightly-2024-01-01-x86_64-unknown-linux-gnu`.
+fn is_channel(name: &str) -> bool {
+    let is_number =
+        |part: &str, len: usize| part.len() == len && part.bytes().all(|b| b.is_ascii_digit());
+    let is_date = |parts: &[&str]| {
+        parts.len() >= 3
+            && is_number(parts[0], 4)
+            && is_number(parts[1], 2)
+            && is_number(parts[2], 2)
+    };
+    let is_version = |version: &str| {
+        let parts: Vec<_> = version.split('.').collect();
+        (2..=3).contains(&parts.len())
+            && parts
+                .iter()
+                .all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()))
+    };
+    // Architecture, vendor, system and optionally ABI, the architecture starts with a letter.
+    let is_triple = |parts: &[&str]| {
+        (3..=4).contains(&parts.len())
+            && parts[0].starts_with(|c: char| c.is_ascii_lowercase())
+            && parts.iter().all(|part| {
+                !part.is_empty()
+                    && part.bytes().all(|b| {
+                        b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_' || b == b'.'
+                    })
+            })
+    };
+    let parts: Vec<_> = name.split('-').collect();
+    let rest = match parts[0] {
+        "stable" | "beta" | "nightly" if is_date(&parts[1..]) => &parts[4..],
+        "stable" | "beta" | "nightly" => &parts[1..],
+        version if is_version(version) => &parts[1..],
+        _ => return false,
+    };
+    rest.is_empty() || is_triple(rest)
+}
+
+/// The host triple of the toolchain, `None` if it's not installed.
+fn host(name: &str) -> Result<Option<String>> {
+    let output = process::Command::new("rustup")
+        .args(["run", name, "rustc", "-vV"])
+        // Newer rustup installs missing toolchains by itself otherwise.
+        .env("RUSTUP_AUTO_INSTALL", "0")
+        .output()
+        .context("failed to launch rustup")?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .map(str::to_string))
+}
+
+/// Launch rustup with the dist server set to the mirror if any, returning its stdout.
+fn rustup(args: &[&str], mirror: Option<&str>) -> Result<String> {
+    let mut cmd = process::Command::new("rustup");
+    cmd.args(args).stderr(Stdio::inherit());
+    if let Some(mirror) = mirror {
+        let server = if mirror.contains("://") {
+            mirror.to_string()
+        } else {
+            let path = Path::new(mirror)
+                .canonicalize()
+                .with_context(|| format!("{MIRROR_ENV} is neither a URL nor a directory"))?;
+            format!("file://{}", path.display())
+        };
+        cmd.env("RUSTUP_DIST_SERVER", server);
+    }
+    let output = cmd.output().context("failed to launch rustup")?;
+    if !output.status.success() {
+        bail!("rustup {} failed with {}", args.join(" "), output.status);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channels() {
+        for name in [
+            "stable",
+            "beta",
+            "nightly",
+            "1.75",
+            "1.75.0",
+            "nightly-2024-01-01",
+            "beta-2024-01-01",
+            "stable-x86_64-unknown-linux-gnu",
+            "nightly-2024-01-01-x86_64-unknown-linux-gnu",
+            "1.75.0-aarch64-apple-darwin",
+            "1.75-x86_64-pc-windows-msvc",
+            "nightly-i686-unknown-linux-musl",
+        ] {
+            assert!(is_channel(name), "{name}");
+        }
+        for name in [
+            "",
+            "my-toolchain",
+            "nightly-2024-1-01",
+            "nightly-2024-01",
+            "1",
+            "1.75.0.1",
+            "1.x",
+            "stable-",
+            "stable-linux",
+            "1.75.0-x86_64-unknown",
+            "nightly-2024-01-01-x86_64",
+            "nightly-2024-01-01-2024-01-01",
+            "stable-x86_64-unknown-linux-gnu-extra",
+            "stable-X86_64-unknown-linux-gnu",
+        ] {
+            assert!(!is_channel(name), "{name}");
         }
     }
 }
@@ -0,0 +1,279 @@
+use crate::repository::repo::{Repository, COMPOSE_CONFIG, PROBLEMS_FOLDER};
+use anyhow::{bail, Context, Result};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use toml_edit::{Array, DocumentMut, Value};
+
+/// Files of a template: the path relative to the problem and the content with placeholders.
+type Template = &'static [(&'static str, &'static str)];
+
+macro_rules! template {
+    ($name: literal, $($file: literal),+ $(,)?) => {
+        &[$(($file, include_str!(concat!("../../templates/", $name, "/", $file)))),+]
+    };
+}
+
+const LIB: Template = template!(
+    "lib",
+    "Cargo.toml",
+    ".config.yml",
+    "README.md",
+    "src/lib.rs",
+    "tests/tests.rs",
+);
+
+const BIN: Template = template!(
+    "bin",
+    "Cargo.toml",
+    ".config.yml",
+    "README.md",
+    "src/main.rs",
+    "tests/tests.rs",
+);
+
+const PROC_MACRO: Template = template!(
+    "proc-macro",
+    "Cargo.toml",
+    ".config.yml",
+    "README.md",
+    "src/lib.rs",
+    "tests/tests.rs",
+);
+
+pub const TEMPLATES: [&str; 3] = ["lib", "bin", "proc-macro"];
+
+/// Create `problems/GROUP/TITLE` from the template in the course repository containing
+/// `path`, and add it to the workspace and to the compose config if there's one.
+pub fn new_problem(path: &Path, problem: &str, template: &str) -> Result<()> {
+    let repository = Repository::from_path(path)?;
+    let (group, title) = split_problem(problem)?;
+    let files = match template {
+        "lib" => LIB,
+        "bin" => BIN,
+        "proc-macro" => PROC_MACRO,
+        template => bail!("template \"{template}\" is not supported"),
+    };
+    let relative = PathBuf::from(PROBLEMS_FOLDER).join(group).join(title);
+    let dir = repository.get_path().join(&relative);
+    if dir.exists() {
+        bail!("problem {problem} already exists");
+    }
+
+    let replacements = [
+        ("{{name}}", title.to_string()),
+        ("{{crate}}", title.replace('-', "_")),
+    ];
+    for (file, content) in files {
+        let content = replacements
+            .iter()
+            .fold(content.to_string(), |content, (from, to)| {
+                content.replace(from, to)
+            });
+        let path = dir.join(file);
+        fs::create_dir_all(path.parent().unwrap())
+            .with_context(|| format!("failed to create directory for {path:?}"))?;
+        fs::write(&path, content).with_context(|| format!("failed to write {path:?}"))?;
+    }
+    println!("Created {}", relative.display());
+
+    let member = relative.to_str().context("non-utf-8 path")?;
+    add_workspace_member(&repository.get_path().join("Cargo.toml"), member)?;
+    println!("Added {member} to workspace members");
+    let compose_config = repository.get_path().join(COMPOSE_CONFIG);
+    if compose_config.exists() {
+        add_compose_problem(&compose_config, problem)?;
+        println!("Added {problem} to problems of {COMPOSE_CONFIG}");
+    }
+    Ok(())
+}
+
+/// Insert the member after the last one of the problems to keep them grouped.
+fn add_workspace_member(manifest: &Path, member: &str) -> Result<()> {
+    let mut document = if manifest.exists() {
+        fs::read_to_string(manifest)
+            .with_context(|| format!("failed to read {manifest:?}"))?
+            .parse::<DocumentMut>()
+            .with_context(|| format!("failed to parse {manifest:?}"))?
+    } else {
+        DocumentMut::new()
+    };
+    let members = document
+        .entry("workspace")
+        .or_insert_with(toml_edit::table)
+        .as_table_like_mut()
+        .context("workspace is not a table")?
+        .entry("members")
+        .or_insert_with(|| toml_edit::value(Array::new()))
+        .as_array_mut()
+        .context("workspace members are not an array")?;
+    if members.iter().any(|value| value.as_str() == Some(member)) {
+        return Ok(());
+    }
+    let position = members
+        .iter()
+        .enumerate()
+        .filter(|(_, value)| {
+            value
+                .as_str()
+                .is_some_and(|value| value.starts_with(PROBLEMS_FOLDER))
+        })
+        .last()
+        .map_or(members.len(), |(position, _)| position + 1);
+    let mut value = Value::from(member);
+    value.decor_mut().set_prefix("\n    ");
+    members.insert_formatted(position, value);
+    members.set_trailing_comma(true);
+    if members.trailing().as_str().is_some_and(str::is_empty) {
+        members.set_trailing("\n");
+    }
+    fs::write(manifest, document.to_string())
+        .with_context(|| format!("failed to write {manifest:?}"))
+}
+
+/// Append the problem to the `problems` list, editing the text to keep the comments.
+fn add_compose_problem(config: &Path, problem: &str) -> Result<()> {
+    let content =
+        fs::read_to_string(config).with_context(|| format!("failed to read {config:?}"))?;
+    let lines: Vec<_> = content.lines().collect();
+    let start = lines
+        .iter()
+        .position(|line| line.starts_with("problems:"))
+        .with_context(|| format!("{COMPOSE_CONFIG} has no problems list"))?;
+    let mut lines = lines;
+    match lines[start]["problems:".len()..].trim() {
+        "" => {}
+        "[]" => {
+            let item = format!("  - {problem}");
+            lines[start] = "problems:";
+            lines.insert(start + 1, &item);
+            return write_lines(config, &lines);
+        }
+        _ => bail!("problems of {COMPOSE_CONFIG} must be a block list"),
+    }
+    // The list ends at the first line that is neither an item nor a comment nor empty.
+    let items: Vec<_> = lines[start + 1..]
+        .iter()
+        .take_while(|line| {
+            let line = line.trim_start();
+            line.is_empty() || line.starts_with('#') || line.starts_with("- ")
+        })
+        .enumerate()
+        .filter(|(_, line)| line.trim_start().starts_with("- "))
+        .collect();
+    if items
+        .iter()
+        .any(|(_, line)| line.trim_start()[2..].trim() == problem)
+    {
+        return Ok(());
+    }
+    let (position, indent) = match items.last() {
+        Some((index, line)) => (
+            start + 1 + index + 1,
+            &line[..line.len() - line.trim_start().len()],
+        ),
+        None => (start + 1, "  "),
+    };
+    let item = format!("{indent}- {problem}");
+    lines.insert(position, &item);
+    write_lines(config, &lines)
+}
+
+/// Split `GROUP/TITLE` into its parts. The title is also the crate name, so both parts must
+/// start with a letter.
+fn split_problem(problem: &str) -> Result<(&str, &str)> {
+    let (group, title) = problem
+        .split_once('/')
+        .context("problem must be given as GROUP/TITLE")?;
+    for part in [group, title] {
+        if !part.starts_with(|c: char| c.is_ascii_lowercase())
+            || !part
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+        {
+            bail!("\"{part}\" must start with a lowercase letter and consist of lowercase letters, digits, '-' and '_'");
+        }
+    }
+    Ok((group, title))
+}
+
+fn write_lines(path: &Path, lines: &[&str]) -> Result<()> {
+    fs::write(path, lines.join("\n") + "\n").with_context(|| format!("failed to write {path:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(content: &str, add: fn(&Path, &str) -> Result<()>, value: &str) -> String {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        fs::write(&path, content).unwrap();
+        add(&path, value).unwrap();
+        fs::read_to_string(&path).unwrap()
+    }
+
+    #[test]
+    fn problem_names() {
+        assert_eq!(split_problem("intro/add").unwrap(), ("intro", "add"));
+        assert_eq!(
+            split_problem("macros-2/mini_frunk2").unwrap(),
+            ("macros-2", "mini_frunk2")
+        );
+        for problem in [
+            "add",
+            "intro/",
+            "/add",
+            "intro/Add",
+            "intro/a+b",
+            "intro/1add",
+            "2intro/add",
+        ] {
+            assert!(split_problem(problem).is_err(), "{problem}");
+        }
+    }
+
+    #[test]
+    fn workspace_members() {
+        let manifest = "[workspace]\nmembers = [\n    \"problems/a/b\",\n    \"tools/rover\",\n]\n";
+        assert_eq!(
+            edit(manifest, add_workspace_member, "problems/a/c"),
+            "[workspace]\nmembers = [\n    \"problems/a/b\",\n    \"problems/a/c\",\n    \"tools/rover\",\n]\n"
+        );
+        assert_eq!(
+            edit(manifest, add_workspace_member, "problems/a/b"),
+            manifest
+        );
+        assert_eq!(
+            edit(
+                "[workspace]\nmembers = []\n",
+                add_workspace_member,
+                "problems/a/b"
+            ),
+            "[workspace]\nmembers = [\n    \"problems/a/b\",\n]\n"
+        );
+    }
+
+    #[test]
+    fn compose_problems() {
+        let config = "problems:\n  - a/b\n  # - a/old\n\n  - a/c\nother: 1\n";
+        assert_eq!(
+            edit(config, add_compose_problem, "a/d"),
+            "problems:\n  - a/b\n  # - a/old\n\n  - a/c\n  - a/d\nother: 1\n"
+        );
+        assert_eq!(edit(config, add_compose_problem, "a/c"), config);
+        assert_eq!(
+            edit("problems:\n# nothing yet\n", add_compose_problem, "a/b"),
+            "problems:\n  - a/b\n# nothing yet\n"
+        );
+        assert_eq!(
+            edit("problems: []\nother: 1\n", add_compose_problem, "a/b"),
+            "problems:\n  - a/b\nother: 1\n"
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        fs::write(&path, "problems: [a/b]\n").unwrap();
+        assert!(add_compose_problem(&path, "a/c").is_err());
+    }
+}
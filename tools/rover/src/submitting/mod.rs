@@ -1 +1,2 @@
+pub(crate) mod status;
 pub(crate) mod submit;
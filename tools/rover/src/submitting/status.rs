@@ -0,0 +1,243 @@
+use crate::{
+    repository::{problem::Problem, repo::Repository},
+    util::git::git_output,
+};
+use anyhow::{Context, Result};
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+const REMOTE: &str = "origin";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ProblemStatus {
+    /// User files are the same as in the course repository and were never submitted.
+    Untouched,
+    /// User files differ from the last commit of the problem branch or from the
+    /// course repository if there's no branch yet.
+    Modified,
+    /// User files are committed to the problem branch, but the branch is not pushed.
+    Committed,
+    /// User files are committed and the remote branch is up to date.
+    Pushed,
+}
+
+impl ProblemStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Untouched => "untouched",
+            Self::Modified => "modified",
+            Self::Committed => "committed",
+            Self::Pushed => "pushed",
+        }
+    }
+}
+
+/// Print the status of every problem of the course repository containing `path`.
+/// Only local repositories are inspected, remote branches are the ones from the last fetch.
+pub fn print_status(path: &Path, solutions_repo: Option<PathBuf>) -> Result<()> {
+    let repository = Repository::from_path(path)?;
+    let solutions_repo = match solutions_repo {
+        Some(path) => Some(path),
+        None => repository.solutions_repo().ok(),
+    };
+    if solutions_repo.is_none() {
+        println!("There's no solutions repository, nothing is submitted yet");
+    }
+    let problems = repository.problems()?;
+    let width = problems
+        .iter()
+        .map(|problem| problem.branch_name().len())
+        .max()
+        .unwrap_or_default();
+    let mut counts = HashMap::new();
+    for problem in &problems {
+        let name = problem.branch_name();
+        match problem_status(repository.get_path(), solutions_repo.as_deref(), problem) {
+            Ok((status, changed)) => {
+                *counts.entry(status).or_insert(0) += 1;
+                if status == ProblemStatus::Modified {
+                    println!(
+                        "{name:<width$}  {:<9}  {changed} file(s) changed",
+                        status.as_str()
+                    );
+                } else {
+                    println!("{name:<width$}  {}", status.as_str());
+                }
+            }
+            Err(err) => println!("{name:<width$}  error: {err:#}"),
+        }
+    }
+    let count = |status| counts.get(&status).copied().unwrap_or(0);
+    println!();
+    println!(
+        "{} problems: {} untouched, {} modified, {} committed, {} pushed",
+        problems.len(),
+        count(ProblemStatus::Untouched),
+        count(ProblemStatus::Modified),
+        count(ProblemStatus::Committed),
+        count(ProblemStatus::Pushed)
+    );
+    Ok(())
+}
+
+/// The status of the problem along with the number of user files that differ from
+/// the last submission, or from the course repository if there's none.
+fn problem_status(
+    course_repo: &Path,
+    solutions_repo: Option<&Path>,
+    problem: &Problem,
+) -> Result<(ProblemStatus, usize)> {
+    let files = problem.repo_user_files()?;
+    let working = worktree_blobs(course_repo, &files)?;
+    let changed = |reference: &HashMap<PathBuf, String>| {
+        files
+            .iter()
+            .filter(|file| working.get(*file) != reference.get(*file))
+            .count()
+    };
+
+    let branch = format!("refs/heads/{}", problem.branch_name());
+    let branch_commit = match solutions_repo {
+        Some(repo) => rev_parse(repo, &branch)?,
+        None => None,
+    };
+    let (solutions_repo, branch_commit) = match (solutions_repo, branch_commit) {
+        (Some(repo), Some(commit)) => (repo, commit),
+        _ => {
+            // A course repository that is not a git one is considered to be modified.
+            let original = tree_blobs(course_repo, "HEAD", &files)?.unwrap_or_default();
+            return Ok(match changed(&original) {
+                0 => (ProblemStatus::Untouched, 0),
+                changed => (ProblemStatus::Modified, changed),
+            });
+        }
+    };
+    let submitted = tree_blobs(solutions_repo, &branch_commit, &files)?.unwrap_or_default();
+    let changed = changed(&submitted);
+    if changed > 0 {
+        return Ok((ProblemStatus::Modified, changed));
+    }
+    let remote = format!("refs/remotes/{REMOTE}/{}", problem.branch_name());
+    if rev_parse(solutions_repo, &remote)?.as_deref() == Some(&branch_commit) {
+        Ok((ProblemStatus::Pushed, 0))
+    } else {
+        Ok((ProblemStatus::Committed, 0))
+    }
+}
+
+/// Hashes of the files as git would store them, missing files are skipped.
+fn worktree_blobs(repo: &Path, files: &[PathBuf]) -> Result<HashMap<PathBuf, String>> {
+    let existing: Vec<_> = files
+        .iter()
+        .filter(|file| repo.join(file).is_file())
+        .collect();
+    if existing.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let mut args: Vec<&OsStr> = vec!["hash-object".as_ref(), "--".as_ref()];
+    args.extend(existing.iter().map(|file| file.as_os_str()));
+    let output = git_output(repo, &args)?.context("git hash-object failed")?;
+    Ok(existing
+        .into_iter()
+        .cloned()
+        .zip(output.lines().map(str::to_string))
+        .collect())
+}
+
+/// Hashes of the files in the tree of the revision, `None` if there's no such revision.
+fn tree_blobs(
+    repo: &Path,
+    revision: &str,
+    files: &[PathBuf],
+) -> Result<Option<HashMap<PathBuf, String>>> {
+    let mut args: Vec<&OsStr> = vec![
+        "ls-tree".as_ref(),
+        "-r".as_ref(),
+        revision.as_ref(),
+        "--".as_ref(),
+    ];
+    args.extend(files.iter().map(|file| file.as_os_str()));
+    let output = match git_output(repo, &args)? {
+        Some(output) => output,
+        None => return Ok(None),
+    };
+    // Each line is "<mode> <type> <hash>\t<path>".
+    let blobs = output
+        .lines()
+        .filter_map(|line| {
+            let (info, path) = line.split_once('\t')?;
+            let hash = info.split(' ').nth(2)?;
+            Some((PathBuf::from(path), hash.to_string()))
+        })
+        .collect();
+    Ok(Some(blobs))
+}
+
+fn rev_parse(repo: &Path, revision: &str) -> Result<Option<String>> {
+    let args = ["rev-parse", "--verify", "--quiet", revision];
+    Ok(git_output(repo, &args)?.map(|output| output.trim().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::git::git;
+    use std::fs;
+
+    #[test]
+    fn statuses() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let course = dir.path().join("mipt-rust");
+        let solutions = dir.path().join("solutions");
+        let problem_dir = course.join("problems/intro/add");
+        fs::create_dir_all(problem_dir.join("src"))?;
+        fs::write(
+            problem_dir.join(".config.yml"),
+            "toolchain: stable\nallowed-patterns: [src/lib.rs]\nsteps:\n  testing: [cargo-test]\n",
+        )?;
+        fs::write(problem_dir.join("src/lib.rs"), "// TODO\n")?;
+        git(&course, &["init", "-q"]);
+        git(&course, &["add", "."]);
+        git(&course, &["commit", "-q", "-m", "course"]);
+        fs::create_dir(&solutions)?;
+        git(&solutions, &["init", "-q"]);
+        git(&solutions, &["commit", "-q", "--allow-empty", "-m", "init"]);
+
+        let repository = Repository::from_path(&course)?;
+        let problem = repository.problem_from_path(&problem_dir)?;
+        let status = || problem_status(repository.get_path(), Some(&solutions), &problem).unwrap();
+        assert_eq!(status(), (ProblemStatus::Untouched, 0));
+        assert_eq!(
+            problem_status(repository.get_path(), None, &problem)?,
+            (ProblemStatus::Untouched, 0)
+        );
+
+        fs::write(problem_dir.join("src/lib.rs"), "pub fn add() {}\n")?;
+        assert_eq!(status(), (ProblemStatus::Modified, 1));
+
+        // Submitted as is, but not pushed.
+        let solution = solutions.join("problems/intro/add/src");
+        fs::create_dir_all(&solution)?;
+        fs::write(solution.join("lib.rs"), "pub fn add() {}\n")?;
+        git(&solutions, &["checkout", "-q", "-b", "intro/add"]);
+        git(&solutions, &["add", "."]);
+        git(&solutions, &["commit", "-q", "-m", "solution"]);
+        assert_eq!(status(), (ProblemStatus::Committed, 0));
+
+        git(
+            &solutions,
+            &["update-ref", "refs/remotes/origin/intro/add", "HEAD"],
+        );
+        assert_eq!(status(), (ProblemStatus::Pushed, 0));
+
+        fs::write(
+            problem_dir.join("src/lib.rs"),
+            "pub fn add() -> i32 { 0 }\n",
+        )?;
+        assert_eq!(status(), (ProblemStatus::Modified, 1));
+        Ok(())
+    }
+}
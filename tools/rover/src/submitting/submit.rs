@@ -1,6 +1,12 @@
-use crate::{launch_git, repository::repo::Repository};
-use anyhow::{bail, Result};
+use crate::{
+    launch_git,
+    repository::{problem::Problem, repo::Repository},
+    util::git::git_output,
+};
+use anyhow::{bail, Context, Result};
+use similar::TextDiff;
 use std::{
+    fs,
     path::{Path, PathBuf},
     process,
 };
@@ -9,6 +15,7 @@ pub fn submit_problem(
     problem_path: &Path,
     message: &str,
     solutions_repo: Option<PathBuf>,
+    dry_run: bool,
 ) -> Result<()> {
     let repository = Repository::from_path(problem_path)?;
     let problem = repository.problem_from_path(problem_path)?;
@@ -16,23 +23,141 @@ pub fn submit_problem(
         Some(path) => path,
         None => repository.solutions_repo()?,
     };
+    if dry_run {
+        return print_diff(&problem, repository.get_path(), &solutions_repo);
+    }
+    // Checked before touching the repository, so that a refusal leaves it as it was.
+    let files = problem.repo_user_files()?;
+    let staged = git_output(&solutions_repo, &["diff", "--cached", "--name-only"])?
+        .context("failed to list staged files")?;
+    let outside: Vec<_> = staged
+        .lines()
+        .filter(|file| !files.iter().any(|allowed| allowed == Path::new(file)))
+        .collect();
+    if !outside.is_empty() {
+        bail!(
+            "refusing to commit files outside of allowed patterns, unstage them with \"git restore --staged\":\n{}",
+            outside.join("\n")
+        )
+    }
     problem.move_solution_files_to(&solutions_repo)?;
-    if !launch_git!(&solutions_repo, "add", ".") {
+    let added = process::Command::new("git")
+        .current_dir(&solutions_repo)
+        .args(["add", "--"])
+        .args(&files)
+        .status()
+        .context("failed to launch git")?;
+    if !added.success() {
         bail!("git add failed");
     }
     if !launch_git!(&solutions_repo, "commit", "-m", message) {
         bail!("git commit failed: either no changes since the last commit or git failed")
     }
-    if !launch_git!(&solutions_repo, "push")
-        && !launch_git!(
-            &solutions_repo,
-            "push",
-            "--set-upstream",
-            "origin",
-            problem.branch_name()
-        )
-    {
+    // A new branch tracks the default one of the remote, so it's pushed by name.
+    if !launch_git!(
+        &solutions_repo,
+        "push",
+        "--set-upstream",
+        "origin",
+        problem.branch_name()
+    ) {
         bail!("git push failed")
     }
     Ok(())
 }
+
+/// Print the unified diff of allowed files between the problem and the last commit of
+/// its branch in the solutions repository, without changing anything.
+fn print_diff(problem: &Problem, course_repo: &Path, solutions_repo: &Path) -> Result<()> {
+    let branch = format!("refs/heads/{}", problem.branch_name());
+    let mut changed = 0;
+    for file in problem.repo_user_files()? {
+        let new = fs::read_to_string(course_repo.join(&file))
+            .with_context(|| format!("failed to read {file:?}"))?;
+        // A missing branch or file is the same as an empty file.
+        let old = git_output(
+            solutions_repo,
+            &["show", &format!("{branch}:{}", file.display())],
+        )?
+        .unwrap_or_default();
+        if old == new {
+            continue;
+        }
+        changed += 1;
+        let diff = TextDiff::from_lines(&old, &new);
+        print!(
+            "{}",
+            diff.unified_diff().context_radius(3).header(
+                &format!("a/{}", file.display()),
+                &format!("b/{}", file.display())
+            )
+        );
+    }
+    if changed == 0 {
+        println!(
+            "Nothing to submit, branch {} is up to date",
+            problem.branch_name()
+        );
+    } else {
+        println!(
+            "{changed} file(s) would be submitted to branch {}",
+            problem.branch_name()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::git::git;
+
+    #[test]
+    fn only_allowed_files_are_committed() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let course = dir.path().join("mipt-rust");
+        let solutions = dir.path().join("solutions");
+        let remote = dir.path().join("remote.git");
+        let problem_dir = course.join("problems/intro/add");
+        fs::create_dir_all(problem_dir.join("src"))?;
+        fs::write(
+            problem_dir.join(".config.yml"),
+            "toolchain: stable\nallowed-patterns: [src/lib.rs]\nsteps:\n  testing: [cargo-test]\n",
+        )?;
+        fs::write(problem_dir.join("src/lib.rs"), "pub fn add() {}\n")?;
+        fs::write(problem_dir.join("Cargo.toml"), "[package]\n")?;
+        fs::create_dir(&remote)?;
+        git(&remote, &["init", "-q", "--bare"]);
+        fs::create_dir(&solutions)?;
+        git(&solutions, &["init", "-q"]);
+        // Submitting commits with the identity of the user.
+        git(&solutions, &["config", "user.name", "student"]);
+        git(&solutions, &["config", "user.email", "student@localhost"]);
+        git(&solutions, &["commit", "-q", "--allow-empty", "-m", "init"]);
+        git(
+            &solutions,
+            &["remote", "add", "origin", remote.to_str().unwrap()],
+        );
+        git(&solutions, &["push", "-q", "origin", "main"]);
+
+        fs::write(solutions.join("notes.txt"), "not a solution\n")?;
+        git(&solutions, &["add", "notes.txt"]);
+        let err = submit_problem(&problem_dir, "add", Some(solutions.clone()), false).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("refusing to commit files outside of allowed patterns"));
+        assert!(err.to_string().ends_with("\nnotes.txt"));
+        // Nothing is touched on refusal.
+        assert_eq!(git(&solutions, &["branch", "--show-current"]), "main\n");
+        assert_eq!(
+            git(&solutions, &["status", "--porcelain"]),
+            "A  notes.txt\n"
+        );
+
+        git(&solutions, &["restore", "--staged", "notes.txt"]);
+        submit_problem(&problem_dir, "add", Some(solutions.clone()), false)?;
+        let committed = git(&remote, &["ls-tree", "-r", "--name-only", "intro/add"]);
+        assert_eq!(committed, "problems/intro/add/src/lib.rs\n");
+        Ok(())
+    }
+}
@@ -0,0 +1,289 @@
+use super::{
+    results::{Status, TestReport},
+    test::{test_problem, TestOptions},
+};
+use crate::repository::{
+    limits::{self, Cancelled},
+    problem::Problem,
+    repo::Repository,
+};
+use anyhow::{bail, Result};
+use glob::Pattern;
+use std::{
+    env,
+    fmt::Write as _,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+#[derive(Debug)]
+pub struct BatchOptions {
+    pub jobs: usize,
+    /// Test only problems of these groups, all groups if empty.
+    pub groups: Vec<String>,
+    /// Test only problems whose `GROUP/TITLE` matches the pattern.
+    pub filter: Option<Pattern>,
+    pub test: TestOptions,
+}
+
+/// Test every problem of the repository with at most `jobs` problems tested at once.
+/// The output of commands is not forwarded, only a line per finished problem is printed.
+/// With several jobs, each one builds in its own cargo target directory, so that they
+/// don't wait for each other on the lock of the shared one.
+pub fn test_all(repository: &Repository, options: &BatchOptions) -> Result<Vec<TestReport>> {
+    let problems = select_problems(repository, options)?;
+    if problems.is_empty() {
+        bail!("no problems match the filters")
+    }
+    let total = problems.len();
+    let jobs = options.jobs.clamp(1, total);
+    let queue = Mutex::new(problems.into_iter().enumerate());
+    let reports = Mutex::new(Vec::with_capacity(total));
+    let finished = AtomicUsize::new(0);
+    thread::scope(|scope| {
+        for job in 0..jobs {
+            let options = TestOptions {
+                target_dir: (jobs > 1).then(|| job_target_dir(repository, job)),
+                ..options.test.clone()
+            };
+            let (queue, reports, finished) = (&queue, &reports, &finished);
+            scope.spawn(move || loop {
+                if limits::interrupted() {
+                    break;
+                }
+                let next = queue.lock().unwrap().next();
+                let Some((index, problem)) = next else {
+                    break;
+                };
+                let name = problem.branch_name();
+                let report = test_problem(&problem, &options)
+                    .unwrap_or_else(|err| TestReport::error(&name, &err));
+                let finished = finished.fetch_add(1, Ordering::SeqCst) + 1;
+                println!(
+                    "[{finished}/{total}] {name}: {} in {:.1}s",
+                    report.status.as_str(),
+                    report.duration.as_secs_f64()
+                );
+                reports.lock().unwrap().push((index, report));
+            });
+        }
+    });
+    if limits::interrupted() {
+        return Err(Cancelled.into());
+    }
+    let mut reports = reports.into_inner().unwrap();
+    reports.sort_by_key(|(index, _)| *index);
+    Ok(reports.into_iter().map(|(_, report)| report).collect())
+}
+
+/// The problems of the groups and matching the filter of the options, sorted by path.
+fn select_problems(repository: &Repository, options: &BatchOptions) -> Result<Vec<Problem>> {
+    Ok(repository
+        .problems()?
+        .into_iter()
+        .filter(|problem| options.groups.is_empty() || options.groups.contains(&problem.group()))
+        .filter(|problem| match &options.filter {
+            Some(pattern) => pattern.matches(&problem.branch_name()),
+            None => true,
+        })
+        .collect())
+}
+
+/// `rover/job-N` in the target directory of the workspace, kept between runs.
+fn job_target_dir(repository: &Repository, job: usize) -> PathBuf {
+    env::var_os("CARGO_TARGET_DIR")
+        .map_or_else(|| repository.get_path().join("target"), PathBuf::from)
+        .join("rover")
+        .join(format!("job-{job}"))
+}
+
+/// Print a table with a row per problem and a column per step.
+pub fn print_matrix(reports: &[TestReport]) {
+    print!("{}", format_matrix(reports));
+}
+
+/// The table printed by `print_matrix`.
+fn format_matrix(reports: &[TestReport]) -> String {
+    let mut steps: Vec<&str> = Vec::new();
+    for report in reports {
+        for step in &report.steps {
+            if !steps.contains(&step.name.as_str()) {
+                steps.push(&step.name);
+            }
+        }
+    }
+    let width = reports
+        .iter()
+        .map(|report| report.problem.len())
+        .chain(std::iter::once("problem".len()))
+        .max()
+        .unwrap_or_default();
+    let widths: Vec<_> = steps.iter().map(|step| step.len().max(4)).collect();
+
+    // Writing into a String never fails.
+    let mut matrix = String::new();
+    writeln!(matrix).unwrap();
+    write!(matrix, "{:<width$}", "problem").unwrap();
+    for (step, step_width) in steps.iter().zip(&widths) {
+        write!(matrix, "  {step:<step_width$}").unwrap();
+    }
+    writeln!(matrix, "  result").unwrap();
+    for report in reports {
+        write!(matrix, "{:<width$}", report.problem).unwrap();
+        for (step, step_width) in steps.iter().zip(&widths) {
+            let cell = match report.steps.iter().find(|s| s.name == *step) {
+                Some(step) => matrix_cell(step.status),
+                None => "-",
+            };
+            write!(matrix, "  {cell:<step_width$}").unwrap();
+        }
+        writeln!(matrix, "  {}", matrix_cell(report.status)).unwrap();
+    }
+    let failed = reports.iter().filter(|report| report.failed()).count();
+    writeln!(matrix).unwrap();
+    writeln!(
+        matrix,
+        "{} problems: {} passed, {failed} failed",
+        reports.len(),
+        reports.len() - failed
+    )
+    .unwrap();
+    matrix
+}
+
+fn matrix_cell(status: Status) -> &'static str {
+    match status {
+        Status::Passed => "ok",
+        Status::Failed => "FAIL",
+        Status::TimedOut => "TIME",
+        Status::Skipped => "skip",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        repository::repo::create_problem,
+        testing::results::{CommandResult, StepResult},
+    };
+    use std::{path::Path, time::Duration};
+
+    fn config(program: &str) -> String {
+        format!("toolchain: empty\nallowed-patterns: []\nsteps:\n  testing:\n    - {program}\n")
+    }
+
+    fn options(groups: &[&str], filter: Option<&str>) -> BatchOptions {
+        BatchOptions {
+            jobs: 2,
+            groups: groups.iter().map(|group| group.to_string()).collect(),
+            filter: filter.map(|filter| Pattern::new(filter).unwrap()),
+            test: TestOptions::default(),
+        }
+    }
+
+    fn course(dir: &Path) -> Repository {
+        let ok = config("{program: sh, args: [-c, 'echo $CARGO_TARGET_DIR']}");
+        create_problem(dir, "intro/add", &ok);
+        create_problem(dir, "intro/sub", &config("{program: \"false\"}"));
+        create_problem(dir, "traits/shape", "steps: 1\n");
+        Repository::from_path(&dir.join("mipt-rust")).unwrap()
+    }
+
+    #[test]
+    fn selection() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = course(dir.path());
+        let selected = |options: BatchOptions| -> Vec<String> {
+            select_problems(&repository, &options)
+                .unwrap()
+                .iter()
+                .map(Problem::branch_name)
+                .collect()
+        };
+        assert_eq!(
+            selected(options(&[], None)),
+            ["intro/add", "intro/sub", "traits/shape"]
+        );
+        assert_eq!(
+            selected(options(&["intro"], None)),
+            ["intro/add", "intro/sub"]
+        );
+        assert_eq!(
+            selected(options(&["intro", "traits"], None)),
+            ["intro/add", "intro/sub", "traits/shape"]
+        );
+        assert_eq!(
+            selected(options(&[], Some("*/s*"))),
+            ["intro/sub", "traits/shape"]
+        );
+        assert_eq!(selected(options(&["intro"], Some("*/s*"))), ["intro/sub"]);
+        assert!(test_all(&repository, &options(&["closures"], None)).is_err());
+    }
+
+    #[test]
+    fn reports_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = course(dir.path());
+        let reports = test_all(&repository, &options(&[], None)).unwrap();
+        let statuses: Vec<_> = reports
+            .iter()
+            .map(|report| (report.problem.as_str(), report.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                ("intro/add", Status::Passed),
+                ("intro/sub", Status::Failed),
+                ("traits/shape", Status::Failed)
+            ]
+        );
+        // A problem that can't be loaded is reported in place of its steps.
+        assert_eq!(reports[2].steps[0].name, "config");
+        // Every job builds in a target directory of its own.
+        let target_dir = &reports[0].steps[0].commands[0].stdout;
+        assert!(
+            target_dir.contains(&format!("rover{}job-", std::path::MAIN_SEPARATOR)),
+            "{target_dir}"
+        );
+    }
+
+    #[test]
+    fn matrix() {
+        let command = |status| CommandResult {
+            status,
+            duration: Duration::from_secs(1),
+            ..CommandResult::skipped("cargo-test")
+        };
+        let report = |problem, statuses: &[(&str, Status)]| {
+            let steps = statuses
+                .iter()
+                .map(|(name, status)| StepResult::new(name, vec![command(*status)]))
+                .collect();
+            TestReport::new(problem, steps)
+        };
+        let reports = [
+            report(
+                "intro/add",
+                &[("linters", Status::Passed), ("testing", Status::Passed)],
+            ),
+            report(
+                "intro/long-name",
+                &[("testing", Status::TimedOut), ("bench", Status::Skipped)],
+            ),
+        ];
+        assert_eq!(
+            format_matrix(&reports),
+            "\n\
+             problem          linters  testing  bench  result\n\
+             intro/add        ok       ok       -      ok\n\
+             intro/long-name  -        TIME     skip   TIME\n\
+             \n\
+             2 problems: 1 passed, 1 failed\n"
+        );
+    }
+}
@@ -1,2 +1,5 @@
+pub mod batch;
 pub mod report;
+pub mod results;
 pub mod test;
+pub mod watch;
@@ -1,69 +1,325 @@
+use super::results::TestReport;
 use anyhow::{bail, Context, Result};
-use reqwest::blocking::{multipart::Form, Client};
-use std::{env, thread, time};
+use reqwest::{
+    blocking::{multipart::Form, Client, RequestBuilder},
+    StatusCode,
+};
+use serde::Serialize;
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    thread, time,
+};
 
 const MANYTASK_URL: &str = "https://mipt-rust.manytask.org/api/report";
-const MANYTASK_RETRIES: usize = 3;
+const REPORT_RETRIES: usize = 3;
 
-pub enum ReportType {
-    NoReport,
-    Manytask,
+/// A system that accepts the results of testing.
+pub trait Reporter {
+    fn push_report(&self, report: &TestReport) -> Result<()>;
 }
 
-impl ReportType {
-    pub fn from_name(name: &str) -> Result<Self> {
-        Ok(match name {
-            "no-report" => Self::NoReport,
-            "manytask" => Self::Manytask,
-            name => bail!("report type \"{name}\" is not supported"),
+/// Options of reporters from the command line, each reporter uses its own subset.
+#[derive(Debug, Default)]
+pub struct ReporterOptions {
+    pub url: Option<String>,
+    pub headers: Vec<String>,
+    pub template: Option<PathBuf>,
+    pub file: Option<PathBuf>,
+}
+
+pub fn reporter_from_name(name: &str, options: ReporterOptions) -> Result<Box<dyn Reporter>> {
+    Ok(match name {
+        "no-report" => Box::new(NoReport),
+        "manytask" => Box::new(Manytask),
+        "webhook" => Box::new(Webhook::new(options)?),
+        "jsonl" => Box::new(JsonLines {
+            path: options
+                .file
+                .context("jsonl reporter requires --report-jsonl-file")?,
+        }),
+        name => bail!("report type \"{name}\" is not supported"),
+    })
+}
+
+pub struct NoReport;
+
+impl Reporter for NoReport {
+    fn push_report(&self, _: &TestReport) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub struct Manytask;
+
+impl Reporter for Manytask {
+    fn push_report(&self, report: &TestReport) -> Result<()> {
+        if env::var("SKIP_REPORT").is_ok() {
+            return Ok(());
+        }
+        let task_name = env::var("CI_COMMIT_REF_NAME")
+            .context("no CI_COMMIT_REF_NAME variable")?
+            .split('/')
+            .nth(1)
+            .context("CI_COMMIT_REF_NAME does not contain '/' symbol")?
+            .to_owned();
+        let user_id = env::var("GITLAB_USER_ID").context("no GITLAB_USER_ID variable")?;
+        let tester_token = env::var("TESTER_TOKEN").context("no TESTER_TOKEN variable")?;
+        let client = Client::new();
+        send_with_retries("manytask", || {
+            let mut data = Form::new()
+                .text("user_id", user_id.clone())
+                .text("task", task_name.clone())
+                .text("token", tester_token.clone());
+            if report.failed() {
+                data = data.text("failed", "1");
+            }
+            client.post(MANYTASK_URL).multipart(data)
+        })?;
+        // Manytask may reject a report, e.g. after the deadline, which doesn't fail the job.
+        Ok(())
+    }
+}
+
+/// Posts a JSON body to an arbitrary URL. The body is built from a template
+/// where every `{{placeholder}}` is replaced with a JSON value:
+///
+/// - `{{problem}}`, `{{status}}`, `{{passed}}`, `{{failed}}`, `{{duration}}` - fields of the report.
+/// - `{{report}}` - the whole report, the same as in `--report-file`.
+/// - `{{env:NAME}}` - the string value of the environment variable `NAME`.
+///
+/// Without a template, the whole report is sent.
+pub struct Webhook {
+    url: String,
+    headers: Vec<(String, String)>,
+    template: String,
+}
+
+impl Webhook {
+    pub fn new(options: ReporterOptions) -> Result<Self> {
+        let url = options
+            .url
+            .context("webhook reporter requires --report-url")?;
+        let headers = options
+            .headers
+            .iter()
+            .map(|header| {
+                let (name, value) = header.split_once(':').with_context(|| {
+                    format!("header \"{header}\" is not in \"Name: value\" form")
+                })?;
+                Ok((name.trim().to_string(), value.trim().to_string()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let template = match options.template {
+            Some(path) => fs::read_to_string(&path)
+                .with_context(|| format!("failed to read webhook template {path:?}"))?,
+            None => "{{report}}".to_string(),
+        };
+        Ok(Self {
+            url,
+            headers,
+            template,
         })
     }
 
-    pub fn push_report(&self, failed: bool) -> Result<()> {
-        match self {
-            Self::NoReport => {
-                if failed {
-                    bail!("testing failed")
-                } else {
-                    Ok(())
-                }
+    fn render(&self, report: &TestReport) -> Result<String> {
+        let mut body = String::new();
+        let mut rest = self.template.as_str();
+        while let Some(begin) = rest.find("{{") {
+            body.push_str(&rest[..begin]);
+            let end = rest[begin..]
+                .find("}}")
+                .context("unclosed \"{{\" in webhook template")?
+                + begin;
+            let placeholder = rest[begin + 2..end].trim();
+            let value = match placeholder {
+                "problem" => serde_json::to_string(&report.problem)?,
+                "status" => serde_json::to_string(&report.status)?,
+                "passed" => (!report.failed()).to_string(),
+                "failed" => report.failed().to_string(),
+                "duration" => report.duration.as_secs_f64().to_string(),
+                "report" => serde_json::to_string(report)?,
+                placeholder => match placeholder.strip_prefix("env:") {
+                    Some(name) => serde_json::to_string(
+                        &env::var(name).with_context(|| format!("no {name} variable"))?,
+                    )?,
+                    None => bail!("unknown placeholder \"{placeholder}\" in webhook template"),
+                },
+            };
+            body.push_str(&value);
+            rest = &rest[end + 2..];
+        }
+        body.push_str(rest);
+        serde_json::from_str::<serde_json::Value>(&body)
+            .context("webhook template does not render to valid JSON")?;
+        Ok(body)
+    }
+}
+
+impl Reporter for Webhook {
+    fn push_report(&self, report: &TestReport) -> Result<()> {
+        let body = self.render(report)?;
+        let client = Client::new();
+        let status = send_with_retries("webhook", || {
+            let mut request = client
+                .post(&self.url)
+                .header("Content-Type", "application/json")
+                .body(body.clone());
+            for (name, value) in &self.headers {
+                request = request.header(name, value);
             }
-            Self::Manytask => {
-                if env::var("SKIP_REPORT").is_ok() {
-                    return Ok(());
+            request
+        })?;
+        if !status.is_success() {
+            bail!("webhook rejected the report with {status}")
+        }
+        Ok(())
+    }
+}
+
+/// Appends the report as a single JSON line to a local file.
+pub struct JsonLines {
+    path: PathBuf,
+}
+
+#[derive(Serialize)]
+struct JsonLinesEntry<'a> {
+    timestamp: u64,
+    #[serde(flatten)]
+    report: &'a TestReport,
+}
+
+impl Reporter for JsonLines {
+    fn push_report(&self, report: &TestReport) -> Result<()> {
+        let timestamp = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .context("system time is before the Unix epoch")?
+            .as_secs();
+        let mut line = serde_json::to_string(&JsonLinesEntry { timestamp, report })?;
+        line.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .with_context(|| format!("failed to append report to {:?}", self.path))
+    }
+}
+
+/// Send the request again while it gets server errors, returning the final status.
+fn send_with_retries(name: &str, request: impl Fn() -> RequestBuilder) -> Result<StatusCode> {
+    for _ in 0..REPORT_RETRIES {
+        let status = request()
+            .send()
+            .with_context(|| format!("post report to {name}"))?
+            .status();
+        if !status.is_server_error() {
+            return Ok(status);
+        }
+        thread::sleep(time::Duration::from_millis(1000));
+    }
+    bail!("{REPORT_RETRIES} posts to {name} gave server error")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::results::{CommandResult, Status, StepResult};
+    use std::{
+        io::{BufRead, BufReader, Read},
+        net::TcpListener,
+        time::Duration,
+    };
+
+    fn report() -> TestReport {
+        let command = CommandResult {
+            name: "cargo-test".to_string(),
+            status: Status::Failed,
+            duration: Duration::from_secs(2),
+            message: Some("command failed".to_string()),
+            stdout: String::new(),
+            stderr: String::new(),
+        };
+        TestReport::new(
+            "intro/conway",
+            vec![StepResult::new("testing", vec![command])],
+        )
+    }
+
+    /// Accept a single request, answer with `status` and return the request head and body.
+    fn stub_server(status: u16) -> (String, thread::JoinHandle<(String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/report", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut head = String::new();
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
                 }
-                let task_name = env::var("CI_COMMIT_REF_NAME")
-                    .context("no CI_COMMIT_REF_NAME variable")?
-                    .split('/')
-                    .nth(1)
-                    .context("CI_COMMIT_REF_NAME does not contain '/' symbol")?
-                    .to_owned();
-                let user_id = env::var("GITLAB_USER_ID").context("no GITLAB_USER_ID variable")?;
-                let tester_token = env::var("TESTER_TOKEN").context("no TESTER_TOKEN variable")?;
-                let client = Client::new();
-                for _ in 0..MANYTASK_RETRIES {
-                    let mut data = Form::new()
-                        .text("user_id", user_id.clone())
-                        .text("task", task_name.clone())
-                        .text("token", tester_token.clone());
-                    if failed {
-                        data = data.text("failed", "1");
-                    }
-                    if client
-                        .post(MANYTASK_URL)
-                        .multipart(data)
-                        .send()
-                        .context("post report to manytask")?
-                        .status()
-                        != 500
-                    {
-                        return Ok(());
-                    } else {
-                        thread::sleep(time::Duration::from_millis(1000));
-                    }
+                if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                    content_length = value.trim().parse().unwrap();
                 }
-                bail!("{MANYTASK_RETRIES} posts to manytask gave 500 code")
+                head.push_str(&line);
             }
-        }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 {status} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            )
+            .unwrap();
+            (head, String::from_utf8(body).unwrap())
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn webhook() {
+        let dir = tempfile::tempdir().unwrap();
+        let template = dir.path().join("template.json");
+        fs::write(
+            &template,
+            r#"{"task": {{problem}}, "failed": {{failed}}, "steps": {{report}}}"#,
+        )
+        .unwrap();
+        let (url, server) = stub_server(200);
+        let webhook = Webhook::new(ReporterOptions {
+            url: Some(url),
+            headers: vec!["X-Token: secret".to_string()],
+            template: Some(template),
+            file: None,
+        })
+        .unwrap();
+        webhook.push_report(&report()).unwrap();
+
+        let (head, body) = server.join().unwrap();
+        assert!(head.starts_with("POST /report "));
+        assert!(head.to_lowercase().contains("x-token: secret"));
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["task"], "intro/conway");
+        assert_eq!(body["failed"], true);
+        assert_eq!(
+            body["steps"]["steps"][0]["commands"][0]["name"],
+            "cargo-test"
+        );
+    }
+
+    #[test]
+    fn webhook_rejected() {
+        let (url, server) = stub_server(403);
+        let webhook = Webhook::new(ReporterOptions {
+            url: Some(url),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(webhook.push_report(&report()).is_err());
+        server.join().unwrap();
     }
 }
@@ -0,0 +1,398 @@
+use anyhow::{bail, Context, Result};
+use serde::{Serialize, Serializer};
+use std::{fmt::Write as _, fs, path::Path, time::Duration};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Status {
+    Passed,
+    Failed,
+    TimedOut,
+    Skipped,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CommandResult {
+    pub name: String,
+    pub status: Status,
+    #[serde(serialize_with = "serialize_secs")]
+    pub duration: Duration,
+    pub message: Option<String>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StepResult {
+    pub name: String,
+    pub status: Status,
+    #[serde(serialize_with = "serialize_secs")]
+    pub duration: Duration,
+    pub commands: Vec<CommandResult>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TestReport {
+    pub problem: String,
+    pub status: Status,
+    #[serde(serialize_with = "serialize_secs")]
+    pub duration: Duration,
+    pub steps: Vec<StepResult>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ReportFormat {
+    Json,
+    Junit,
+}
+
+impl Status {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Passed => "passed",
+            Self::Failed => "failed",
+            Self::TimedOut => "timed-out",
+            Self::Skipped => "skipped",
+        }
+    }
+}
+
+impl CommandResult {
+    pub fn skipped(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            status: Status::Skipped,
+            duration: Duration::ZERO,
+            message: None,
+            stdout: String::new(),
+            stderr: String::new(),
+        }
+    }
+}
+
+impl StepResult {
+    pub fn new(name: &str, commands: Vec<CommandResult>) -> Self {
+        Self {
+            name: name.to_string(),
+            status: Self::merge_statuses(commands.iter().map(|command| command.status)),
+            duration: commands.iter().map(|command| command.duration).sum(),
+            commands,
+        }
+    }
+
+    fn merge_statuses(statuses: impl Iterator<Item = Status>) -> Status {
+        let mut result = Status::Skipped;
+        for status in statuses {
+            match status {
+                Status::Failed | Status::TimedOut => return status,
+                Status::Passed => result = Status::Passed,
+                Status::Skipped => {}
+            }
+        }
+        result
+    }
+}
+
+impl TestReport {
+    pub fn new(problem: &str, steps: Vec<StepResult>) -> Self {
+        Self {
+            problem: problem.to_string(),
+            status: StepResult::merge_statuses(steps.iter().map(|step| step.status)),
+            duration: steps.iter().map(|step| step.duration).sum(),
+            steps,
+        }
+    }
+
+    /// The report of a problem that could not be tested at all, e.g. because of its config.
+    pub fn error(problem: &str, err: &anyhow::Error) -> Self {
+        let command = CommandResult {
+            message: Some(format!("{err:#}")),
+            status: Status::Failed,
+            ..CommandResult::skipped("load-config")
+        };
+        Self::new(problem, vec![StepResult::new("config", vec![command])])
+    }
+
+    pub fn failed(&self) -> bool {
+        matches!(self.status, Status::Failed | Status::TimedOut)
+    }
+
+    pub fn print_summary(&self) {
+        println!();
+        println!(
+            "Summary for {}: {} in {:.1}s",
+            self.problem,
+            self.status.as_str(),
+            self.duration.as_secs_f64()
+        );
+        for step in &self.steps {
+            println!(
+                "  {:<24} {:<8} {:>7.1}s",
+                step.name,
+                step.status.as_str(),
+                step.duration.as_secs_f64()
+            );
+            for command in &step.commands {
+                println!(
+                    "    {:<22} {:<8} {:>7.1}s",
+                    command.name,
+                    command.status.as_str(),
+                    command.duration.as_secs_f64()
+                );
+                for line in command.message.iter().flat_map(|message| message.lines()) {
+                    println!("      {line}");
+                }
+            }
+        }
+    }
+
+    pub fn write_to_file(&self, path: &Path, format: ReportFormat) -> Result<()> {
+        let content = match format {
+            ReportFormat::Json => {
+                serde_json::to_string_pretty(self).context("failed to serialize report")?
+            }
+            ReportFormat::Junit => to_junit(&self.problem, std::slice::from_ref(self)),
+        };
+        fs::write(path, content).with_context(|| format!("failed to write report to {path:?}"))
+    }
+
+    /// Write reports of several problems: a JSON array or a single JUnit document.
+    pub fn write_all_to_file(reports: &[Self], path: &Path, format: ReportFormat) -> Result<()> {
+        let content = match format {
+            ReportFormat::Json => {
+                serde_json::to_string_pretty(reports).context("failed to serialize reports")?
+            }
+            ReportFormat::Junit => to_junit("all", reports),
+        };
+        fs::write(path, content).with_context(|| format!("failed to write report to {path:?}"))
+    }
+}
+
+impl ReportFormat {
+    pub fn from_name(name: &str) -> Result<Self> {
+        Ok(match name {
+            "json" => Self::Json,
+            "junit" => Self::Junit,
+            name => bail!("report format \"{name}\" is not supported"),
+        })
+    }
+
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("xml") => Self::Junit,
+            _ => Self::Json,
+        }
+    }
+}
+
+fn to_junit(name: &str, reports: &[TestReport]) -> String {
+    let commands = || {
+        reports
+            .iter()
+            .flat_map(|report| report.steps.iter())
+            .flat_map(|step| step.commands.iter())
+    };
+    let count = |statuses: &[Status]| {
+        commands()
+            .filter(|command| statuses.contains(&command.status))
+            .count()
+    };
+    let duration: Duration = reports.iter().map(|report| report.duration).sum();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    // Writing into a String never fails.
+    writeln!(
+        xml,
+        "<testsuites name=\"{}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\">",
+        escape_xml(name),
+        commands().count(),
+        count(&[Status::Failed, Status::TimedOut]),
+        count(&[Status::Skipped]),
+        duration.as_secs_f64()
+    )
+    .unwrap();
+    for report in reports {
+        for step in &report.steps {
+            let count = |statuses: &[Status]| {
+                step.commands
+                    .iter()
+                    .filter(|command| statuses.contains(&command.status))
+                    .count()
+            };
+            writeln!(
+            xml,
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\">",
+            escape_xml(&step.name),
+            step.commands.len(),
+            count(&[Status::Failed, Status::TimedOut]),
+            count(&[Status::Skipped]),
+            step.duration.as_secs_f64()
+        )
+            .unwrap();
+            for command in &step.commands {
+                writeln!(
+                    xml,
+                    "    <testcase classname=\"{}.{}\" name=\"{}\" time=\"{:.3}\">",
+                    escape_xml(&report.problem),
+                    escape_xml(&step.name),
+                    escape_xml(&command.name),
+                    command.duration.as_secs_f64()
+                )
+                .unwrap();
+                match command.status {
+                    Status::Failed => writeln!(
+                        xml,
+                        "      <failure message=\"{}\"/>",
+                        escape_xml(command.message.as_deref().unwrap_or_default())
+                    )
+                    .unwrap(),
+                    Status::TimedOut => writeln!(
+                        xml,
+                        "      <failure type=\"timeout\" message=\"{}\"/>",
+                        escape_xml(command.message.as_deref().unwrap_or_default())
+                    )
+                    .unwrap(),
+                    Status::Skipped => xml.push_str("      <skipped/>\n"),
+                    Status::Passed => {}
+                }
+                if !command.stdout.is_empty() {
+                    writeln!(
+                        xml,
+                        "      <system-out>{}</system-out>",
+                        escape_xml(&command.stdout)
+                    )
+                    .unwrap();
+                }
+                if !command.stderr.is_empty() {
+                    writeln!(
+                        xml,
+                        "      <system-err>{}</system-err>",
+                        escape_xml(&command.stderr)
+                    )
+                    .unwrap();
+                }
+                xml.push_str("    </testcase>\n");
+            }
+            xml.push_str("  </testsuite>\n");
+        }
+    }
+    xml.push_str("</testsuites>\n");
+    xml
+}
+
+fn serialize_secs<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+fn escape_xml(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Control characters like ANSI escapes are not allowed in XML 1.0.
+            c if c < ' ' && !matches!(c, '\t' | '\n' | '\r') => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn report() -> TestReport {
+        let passed = CommandResult {
+            status: Status::Passed,
+            duration: Duration::from_millis(1500),
+            ..CommandResult::skipped("cargo-fmt")
+        };
+        let failed = CommandResult {
+            name: "cargo-test".to_string(),
+            status: Status::Failed,
+            duration: Duration::from_millis(250),
+            message: Some("exited with <code> 101 & \"panicked\"".to_string()),
+            stdout: "assert 'a' < 'b'\x1b[0m\n".to_string(),
+            stderr: String::new(),
+        };
+        let steps = vec![
+            StepResult::new("linters", vec![passed]),
+            StepResult::new(
+                "testing",
+                vec![failed, CommandResult::skipped("cargo-miri")],
+            ),
+        ];
+        TestReport::new("intro/add", steps)
+    }
+
+    #[test]
+    fn junit() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.xml");
+        report().write_to_file(&path, ReportFormat::Junit).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="intro/add" tests="3" failures="1" skipped="1" time="1.750">
+  <testsuite name="linters" tests="1" failures="0" skipped="0" time="1.500">
+    <testcase classname="intro/add.linters" name="cargo-fmt" time="1.500">
+    </testcase>
+  </testsuite>
+  <testsuite name="testing" tests="2" failures="1" skipped="1" time="0.250">
+    <testcase classname="intro/add.testing" name="cargo-test" time="0.250">
+      <failure message="exited with &lt;code&gt; 101 &amp; &quot;panicked&quot;"/>
+      <system-out>assert &apos;a&apos; &lt; &apos;b&apos;[0m
+</system-out>
+    </testcase>
+    <testcase classname="intro/add.testing" name="cargo-miri" time="0.000">
+      <skipped/>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#
+        );
+
+        TestReport::write_all_to_file(&[report(), report()], &path, ReportFormat::Junit).unwrap();
+        let xml = fs::read_to_string(&path).unwrap();
+        assert!(xml.contains(
+            r#"<testsuites name="all" tests="6" failures="2" skipped="2" time="3.500">"#
+        ));
+        assert_eq!(xml.matches("<testsuite ").count(), 4);
+    }
+
+    #[test]
+    fn json() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.json");
+        report().write_to_file(&path, ReportFormat::Json).unwrap();
+        let value: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(value["problem"], "intro/add");
+        assert_eq!(value["status"], "failed");
+        assert_eq!(value["duration"], 1.75);
+        let testing = &value["steps"][1];
+        assert_eq!(testing["name"], "testing");
+        assert_eq!(testing["status"], "failed");
+        assert_eq!(
+            testing["commands"][0],
+            json!({
+                "name": "cargo-test",
+                "status": "failed",
+                "duration": 0.25,
+                "message": "exited with <code> 101 & \"panicked\"",
+                "stdout": "assert 'a' < 'b'\u{1b}[0m\n",
+                "stderr": "",
+            })
+        );
+        assert_eq!(testing["commands"][1]["status"], "skipped");
+        assert_eq!(testing["commands"][1]["message"], Value::Null);
+
+        TestReport::write_all_to_file(&[report(), report()], &path, ReportFormat::Json).unwrap();
+        let value: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(value.as_array().unwrap().len(), 2);
+        assert_eq!(value[1]["steps"][0]["commands"][0]["status"], "passed");
+    }
+}
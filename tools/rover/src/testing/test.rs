@@ -1,6 +1,175 @@
-use crate::repository::problem::Problem;
+use super::results::{CommandResult, Status, StepResult, TestReport};
+use crate::repository::{
+    capture::OutputTail,
+    limits::{self, Cancelled, Limits, TimedOut},
+    problem::Problem,
+};
 use anyhow::Result;
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
 
-pub fn test_problem(problem: Problem) -> Result<()> {
-    problem.launch_all_steps()
+#[derive(Clone, Debug, Default)]
+pub struct TestOptions {
+    /// Launch the rest of commands after a failed one instead of skipping them.
+    pub keep_going: bool,
+    /// Forward the output of commands to ours, otherwise it's only captured.
+    pub echo: bool,
+    /// Once set, the running command is killed and testing fails with [`Cancelled`].
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// Directory with hidden tests of the problems in `GROUP/TITLE` subdirectories.
+    pub hidden_tests: Option<PathBuf>,
+    /// Target directory of cargo, the one of the workspace if unset.
+    pub target_dir: Option<PathBuf>,
+}
+
+/// Launch all the steps of the problem. Testing stops at the first failed
+/// command unless `keep_going` is set, the rest of commands are skipped.
+pub fn test_problem(problem: &Problem, options: &TestOptions) -> Result<TestReport> {
+    let cancelled = || {
+        limits::interrupted()
+            || options
+                .cancel
+                .as_ref()
+                .is_some_and(|cancel| cancel.load(Ordering::SeqCst))
+    };
+    let config = problem.config()?;
+    let toolchain = config.get_toolchain();
+    toolchain.preflight()?;
+    let context = config
+        .get_command_context()
+        .with_target_dir(options.target_dir.clone());
+    let mut failed = false;
+    let mut steps = Vec::new();
+    let hidden_step = match &options.hidden_tests {
+        Some(dir) => config.get_hidden_step(&dir.join(problem.branch_name()))?,
+        None => None,
+    };
+    for step in config.get_steps().iter().chain(&hidden_step) {
+        let step_deadline = step
+            .limits()
+            .timeout
+            .map(|timeout| Instant::now() + timeout);
+        let mut commands = Vec::new();
+        for command in step.commands() {
+            if failed && !options.keep_going {
+                commands.push(CommandResult::skipped(command.name()));
+                continue;
+            }
+            if cancelled() {
+                return Err(Cancelled.into());
+            }
+            let mut output = OutputTail::new(options.echo).with_cancel(options.cancel.clone());
+            let start = Instant::now();
+            // The timeout of the step limits the whole step, not each command.
+            let mut limits = command.limits().or(Limits {
+                timeout: None,
+                ..step.limits()
+            });
+            if let Some(deadline) = step_deadline {
+                let remaining = deadline.saturating_duration_since(start);
+                limits.timeout = Some(limits.timeout.map_or(remaining, |t| t.min(remaining)));
+            }
+            let result = toolchain.run_command(command, &context, limits, &mut output);
+            let duration = start.elapsed();
+            if let Err(err) = &result {
+                if err.downcast_ref::<Cancelled>().is_some() {
+                    return Err(Cancelled.into());
+                }
+                if options.echo {
+                    eprintln!("Command {} failed: {err:#}", command.name());
+                }
+                failed = true;
+            }
+            commands.push(CommandResult {
+                name: command.name().to_string(),
+                status: match &result {
+                    Ok(()) => Status::Passed,
+                    Err(err) if err.downcast_ref::<TimedOut>().is_some() => Status::TimedOut,
+                    Err(_) => Status::Failed,
+                },
+                duration,
+                message: result.err().map(|err| format!("{err:#}")),
+                stdout: output.stdout(),
+                stderr: output.stderr(),
+            });
+        }
+        steps.push(StepResult::new(step.name(), commands));
+    }
+    Ok(TestReport::new(&problem.branch_name(), steps))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::repo::create_problem;
+    use std::fs;
+
+    #[test]
+    fn hidden_tests() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = "toolchain: empty\nallowed-patterns: [src/lib.rs]\nsteps:\n  testing:\n    - {program: \"true\"}\nhidden-tests: [pass.rs, fail.rs]\n";
+        let problem = create_problem(dir.path(), "intro/add", config);
+        fs::write(
+            problem.path().join("Cargo.toml"),
+            "[package]\nname = \"add\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[workspace]\n",
+        )
+        .unwrap();
+        fs::create_dir(problem.path().join("src")).unwrap();
+        fs::write(
+            problem.path().join("src/lib.rs"),
+            "pub fn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n",
+        )
+        .unwrap();
+        let hidden = dir.path().join("hidden");
+        fs::create_dir_all(hidden.join("intro/add")).unwrap();
+        fs::write(
+            hidden.join("intro/add/pass.rs"),
+            "#[test]\nfn pass() {\n    assert_eq!(add::add(2, 2), 4);\n}\n",
+        )
+        .unwrap();
+        fs::write(
+            hidden.join("intro/add/fail.rs"),
+            "#[test]\nfn fail() {\n    assert_eq!(add::add(2, 2), 5);\n}\n",
+        )
+        .unwrap();
+
+        let options = TestOptions {
+            keep_going: true,
+            hidden_tests: Some(hidden),
+            ..TestOptions::default()
+        };
+        let report = test_problem(&problem, &options).unwrap();
+        let steps: Vec<_> = report
+            .steps
+            .iter()
+            .map(|step| (step.name.as_str(), step.status))
+            .collect();
+        assert_eq!(
+            steps,
+            [
+                ("testing", Status::Passed),
+                ("hidden-tests", Status::Failed)
+            ]
+        );
+        let commands: Vec<_> = report.steps[1]
+            .commands
+            .iter()
+            .map(|command| (command.name.as_str(), command.status))
+            .collect();
+        assert_eq!(
+            commands,
+            [
+                ("hidden test pass.rs", Status::Passed),
+                ("hidden test fail.rs", Status::Failed)
+            ]
+        );
+        // The tests are removed after passing and failing alike.
+        assert!(!problem.path().join("tests").exists());
+    }
 }
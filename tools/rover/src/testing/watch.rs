@@ -0,0 +1,322 @@
+use super::{
+    results::{Status, TestReport},
+    test::{test_problem, TestOptions},
+};
+use crate::repository::{
+    config::Config,
+    limits::{self, Cancelled},
+    problem::{Problem, DEFAULT_YML_NAME},
+};
+use anyhow::{Context, Result};
+use notify::{RecursiveMode, Watcher};
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+/// Changes closer to each other than this are handled at once.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Test the problem each time its allowed files or config change, until interrupted.
+/// A run in progress is cancelled as soon as the files change again.
+pub fn watch_problem(problem: &Problem, options: &TestOptions) -> Result<()> {
+    let mut changes = Changes::new(problem.path().join(DEFAULT_YML_NAME))?;
+    // The whole problem is watched, so that new files matching the allowed patterns,
+    // e.g. modules in new directories, are picked up.
+    let (sender, events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if let Ok(event) = event {
+            let _ = sender.send(event);
+        }
+    })
+    .context("failed to create file watcher")?;
+    watcher
+        .watch(problem.path(), RecursiveMode::Recursive)
+        .with_context(|| format!("failed to watch {:?}", problem.path()))?;
+    limits::handle_interrupts();
+
+    let mut run = 1;
+    loop {
+        print!("[run {run}] testing {}...", problem.branch_name());
+        io::stdout().flush()?;
+        let (result, changed) = run_until_change(problem, options, &events, &mut changes);
+        // Clear the status line.
+        print!("\r\x1b[2K");
+        if limits::interrupted() {
+            return Ok(());
+        }
+        match result {
+            Ok(report) => print_run(run, &report),
+            Err(err) if err.downcast_ref::<Cancelled>().is_some() => {
+                println!("[run {run}] cancelled, files changed");
+            }
+            Err(err) => println!("[run {run}] error: {err:#}"),
+        }
+        if !changed && !wait_for_change(&events, &mut changes) {
+            return Ok(());
+        }
+        run += 1;
+    }
+}
+
+/// Tells the events that trigger a new run: changes of the config or of the files
+/// matching its allowed patterns.
+struct Changes {
+    config_path: PathBuf,
+    config: Config,
+}
+
+impl Changes {
+    fn new(config_path: PathBuf) -> Result<Self> {
+        let config = Config::from_yml(&config_path)?;
+        Ok(Self {
+            config_path,
+            config,
+        })
+    }
+
+    /// Whether the event is relevant. A changed config is reloaded, so that its new
+    /// allowed patterns apply to the next events.
+    fn matches(&mut self, event: &notify::Event) -> bool {
+        if event.kind.is_access() {
+            return false;
+        }
+        if event.paths.contains(&self.config_path) {
+            // An invalid config is reported by the run, the old patterns are kept till it's fixed.
+            if let Ok(config) = Config::from_yml(&self.config_path) {
+                self.config = config;
+            }
+            return true;
+        }
+        let is_user_file = |path: &Path| self.config.is_user_file(path);
+        event.paths.iter().any(|path| {
+            is_user_file(path)
+                // Files created along with the directory may precede its watch.
+                || (event.kind.is_create() && has_user_files(path, &is_user_file))
+        })
+    }
+}
+
+/// Test the problem, cancelling the run as soon as the files change or rover is
+/// interrupted. Returns the result of the run and whether the files changed.
+fn run_until_change(
+    problem: &Problem,
+    options: &TestOptions,
+    events: &Receiver<notify::Event>,
+    changes: &mut Changes,
+) -> (Result<TestReport>, bool) {
+    let cancel = Arc::new(AtomicBool::new(false));
+    let options = TestOptions {
+        echo: false,
+        cancel: Some(cancel.clone()),
+        ..options.clone()
+    };
+    thread::scope(|scope| {
+        let handle = scope.spawn(|| test_problem(problem, &options));
+        let mut changed = false;
+        while !handle.is_finished() && !limits::interrupted() {
+            match events.recv_timeout(POLL_INTERVAL) {
+                Ok(event) if changes.matches(&event) => {
+                    debounce(events, changes);
+                    changed = true;
+                    break;
+                }
+                Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        cancel.store(true, Ordering::SeqCst);
+        let result = handle.join().expect("testing thread panicked");
+        (result, changed)
+    })
+}
+
+/// Block until a relevant change, false if interrupted.
+fn wait_for_change(events: &Receiver<notify::Event>, changes: &mut Changes) -> bool {
+    while !limits::interrupted() {
+        match events.recv_timeout(POLL_INTERVAL) {
+            Ok(event) if changes.matches(&event) => {
+                debounce(events, changes);
+                return true;
+            }
+            Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return false,
+        }
+    }
+    false
+}
+
+/// Whether the path is a directory with files matching the allowed patterns.
+fn has_user_files(path: &Path, is_user_file: &dyn Fn(&Path) -> bool) -> bool {
+    let Ok(entries) = fs::read_dir(path) else {
+        return false;
+    };
+    entries.flatten().any(|entry| {
+        let path = entry.path();
+        is_user_file(&path) || has_user_files(&path, is_user_file)
+    })
+}
+
+/// Skip the events until there's a pause in them, still reloading the config if it changes.
+fn debounce(events: &Receiver<notify::Event>, changes: &mut Changes) {
+    while let Ok(event) = events.recv_timeout(DEBOUNCE) {
+        changes.matches(&event);
+    }
+}
+
+/// Print a line with the status of each step, and the output of the failed command if any.
+fn print_run(run: usize, report: &TestReport) {
+    let steps: Vec<_> = report
+        .steps
+        .iter()
+        .map(|step| format!("{} {}", step.name, step.status.as_str()))
+        .collect();
+    println!(
+        "[run {run}] {} in {:.1}s: {}",
+        report.status.as_str(),
+        report.duration.as_secs_f64(),
+        steps.join(", ")
+    );
+    let failed = report
+        .steps
+        .iter()
+        .flat_map(|step| &step.commands)
+        .find(|command| matches!(command.status, Status::Failed | Status::TimedOut));
+    if let Some(command) = failed {
+        print!("{}{}", command.stdout, command.stderr);
+        if let Some(message) = &command.message {
+            println!("{}: {message}", command.name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::repo::create_problem;
+    use notify::event::{AccessKind, CreateKind, EventKind, ModifyKind};
+    use std::{sync::mpsc::Sender, time::Instant};
+
+    const CONFIG: &str = "toolchain: empty\nallowed-patterns: [src/*.rs]\nsteps:\n  testing:\n    - {program: sleep, args: [0]}\n";
+
+    fn event(kind: EventKind, path: PathBuf) -> notify::Event {
+        notify::Event::new(kind).add_path(path)
+    }
+
+    fn modify(path: PathBuf) -> notify::Event {
+        event(EventKind::Modify(ModifyKind::Any), path)
+    }
+
+    fn send_later(sender: Sender<notify::Event>, events: Vec<notify::Event>, interval: Duration) {
+        thread::spawn(move || {
+            for event in events {
+                thread::sleep(interval);
+                let _ = sender.send(event);
+            }
+        });
+    }
+
+    #[test]
+    fn allowed_patterns() {
+        let dir = tempfile::tempdir().unwrap();
+        let problem = create_problem(dir.path(), "intro/add", CONFIG);
+        let path = problem.path();
+        let config_path = path.join(DEFAULT_YML_NAME);
+        let mut changes = Changes::new(config_path.clone()).unwrap();
+
+        assert!(changes.matches(&modify(path.join("src/lib.rs"))));
+        assert!(!changes.matches(&modify(path.join("tests/tests.rs"))));
+        assert!(!changes.matches(&modify(path.join("Cargo.toml"))));
+        let read = EventKind::Access(AccessKind::Any);
+        assert!(!changes.matches(&event(read, path.join("src/lib.rs"))));
+
+        // A directory may be created with the files in it before it's watched.
+        fs::create_dir_all(path.join("src/utils")).unwrap();
+        fs::write(path.join("src/utils/mod.rs"), "").unwrap();
+        let create = EventKind::Create(CreateKind::Folder);
+        assert!(changes.matches(&event(create, path.join("src/utils"))));
+        assert!(!changes.matches(&modify(path.join("src/utils"))));
+
+        // The new patterns apply right after the config changes.
+        fs::write(&config_path, CONFIG.replace("src/*.rs", "tests/*.rs")).unwrap();
+        assert!(changes.matches(&modify(config_path.clone())));
+        assert!(changes.matches(&modify(path.join("tests/tests.rs"))));
+        assert!(!changes.matches(&modify(path.join("src/lib.rs"))));
+
+        // An invalid config still triggers a run, the old patterns are kept.
+        fs::write(&config_path, "steps: 1\n").unwrap();
+        assert!(changes.matches(&modify(config_path)));
+        assert!(changes.matches(&modify(path.join("tests/tests.rs"))));
+    }
+
+    #[test]
+    fn debounce_skips_bursts() {
+        let dir = tempfile::tempdir().unwrap();
+        let problem = create_problem(dir.path(), "intro/add", CONFIG);
+        let config_path = problem.path().join(DEFAULT_YML_NAME);
+        let mut changes = Changes::new(config_path.clone()).unwrap();
+        let (sender, events) = mpsc::channel();
+
+        // The config changed in the middle of the burst is reloaded as well.
+        fs::write(&config_path, CONFIG.replace("src/*.rs", "tests/*.rs")).unwrap();
+        let burst = vec![
+            modify(problem.path().join("src/lib.rs")),
+            modify(config_path),
+            modify(problem.path().join("src/main.rs")),
+        ];
+        send_later(sender.clone(), burst, DEBOUNCE / 3);
+        let start = Instant::now();
+        debounce(&events, &mut changes);
+        assert!(start.elapsed() >= DEBOUNCE * 2);
+        assert!(events.try_recv().is_err());
+        assert!(changes.matches(&modify(problem.path().join("tests/tests.rs"))));
+
+        // Events after a pause are not skipped.
+        send_later(
+            sender,
+            vec![modify(problem.path().join("src/lib.rs"))],
+            DEBOUNCE * 2,
+        );
+        debounce(&events, &mut changes);
+        assert!(events.recv_timeout(DEBOUNCE * 4).is_ok());
+    }
+
+    #[test]
+    fn change_cancels_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let problem = create_problem(dir.path(), "intro/add", &CONFIG.replace("[0]", "[10]"));
+        let mut changes = Changes::new(problem.path().join(DEFAULT_YML_NAME)).unwrap();
+        let (sender, events) = mpsc::channel();
+
+        let changed_files = vec![
+            modify(problem.path().join("Cargo.toml")),
+            modify(problem.path().join("src/lib.rs")),
+        ];
+        send_later(sender.clone(), changed_files, Duration::from_millis(200));
+        let start = Instant::now();
+        let (result, changed) =
+            run_until_change(&problem, &TestOptions::default(), &events, &mut changes);
+        assert!(changed);
+        assert!(result.unwrap_err().downcast_ref::<Cancelled>().is_some());
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        // The next run goes till the end if nothing changes.
+        let config_path = problem.path().join(DEFAULT_YML_NAME);
+        fs::write(&config_path, CONFIG).unwrap();
+        let mut changes = Changes::new(config_path).unwrap();
+        let (result, changed) =
+            run_until_change(&problem, &TestOptions::default(), &events, &mut changes);
+        assert!(!changed);
+        assert!(!result.unwrap().failed());
+        drop(sender);
+    }
+}
@@ -0,0 +1,48 @@
+use anyhow::{Context, Result};
+use std::{
+    ffi::OsStr,
+    path::Path,
+    process::{self, Stdio},
+};
+
+/// Stdout of the git command, `None` if it fails.
+pub fn git_output<S: AsRef<OsStr>>(repo: &Path, args: &[S]) -> Result<Option<String>> {
+    let output = process::Command::new("git")
+        .current_dir(repo)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .context("failed to launch git")?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(Some(
+        String::from_utf8(output.stdout).context("git output is not UTF-8")?,
+    ))
+}
+
+/// Run git in the repository with a fixed identity, panicking if it fails.
+#[cfg(test)]
+pub fn git(repo: &Path, args: &[&str]) -> String {
+    let output = process::Command::new("git")
+        .current_dir(repo)
+        .args(["-c", "user.name=rover", "-c", "user.email=rover@localhost"])
+        .args([
+            "-c",
+            "init.defaultBranch=main",
+            "-c",
+            "commit.gpgsign=false",
+        ])
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .expect("failed to launch git");
+    assert!(
+        output.status.success(),
+        "git {} failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
@@ -1 +1,2 @@
+pub(crate) mod git;
 pub(crate) mod util_macro;
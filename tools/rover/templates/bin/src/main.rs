@@ -0,0 +1,7 @@
+#![forbid(unsafe_code)]
+
+fn main() {
+    // compose::begin_private(unimplemented)
+    println!("42");
+    // compose::end_private
+}
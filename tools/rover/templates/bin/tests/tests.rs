@@ -0,0 +1,10 @@
+use std::process::Command;
+
+#[test]
+fn it_works() {
+    let output = Command::new(env!("CARGO_BIN_EXE_{{name}}"))
+        .output()
+        .expect("failed to launch the program");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "42\n");
+}
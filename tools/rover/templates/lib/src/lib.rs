@@ -0,0 +1,7 @@
+#![forbid(unsafe_code)]
+
+pub fn solve() -> i32 {
+    // compose::begin_private(unimplemented)
+    42
+    // compose::end_private
+}
@@ -0,0 +1,6 @@
+use {{crate}}::solve;
+
+#[test]
+fn it_works() {
+    assert_eq!(solve(), 42);
+}
@@ -0,0 +1,22 @@
+#![forbid(unsafe_code)]
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, DeriveInput};
+
+#[proc_macro_derive(Answer)]
+pub fn derive_answer(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    // compose::begin_private(unimplemented)
+    let name = input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    quote! {
+        impl #impl_generics #name #type_generics #where_clause {
+            pub fn answer(&self) -> i32 {
+                42
+            }
+        }
+    }
+    .into()
+    // compose::end_private
+}
@@ -0,0 +1,9 @@
+use {{crate}}::Answer;
+
+#[derive(Answer)]
+struct Question;
+
+#[test]
+fn it_works() {
+    assert_eq!(Question.answer(), 42);
+}